@@ -1,858 +1,698 @@
-use core::panic;
-use std::{collections::HashMap, fs::File};
-use crate::shared::{
-    compiler_defaults::SIZES, errors::CompilerError, meta::{ AnyMetadata, NumberType }, parser_nodes::{Expression, ExpressionStatement, FieldAccessExpression, FunctionDeclaration, LiteralExpression, Program, ReturnStatement, Statement, StructDeclaration, StructLiteralExpression, TypedExpression, UnaryExpression, VarDeclarationStatement, VariableReassignmentStatement}, tokens::{Token, TokenType}
-};
-
-pub struct Symbol {
-    pub offset: isize,
-    pub size: usize,
-    pub var_type: TypedExpression
+mod regalloc;
+
+use std::fs::File;
+use regalloc::{Allocation, Location};
+use crate::ir::{self, BinaryOp, Binding, Callee, Inst, InlineAsm, Terminator, Ty, VReg};
+use crate::shared::{errors::CompilerError, typed_nodes::TypedProgram};
+
+// where a call argument is passed
+enum ArgumentLocation {
+    Integer(&'static str),
+    Vector(usize),
+    Stack(usize)
 }
 
+#[allow(dead_code)]
 pub enum SupportedTargets {
     Linux,
     Mac
 }
 
 #[cfg(target_os = "macos")]
-pub fn getCurrentTarget() -> SupportedTargets {
+pub fn get_current_target() -> SupportedTargets {
     SupportedTargets::Mac
 }
 
 #[cfg(target_os = "linux")]
-pub fn getCurrentTarget() -> SupportedTargets {
+pub fn get_current_target() -> SupportedTargets {
     SupportedTargets::Linux
 }
 
+const ARGUMENT_REGISTERS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+const SYSCALL_REGISTERS: [&str; 7] = ["rax", "rdi", "rsi", "rdx", "r10", "r8", "r9"];
+
 #[allow(dead_code)]
 pub struct Compiler<'a> {
-    pub prog: Program<'a>,
+    pub prog: TypedProgram<'a>,
     pub file_handler: File,
+    // what ends up in the output file, NASM or the IR with `--emit=ir`
     pub asm: Vec<String>,
-    pub symbol_table: HashMap<&'a str, Symbol>,
-    pub current_stack_offset: isize,
-    pub data_section: Vec<String>,
-    pub text_section: Vec<String>,
-    pub data_counter: usize,
-    pub label_table: HashMap<String, Vec<String>>,
     pub current_target: SupportedTargets,
-    pub custom_types: HashMap<String, TypedExpression>,
-    pub struct_defs: HashMap<String, StructDef>
-}
-
-#[derive(Debug, Clone)]
-pub struct StructFieldDef {
-    pub name: String,
-    pub field_type: TypedExpression,
-    pub offset: usize,
-    pub size: usize,
-    pub align: usize
-}
-
-#[derive(Debug, Clone)]
-pub struct StructDef {
-    pub name: String,
-    pub fields: Vec<StructFieldDef>,
-    pub size: usize,
-    pub align: usize
+    // `--no-libc`, the program gets its own `_start` and is linked without libc
+    pub freestanding: bool,
+    // `--emit=ir`, write the IR the assembly would be selected from instead
+    pub emit_ir: bool,
+    // off with `--no-regalloc`, every value then lives in the frame
+    pub allocate_registers: bool
 }
 
-#[allow(dead_code)]
 impl<'a> Compiler<'a> {
-    pub fn new(ast: Program<'a>, out_file: &'a str) -> Result<Self, CompilerError> {
+    pub fn new(ast: TypedProgram<'a>, out_file: &'a str) -> Result<Self, CompilerError> {
         let file = match File::create(out_file) {
             Ok(handler) => handler,
             Err(_) => return Err(CompilerError::IllegalOutputFile)
         };
-        let asm = vec![];
-        let data_section = vec!["section .data\n".to_string()];
-        let text_section = vec!["section .text\n".to_string()];
-        let current_target = getCurrentTarget();
-
         Ok(Self {
             prog: ast,
             file_handler: file,
-            asm,
-            data_section,
-            text_section,
-            symbol_table: HashMap::new(),
-            current_stack_offset: 0,
-            data_counter: 0,
-            label_table: HashMap::new(),
-            current_target,
-            custom_types: HashMap::new(),
-            struct_defs: HashMap::new()
+            asm: vec![],
+            current_target: get_current_target(),
+            freestanding: false,
+            emit_ir: false,
+            allocate_registers: true
         })
     }
 
     pub fn compile(&mut self) -> Result<(), CompilerError> {
-        let progs = self.prog.stmts.clone();
-        for statement in progs {
-            if let Statement::FunctionDeclaration(fx) = &statement {
-                let compiled_fx = self.compile_function_declaration_statement(fx);
-                if let Ok((function_name, body)) = compiled_fx {
-                    let mut fx_name: String = "_".to_string();
-                    if let SupportedTargets::Mac = self.current_target {
-                        fx_name.push_str(&function_name);
-                    } else {
-                        fx_name = function_name;
-                    }
-                    self.text_section.push(format!("\tglobal {}\n", fx_name).to_string());
-                    self.label_table.insert(fx_name, body);
-                } else {
-                    println!("Err: {:?}", compiled_fx);
-                }
-            } else if let Statement::ExternStatement(ex) = &statement {
-                let mut fx_name: String = "_".to_string();
-                if let SupportedTargets::Mac = self.current_target {
-                    fx_name.push_str(ex.fx_name);
-                } else {
-                    fx_name = ex.fx_name.to_string()
-                };
-                self.text_section.push(format!("\textern {}\n", fx_name));
-            } else if let Statement::TypeDeclarationStatement(tds) = &statement {
-                if let AnyMetadata::Identifier { value } = tds.alias.meta_data {
-                    self.custom_types.insert(value.to_string(), tds.alias_for.clone());
-                }
-            } else if let Statement::StructDeclaration(sd) = &statement {
-                let def = self.build_struct_def(sd.name, sd.fields.clone());
-                self.struct_defs.insert(sd.name.to_string(), def);
+        let mut module = ir::lower::lower(&self.prog)?;
+        if let Err(errors) = ir::verify::verify(&module) {
+            panic!("the lowered IR is malformed:\n{}", errors.join("\n"));
+        }
+        if self.allocate_registers {
+            for function in &mut module.functions {
+                ir::promote::promote_locals(function);
+            }
+            if let Err(errors) = ir::verify::verify(&module) {
+                panic!("promoting locals left the IR malformed:\n{}", errors.join("\n"));
             }
         }
-        self.asm.extend_from_slice(&self.data_section);
-        self.asm.extend_from_slice(&self.text_section);
+        if self.emit_ir {
+            self.asm.push(module.to_string());
+            return Ok(());
+        }
 
-        for t in self.label_table.iter() {
-            self.asm.push(format!("{}:\n", t.0));
-            for stmt in t.1 {
-                self.asm.push(stmt.to_string());
+        let mut data_section = vec!["section .data\n".to_string()];
+        for (i, text) in module.strings.iter().enumerate() {
+            // the lexer already decoded escapes, emitting raw bytes keeps quotes,
+            // control characters and UTF-8 exactly as written
+            let bytes: Vec<String> = text.bytes().chain([0]).map(|b| b.to_string()).collect();
+            data_section.push(format!("\tLC_{} db {}\n", i, bytes.join(", ")));
+            data_section.push(format!("\tLC_len_{} equ {}\n", i, text.len()));
+        }
+        let mut text_section = vec!["section .text\n".to_string()];
+        let mut functions = vec![];
+        for function in &module.functions {
+            let name = self.symbol(function.name);
+            text_section.push(format!("\tglobal {}\n", name));
+            functions.push(format!("{}:\n", name));
+            functions.extend(FunctionCompiler::new(self, function).compile()?);
+        }
+        for name in &module.externs {
+            text_section.push(format!("\textern {}\n", self.symbol(name)));
+        }
+        if self.freestanding {
+            if let SupportedTargets::Mac = self.current_target {
+                return Err(CompilerError::UnsupportedTarget("--no-libc", None));
+            }
+            if !module.functions.iter().any(|fx| fx.name == "main") {
+                return Err(CompilerError::MissingEntryPoint);
             }
+            text_section.push("\tglobal _start\n".to_string());
+            functions.push("_start:\n".to_string());
+            functions.extend(Self::compile_entry_point());
         }
-
+        self.asm.extend(data_section);
+        self.asm.extend(text_section);
+        self.asm.extend(functions);
+        self.asm.extend(module.global_asm.iter().map(|line| format!("{}\n", line)));
         Ok(())
     }
 
-    pub fn compile_statement(&mut self, stmt: &Statement<'a>) -> Result<Vec<String>, CompilerError> {
-        match stmt {
-            Statement::ExpressionStatement(e) => self.compile_expression_statement(e),
-            Statement::VarDeclaration(var) => self.compile_variable_declaration_statement(var),
-            Statement::ReturnStatement(ret) => self.compile_return_statement(ret),
-            Statement::VariableReassignmentStatement(vrs) => self.compile_variable_reassignment_statement(vrs),
-            _ => {
-                Err(CompilerError::UnexpectedStatement)
-            }
+    // functions and externs are prefixed with an underscore on macOS
+    fn symbol(&self, name: &str) -> String {
+        match self.current_target {
+            SupportedTargets::Mac => format!("_{}", name),
+            SupportedTargets::Linux => name.to_string()
         }
     }
 
-    pub fn compile_variable_reassignment_statement(&mut self, stmt: &VariableReassignmentStatement<'a>) -> Result<Vec<String>, CompilerError> {
-        let compiled_lhs = self.compile_address(&stmt.lhs, "rbx");
-        let compiled_rhs = self.compile_expression(&stmt.rhs, "rdx");
-        let mut asms_main = vec!["\n\t; VARIABLE REASSIGNMENT".to_string()];
-        if let Ok(compiled_lhs) =  compiled_lhs {
-            for lhs_asm in compiled_lhs {
-                asms_main.push(lhs_asm);
-            }
+    // Without libc nothing calls main for us. The kernel starts the process with
+    // argc at [rsp] followed by the argv pointers, a NULL and the envp pointers.
+    fn compile_entry_point() -> Vec<String> {
+        vec![
+            "\txor rbp, rbp\n".to_string(),
+            "\tmov rdi, QWORD [rsp]\n".to_string(),
+            "\tlea rsi, [rsp+8]\n".to_string(),
+            "\tlea rdx, [rsi+rdi*8+8]\n".to_string(),
+            "\tand rsp, -16\n".to_string(),
+            "\tcall main\n".to_string(),
+            "\tmov edi, eax\n".to_string(),
+            "\tmov eax, 231\n".to_string(),
+            "\tsyscall\n".to_string(),
+        ]
+    }
+}
 
-            if let Ok(compiled_rhs) =  compiled_rhs {
-                for rhs_asm in compiled_rhs {
-                    asms_main.push(rhs_asm);
-                }
-                asms_main.push("\tmov [rbx], rdx\n".to_string());
-                Ok(asms_main)
+// selects the instructions for one IR function once its registers have been
+// allocated. values live in the register they were given or in a spill slot,
+// the selected instructions use rax, rcx, rdx, r11 and the xmm registers as
+// scratch. only the low bits of an int or a bool are meaningful, they are
+// extended where something reads all 64 bits.
+struct FunctionCompiler<'c, 'm, 'a> {
+    compiler: &'c Compiler<'a>,
+    function: &'m ir::Function<'a>,
+    allocation: Allocation,
+    // how far below rbp every slot starts
+    slot_offsets: Vec<usize>,
+    // how far below rbp the spill slots start
+    spill_area: usize,
+    code: Vec<String>,
+    // where in `code` the callee-saved registers have to be restored
+    epilogues: Vec<usize>
+}
+
+// where a value moved into a register comes from
+enum Source {
+    Register(&'static str),
+    Vector(usize),
+    Memory(String)
+}
+
+impl<'c, 'm, 'a> FunctionCompiler<'c, 'm, 'a> {
+    fn new(compiler: &'c Compiler<'a>, function: &'m ir::Function<'a>) -> Self {
+        let mut offset: usize = 0;
+        let mut slot_offsets = vec![];
+        for slot in &function.slots {
+            offset = (offset + slot.size).next_multiple_of(slot.align);
+            slot_offsets.push(offset);
+        }
+        Self {
+            compiler,
+            function,
+            allocation: if compiler.allocate_registers {
+                regalloc::allocate(function)
             } else {
-                panic!("{:?}", compiled_rhs);
-            }
-        } else {
-            panic!("{:?}", compiled_lhs);
+                regalloc::spill_everything(function)
+            },
+            slot_offsets,
+            spill_area: offset.next_multiple_of(8),
+            code: vec![],
+            epilogues: vec![]
         }
     }
 
-    pub fn compile_user_defined_type(&self, ut: &TypedExpression) -> TypedExpression {
-        match ut {
-            TypedExpression::UserDefinedTypeAlias { alias_for, .. } => {
-                self.compile_user_defined_type(&*alias_for)
-            }
-            TypedExpression::Pointer(inner) => {
-                TypedExpression::Pointer(Box::new(self.compile_user_defined_type(inner)))
-            }
-            TypedExpression::Function { args, return_type } => {
-                let resolved_args = args.iter()
-                    .map(|arg| self.compile_user_defined_type(arg))
-                    .collect();
-                let resolved_return = self.compile_user_defined_type(return_type);
-                TypedExpression::Function {
-                    args: resolved_args,
-                    return_type: Box::new(resolved_return)
-                }
-            }
-            _ => {
-                ut.clone()
-            }
-        }
+    fn emit(&mut self, line: String) {
+        self.code.push(format!("\t{}\n", line));
     }
 
-    fn type_size_align(&self, t: &TypedExpression) -> (usize, usize) {
-        match t {
-            TypedExpression::Integer => (SIZES.d_int, SIZES.d_int),
-            TypedExpression::Float => (SIZES.d_float, SIZES.d_float),
-            TypedExpression::String => (SIZES.d_ptr, SIZES.d_ptr),
-            TypedExpression::Void => (SIZES.d_bool, SIZES.d_bool),
-            TypedExpression::Pointer(_) => (SIZES.d_ptr, SIZES.d_ptr),
-            TypedExpression::Function { .. } => (SIZES.d_ptr, SIZES.d_ptr),
-            TypedExpression::Struct { name } => {
-                let def = self.struct_defs.get(name)
-                    .unwrap_or_else(|| panic!("Unknown struct type {}", name));
-                (def.size, def.align)
-            }
-            TypedExpression::UserDefinedTypeAlias { alias_for, .. } => {
-                let resolved = self.compile_user_defined_type(alias_for);
-                self.type_size_align(&resolved)
-            }
-        }
+    fn location(&self, vreg: VReg) -> Option<Location> {
+        self.allocation.locations[vreg.0]
     }
 
-    fn allocate_stack_slot(&mut self, size: usize, align: usize) -> isize {
-        self.current_stack_offset -= size as isize;
-        let abs = self.current_stack_offset.abs() as usize;
-        let rem = abs % align;
-        if rem != 0 {
-            self.current_stack_offset -= (align - rem) as isize;
-        }
-        self.current_stack_offset
+    fn spill_slot(&self, n: usize) -> String {
+        format!("[rbp-{}]", self.spill_area + (n + 1) * 8)
     }
 
-    fn calculate_stack_size_for_function(&self, stmt: &FunctionDeclaration<'a>) -> usize {
-        let mut offset: isize = 0;
-        for arg in &stmt.arguments {
-            let arg_type = self.compile_user_defined_type(&arg.arg_type);
-            let (size, align) = self.type_size_align(&arg_type);
-            offset -= size as isize;
-            let abs = offset.abs() as usize;
-            let rem = abs % align;
-            if rem != 0 {
-                offset -= (align - rem) as isize;
-            }
-        }
-        for st in &stmt.body.values {
-            if let Statement::VarDeclaration(var) = st {
-                let var_type = self.compile_user_defined_type(&var.variable_type);
-                let (size, align) = self.type_size_align(&var_type);
-                offset -= size as isize;
-                let abs = offset.abs() as usize;
-                let rem = abs % align;
-                if rem != 0 {
-                    offset -= (align - rem) as isize;
-                }
-            }
+    // the value as an operand of `size` bytes, a register or a spill slot
+    fn operand(&self, vreg: VReg, size: usize) -> String {
+        match self.location(vreg).expect("a register that is read has a location") {
+            Location::Register(register) => Self::register_of_size(register, size).expect("an allocatable register"),
+            Location::Spill(n) => format!("{} {}", Self::operand_size(size).expect("a register sized value"), self.spill_slot(n))
         }
-        self.align_bytes(offset.abs() as usize, 16)
     }
 
-    pub fn compile_variable_declaration_statement(&mut self, stmt: &VarDeclarationStatement<'a>) -> Result<Vec<String>, CompilerError> {
-        let mut asms_main = vec!["\n\t; VARIABLE DECLARATION\n".to_string()];
-        let resolved_type = self.compile_user_defined_type(&stmt.variable_type);
-        if let TypedExpression::Struct { name } = &resolved_type {
-            let (struct_size, struct_align) = {
-                let struct_def = self.struct_defs.get(name)
-                    .unwrap_or_else(|| panic!("Unknown struct type {}", name));
-                (struct_def.size, struct_def.align)
-            };
-            let offset = self.allocate_stack_slot(struct_size, struct_align);
-            self.symbol_table.insert(stmt.name, Symbol {
-                offset,
-                size: struct_size,
-                var_type: resolved_type.clone()
-            });
-            if let Expression::StructLiteral(lit) = &stmt.value {
-                self.emit_struct_literal_init(lit, offset, &mut asms_main)?;
-            } else {
-                return Err(CompilerError::UnknownDataType);
-            }
-        } else {
-            asms_main.extend(self.compile_expression(&stmt.value, "rax")?);
-            let (size, align) = self.type_size_align(&resolved_type);
-            let offset = self.allocate_stack_slot(size, align);
-            self.symbol_table.insert(stmt.name, Symbol {
-                offset,
-                size,
-                var_type: resolved_type
-            });
-            match size {
-                8 => asms_main.push(format!("\tmov QWORD [rbp{}], rax\n", offset)),
-                4 => asms_main.push(format!("\tmov DWORD [rbp{}], eax\n", offset)),
-                1 => asms_main.push(format!("\tmov BYTE [rbp{}], al\n", offset)),
-                _ => return Err(CompilerError::UnknownDataType),
+    // the register holding the value, loaded into `scratch` when it was spilled
+    fn in_register(&mut self, vreg: VReg, scratch: &'static str) -> &'static str {
+        match self.location(vreg).expect("a register that is read has a location") {
+            Location::Register(register) => register,
+            Location::Spill(n) => {
+                self.emit(format!("mov {}, QWORD {}", scratch, self.spill_slot(n)));
+                scratch
             }
         }
-        asms_main.push("\n".to_string());
-        Ok(asms_main)
     }
-    pub fn align_bytes(&self, bytes: usize, alignment: usize) -> usize {
-        let rem = bytes%alignment;
-        if rem > 0 {
-            bytes + alignment - rem
-        } else {
-            bytes
+
+    // the register a result should be computed in, its own or `scratch`
+    fn target(&self, vreg: VReg, scratch: &'static str) -> &'static str {
+        match self.location(vreg) {
+            Some(Location::Register(register)) => register,
+            _ => scratch
         }
     }
 
-    pub fn get_size_from_type(&self, t: &TypedExpression) -> usize {
-        match t {
-            TypedExpression::Integer => SIZES.d_int,
-            TypedExpression::String => SIZES.d_ptr,
-            TypedExpression::Pointer(_) => SIZES.d_ptr,
-            TypedExpression::Void => SIZES.d_bool,
-            TypedExpression::Float => SIZES.d_float,
-            TypedExpression::UserDefinedTypeAlias { alias_for, .. } => self.get_size_from_type(&alias_for),
-            TypedExpression::Function { .. } => SIZES.d_ptr,
-            TypedExpression::Struct { name } => {
-                let def = self.struct_defs.get(name)
-                    .unwrap_or_else(|| panic!("Unknown struct type {}", name));
-                def.size
-            }
+    // puts a result computed in `register` where its value lives
+    fn assign(&mut self, vreg: VReg, register: &str) {
+        match self.location(vreg) {
+            Some(Location::Register(home)) if home != register => self.emit(format!("mov {}, {}", home, register)),
+            Some(Location::Spill(n)) => self.emit(format!("mov QWORD {}, {}", self.spill_slot(n), register)),
+            _ => {}
         }
     }
 
-    pub fn compile_function_declaration_statement(&mut self, stmt: &FunctionDeclaration<'a>) -> Result<(String, Vec<String>), CompilerError> {
-        let old_sp = self.current_stack_offset;
-        self.current_stack_offset = 0;
-        let total_arg_size = self.calculate_stack_size_for_function(stmt);
+    fn slot(&self, slot: ir::SlotId) -> String {
+        format!("[rbp-{}]", self.slot_offsets[slot.0])
+    }
 
-        let mut body_stmts = vec![
-            "\tpush rbp\n".to_string(),
-            "\tmov rbp, rsp\n".to_string(),
-            format!("\tsub rsp, {}\n", self.align_bytes(total_arg_size, 16))
-        ];
-        if stmt.arity > 0 {
-            let order = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
-            let order_32_bit = ["edi", "esi", "edx", "ecx", "e8", "e9"];
-            (0..stmt.arity).for_each(|i| {
-                let arg_type = self.compile_user_defined_type(&stmt.arguments[i].arg_type);
-                let (size, align) = self.type_size_align(&arg_type);
-                let (operand_size, register): (&'a str, &'a str) = match size {
-                    4 => ("DWORD", order_32_bit[i]),
-                    8 => ("QWORD", order[i]),
-                    1 => ("BYTE", order[i]),
-                    _ => unimplemented!("Can not determine size of other things.")
-                };
-                let offset = self.allocate_stack_slot(size, align);
-                body_stmts.push(format!("\tmov {} [rbp{}], {}\n", operand_size, offset, register));
-                self.symbol_table.insert(stmt.arguments[i].name, Symbol {
-                    offset,
-                    size,
-                    var_type: arg_type
-                });
-            });
-        }
-        // will be used to later check if the function is returning by itself or not.
-        let mut has_explicit_return = false;
-        for body_statement in &stmt.body.values {
-            if let Statement::ReturnStatement(_) = body_statement {
-                has_explicit_return = true;
+    fn ty(&self, vreg: VReg) -> Ty {
+        self.function.vregs[vreg.0]
+    }
+
+    fn compile(mut self) -> Result<Vec<String>, CompilerError> {
+        self.compile_parameters();
+        for (i, block) in self.function.blocks.iter().enumerate() {
+            if i > 0 {
+                self.code.push(format!(".bb{}:\n", i));
+            }
+            for inst in &block.insts {
+                self.compile_inst(inst)?;
             }
-            let compiled = self.compile_statement(body_statement);
-            if let Ok(value) = compiled{
-                for compiled_stmt in value {
-                    body_stmts.push(compiled_stmt);
+            match block.terminator.expect("the verifier checks every block is terminated") {
+                Terminator::Jump(target) => {
+                    if target.0 != i + 1 {
+                        self.emit(format!("jmp .bb{}", target.0));
+                    }
                 }
-            } else {
-                println!("{:?}", body_statement);
-                panic!("{:?}", compiled);
+                Terminator::Return(value) => self.compile_return(value)
             }
         }
 
-        if !has_explicit_return {
-            body_stmts.push("\tmov rax, 0\n".to_string());
-            body_stmts.push("\tleave\n".to_string());
-            body_stmts.push("\tret\n".to_string());
-        } else {
-            body_stmts.push("\tleave\n".to_string());
-            body_stmts.push("\tret\n".to_string());
+        // C can call straight into any nerv function (qsort comparators, atexit
+        // handlers...) so the SysV callee-saved registers have to survive the call.
+        let saved_registers = self.used_callee_saved_registers();
+        let save_area_start = self.spill_area + self.allocation.spills * 8;
+        let frame_size = (save_area_start + saved_registers.len() * 8).next_multiple_of(16);
+        let mut function_asm = vec!["\tpush rbp\n".to_string(), "\tmov rbp, rsp\n".to_string()];
+        if frame_size > 0 {
+            function_asm.push(format!("\tsub rsp, {}\n", frame_size));
         }
-        self.current_stack_offset = old_sp;
-        Ok((stmt.name.to_string(), body_stmts))
-    }
-
-    pub fn compile_return_statement(&mut self, ret: &ReturnStatement<'a>) -> Result<Vec<String>, CompilerError> {
-        let x = &ret.value;
-        let mut main_asm_for_return = vec![];
-        if let Ok(compiled_literal) = self.compile_expression(x, "rax") {
-            main_asm_for_return.push("\n\t; Return Statement\n".to_string());
-            for v in compiled_literal {
-                main_asm_for_return.push(v);
+        let mut restore = vec![];
+        for (i, saved) in saved_registers.iter().enumerate() {
+            function_asm.push(format!("\tmov QWORD [rbp-{}], {}\n", save_area_start + (i + 1) * 8, saved));
+            restore.push(format!("\tmov {}, QWORD [rbp-{}]\n", saved, save_area_start + (i + 1) * 8));
+        }
+        for (i, line) in self.code.into_iter().enumerate() {
+            // the epilogue is only known once the body has been compiled
+            if self.epilogues.contains(&i) {
+                function_asm.extend(restore.iter().cloned());
             }
-            Ok(main_asm_for_return)
-        } else {
-            Ok(vec![
-                "mov rax, -1".to_string(),
-                "leave".to_string(),
-                "ret".to_string()
-            ])
+            function_asm.push(line);
         }
+        Ok(function_asm)
     }
 
-    pub fn compile_expression_statement(&mut self, stmt: &ExpressionStatement<'a>) -> Result<Vec<String>, CompilerError> {
-        let compiled = self.compile_expression(&stmt.value, "rax");
-        if let Ok(mut x) = compiled{
-            x.insert(0, "; Expression Statement\n".to_string());
-            Ok(x)
-        } else {
-            compiled
-        }
+    fn used_callee_saved_registers(&self) -> Vec<&'static str> {
+        let callee_saved = [
+            ("rbx", ["rbx", "ebx", "bx", "bl"]),
+            ("r12", ["r12", "r12d", "r12w", "r12b"]),
+            ("r13", ["r13", "r13d", "r13w", "r13b"]),
+            ("r14", ["r14", "r14d", "r14w", "r14b"]),
+            ("r15", ["r15", "r15d", "r15w", "r15b"]),
+        ];
+        // an asm block can clobber a register without spelling it out
+        let clobbers: Vec<&str> = self.function.blocks.iter()
+            .flat_map(|block| &block.insts)
+            .filter_map(|inst| match inst {
+                Inst::Asm(asm) => Some(asm.clobbers.iter().copied()),
+                _ => None
+            })
+            .flatten()
+            .collect();
+        callee_saved.iter()
+            .filter(|(_, names)| clobbers.iter().any(|clobber| names.contains(clobber)) || self.code.iter().any(|line| {
+                line.split(|c: char| !c.is_ascii_alphanumeric()).any(|word| names.contains(&word))
+            }))
+            .map(|(register, _)| *register)
+            .collect()
     }
 
-    fn infer_expression_type(&self, expr: &Expression<'a>) -> TypedExpression {
-        match expr {
-            Expression::Literal(LiteralExpression {
-                value: Token { meta_data: AnyMetadata::Identifier { value }, .. },
-                ..
-            }) => {
-                if let Some(sym) = self.symbol_table.get(value) {
-                    sym.var_type.clone()
-                } else {
-                    panic!("Unknown variable: {}", value);
-                }
+    // moves values into registers whose current contents may still be needed
+    // by another move, e.g. `rdi <- rsi` and `rsi <- rdi`. registers are read
+    // before anything is loaded from memory, a cycle is broken through xmm15.
+    fn move_into_registers<'r>(&mut self, moves: Vec<(&'r str, Source)>) {
+        let mut pending: Vec<(&'r str, &'r str)> = vec![];
+        let mut rest = vec![];
+        for (destination, source) in moves {
+            match source {
+                Source::Register(register) if register == destination => {}
+                Source::Register(register) => pending.push((destination, register)),
+                source => rest.push((destination, source))
             }
-            Expression::Unary(UnaryExpression { operator, value }) => {
-                match operator.token_type {
-                    TokenType::Ampersand => {
-                        let inner = self.infer_expression_type(value);
-                        TypedExpression::Pointer(Box::new(inner))
-                    }
-                    TokenType::Star => {
-                        if let TypedExpression::Pointer(inner) = self.infer_expression_type(value) {
-                            *inner
-                        } else {
-                            panic!("Trying to deref non-pointer");
+        }
+        while !pending.is_empty() {
+            let ready = pending.iter().position(|(destination, _)| !pending.iter().any(|(_, source)| source == destination));
+            let i = match ready {
+                Some(i) => i,
+                None => {
+                    // every destination is still to be read, park one of them
+                    let (parked, _) = pending[0];
+                    self.emit(format!("movq xmm15, {}", parked));
+                    for (_, source) in pending.iter_mut() {
+                        if *source == parked {
+                            *source = "xmm15";
                         }
                     }
-                    _ => panic!("Unsupported unary type inference")
-                }
-            }
-            Expression::FieldAccess(fa) => {
-                let target_type = self.infer_expression_type(&fa.target);
-                if let TypedExpression::Struct { name } = target_type {
-                    let def = self.struct_defs.get(&name)
-                        .unwrap_or_else(|| panic!("Unknown struct type {}", name));
-                    let field = def.fields.iter().find(|f| f.name == fa.field)
-                        .unwrap_or_else(|| panic!("Unknown field {} for struct {}", fa.field, name));
-                    field.field_type.clone()
-                } else {
-                    panic!("Field access on non-struct");
+                    0
                 }
+            };
+            let (destination, source) = pending.remove(i);
+            if source == "xmm15" {
+                self.emit(format!("movq {}, xmm15", destination));
+            } else {
+                self.emit(format!("mov {}, {}", destination, source));
             }
-            Expression::StructLiteral(sl) => {
-                TypedExpression::Struct { name: sl.name.to_string() }
+        }
+        for (destination, source) in rest {
+            match source {
+                Source::Vector(n) => self.emit(format!("movq {}, xmm{}", destination, n)),
+                Source::Memory(address) => self.emit(format!("mov {}, QWORD {}", destination, address)),
+                Source::Register(_) => unreachable!("register moves are done above")
             }
-            _ => panic!("Unsupported expression type inference")
         }
     }
 
-    fn get_register_info(&self, register: &str) -> Option<(usize, &str, &str, &str, &str)> {
-        // Returns (size_bytes, 64bit, 32bit, 16bit, 8bit)
-        match register {
-            "rax" => Some((8, "rax", "eax", "ax", "al")),
-            "rbx" => Some((8, "rbx", "ebx", "bx", "bl")),
-            "rcx" => Some((8, "rcx", "ecx", "cx", "cl")),
-            "rdx" => Some((8, "rdx", "edx", "dx", "dl")),
-            "rdi" => Some((8, "rdi", "edi", "di", "dil")),
-            "rsi" => Some((8, "rsi", "esi", "si", "sil")),
-            "r8" => Some((8, "r8", "r8d", "r8w", "r8b")),
-            "r9" => Some((8, "r9", "r9d", "r9w", "r9b")),
-            // Add more registers as needed
-            _ => None,
+    fn source(&self, vreg: VReg) -> Source {
+        match self.location(vreg).expect("a register that is read has a location") {
+            Location::Register(register) => Source::Register(register),
+            Location::Spill(n) => Source::Memory(self.spill_slot(n))
         }
     }
 
-
-    fn emit_address_of_variable(&mut self, var_name: &str, target_register: &str) -> Result<Vec<String>, CompilerError> {
-        let s = self.symbol_table.get(var_name).unwrap();
-        Ok(vec![format!("\n\tlea {}, [rbp{}]\n", target_register, s.offset)])
-    }
-
-    fn compile_address(&mut self, expr: &Expression<'a>, register: &'a str) -> Result<Vec<String>, CompilerError> {
-        match expr {
-            Expression::Literal(LiteralExpression {
-                value: Token {
-                    meta_data: AnyMetadata::Identifier { value: name },
-                    ..
-                },
-                ..
-            }) => {
-                self.emit_address_of_variable(name, register)
-            },
-            Expression::FieldAccess(FieldAccessExpression { target, field, .. }) => {
-                let mut result = self.compile_address(target, register)?;
-                let target_type = self.infer_expression_type(target);
-                if let TypedExpression::Struct { name } = target_type {
-                    let def = self.struct_defs.get(&name)
-                        .unwrap_or_else(|| panic!("Unknown struct type {}", name));
-                    let field_def = def.fields.iter()
-                        .find(|f| f.name == *field)
-                        .unwrap_or_else(|| panic!("Unknown field {} for struct {}", field, name));
-                    if field_def.offset > 0 {
-                        result.push(format!("\tadd {}, {}\n", register, field_def.offset));
-                    }
-                    Ok(result)
-                } else {
-                    panic!("Field access on non-struct");
-                }
-            }
-            Expression::Unary(UnaryExpression{ operator, value }) => {
-                match operator.token_type {
-                    TokenType::Ampersand => {
-                        let mut result = self.compile_address(value, register)?;
-                        result.push(format!("\tmov {}, [{}]\n", register, register));
-                        Ok(result)
-                    }
-                    t => {
-                        let mut result = self.compile_address(value, register)?;
-                        result.push(format!("\tmov {}, [{}]\n", register, register));
-                        Ok(result)
+    // moves every parameter from where the SysV ABI passes it to where it lives
+    fn compile_parameters(&mut self) {
+        let mut int_index = 0;
+        let mut float_index = 0;
+        // arguments which didn't fit in registers sit above the return address
+        let mut stack_argument_offset = 16;
+        let mut moves = vec![];
+        for param in &self.function.params {
+            let source = if self.ty(*param) == Ty::F64 && float_index < 8 {
+                float_index += 1;
+                Source::Vector(float_index - 1)
+            } else if self.ty(*param) != Ty::F64 && int_index < ARGUMENT_REGISTERS.len() {
+                int_index += 1;
+                Source::Register(ARGUMENT_REGISTERS[int_index - 1])
+            } else {
+                stack_argument_offset += 8;
+                Source::Memory(format!("[rbp+{}]", stack_argument_offset - 8))
+            };
+            match self.location(*param) {
+                Some(Location::Register(register)) => moves.push((register, source)),
+                // spilled parameters are stored first, before any register is overwritten
+                Some(Location::Spill(n)) => {
+                    let slot = self.spill_slot(n);
+                    match source {
+                        Source::Register(register) => self.emit(format!("mov QWORD {}, {}", slot, register)),
+                        Source::Vector(n) => self.emit(format!("movq QWORD {}, xmm{}", slot, n)),
+                        Source::Memory(address) => {
+                            self.emit(format!("mov rax, QWORD {}", address));
+                            self.emit(format!("mov QWORD {}, rax", slot));
+                        }
                     }
                 }
+                None => {}
             }
-            _ => unimplemented!()
         }
+        self.move_into_registers(moves);
     }
 
-    fn compile_deref(&mut self, expr: &Expression<'a>, register: &'a str) -> Result<Vec<String>, CompilerError> {
-        match expr {
-            Expression::Literal(LiteralExpression {
-                value: Token {
-                    meta_data: AnyMetadata::Identifier{..},
-                    ..
-                },
-                ..
-            }) => {
-                let mut res = self.compile_expression(expr, register)?;
-                res.push(format!("\tmov {}, [{}]\n", register, register));
-                Ok(res)
-            },
-            Expression::Unary(UnaryExpression{ operator, value }) => {
-                match operator.token_type {
-                    TokenType::Ampersand => {
-                        let mut result = self.compile_address(value, register)?;
-                        result.push(format!("mov {}, [{}]", register, register));
-                        Ok(result)
-                    }
-                    TokenType::Star => {
-                        let mut res = self.compile_expression(expr, register)?;
-                        res.push(format!("\tmov {}, [{}]\n", register, register));
-                        Ok(res)
-                    }
-                    _ => unimplemented!()
-                }
-            }
-            _ => unimplemented!()
+    // widens a value which only has its low bits set, for readers of all 64
+    fn extend(&mut self, register: &str, ty: Ty) -> Result<(), CompilerError> {
+        match ty {
+            Ty::I8 => self.emit(format!("movzx {}, {}", Self::register_of_size(register, 4)?, Self::register_of_size(register, 1)?)),
+            Ty::I32 => self.emit(format!("movsxd {}, {}", register, Self::register_of_size(register, 4)?)),
+            Ty::F64 | Ty::Ptr => {}
         }
+        Ok(())
     }
 
-    pub fn compile_expression(&mut self, expr: &Expression<'a>, register: &'a str) -> Result<Vec<String>, CompilerError> {
-        let mut asms_main = vec![];
-        match expr {
-            Expression::Binary(bin) => {
-                if let Ok(compiled_asms) = self.compile_expression(&bin.left, register) {
-                    for asm in compiled_asms {
-                        asms_main.push(asm);
-                    }
-                }
-
-                asms_main.push(format!("\tpush {}\n", register));
-
-                if let Ok(compiled_asms) = self.compile_expression(&bin.right, register) {
-                    for asm in compiled_asms {
-                        asms_main.push(asm);
-                    }
-                }
+    fn operand_size(size: usize) -> Result<&'static str, CompilerError> {
+        match size {
+            8 => Ok("QWORD"),
+            4 => Ok("DWORD"),
+            1 => Ok("BYTE"),
+            _ => Err(CompilerError::InvalidOperandSize(size))
+        }
+    }
 
-                asms_main.push("\tpop rcx\n".to_string());
-                match bin.operator.token_type {
-                    TokenType::Plus => asms_main.push(format!("\tadd {}, rcx\n", register)),
-                    TokenType::Minus =>asms_main.push(format!("\tsub {}, rcx\n", register)),
-                    TokenType::Star => asms_main.push(format!("\timul {}, rcx\n", register)),
-                    TokenType::Slash => {
-                        asms_main.push("\tmov rax, rcx\n".to_string());
-                        asms_main.push("\txor rdx, rdx\n".to_string());
-                        asms_main.push(format!("\tdiv {}\n", register));
-                        asms_main.push(format!("\tmov {}, rax\n", register));
-                    }
-                    _ => unimplemented!("Operator not implemented"),
-                }
+    // the name of the low `size` bytes of a 64 bit register
+    fn register_of_size(register: &str, size: usize) -> Result<String, CompilerError> {
+        let names = match register {
+            "rax" => ["rax", "eax", "al"],
+            "rbx" => ["rbx", "ebx", "bl"],
+            "rcx" => ["rcx", "ecx", "cl"],
+            "rdx" => ["rdx", "edx", "dl"],
+            "rdi" => ["rdi", "edi", "dil"],
+            "rsi" => ["rsi", "esi", "sil"],
+            "r8" | "r9" | "r10" | "r11" | "r12" | "r13" | "r14" | "r15" => {
+                let suffix = match size {
+                    8 => "",
+                    4 => "d",
+                    _ => "b"
+                };
+                return Ok(format!("{}{}", register, suffix));
             }
+            _ => return Err(CompilerError::InvalidRegister(register.to_string()))
+        };
+        Ok(match size {
+            8 => names[0],
+            4 => names[1],
+            _ => names[2]
+        }.to_string())
+    }
 
-            Expression::Literal(lit) => match &lit.value.meta_data {
-                AnyMetadata::Number { value: NumberType::Integer(val) } => {
-                    asms_main.push(format!("\tmov {}, {}\n", register, val));
-                }
-                AnyMetadata::Number { value: NumberType::Float(val) } => {
-                    asms_main.push(format!("\tmov {}, {:.9}\n", register, val));
-                }
-                AnyMetadata::Identifier { value } => {
-                    let variable_symbol = self.symbol_table.get(value);
-                    match variable_symbol {
-                        Some(s) => {
-                            // Get target register info
-                            let reg_info = self.get_register_info(register)
-                                .ok_or_else(|| {
-                                    panic!("Unsupported register: {}", register);
-                                })?;
-
-                            let (target_reg_size, reg_64, reg_32, reg_16, reg_8) = reg_info;
-
-                            // Check if we can fit the variable size into the target register
-                            if s.size > target_reg_size {
-                                panic!(
-                                    "Size mismatch: Variable '{}' has size {} bytes, but target register '{}' can only hold {} bytes",
-                                    value, s.size, register, target_reg_size
-                                );
-                            }
-
-                            // Generate appropriate mov instruction based on variable size
-                            match s.size {
-                                4 => {
-                                    asms_main.push(format!("\tmov {}, DWORD [rbp{}]\n", reg_32, s.offset));
-                                    // If target is 64-bit register, the upper 32 bits are automatically cleared
-                                }
-                                8 => {
-                                    if target_reg_size < 8 {
-                                        panic!(
-                                            "Size mismatch: Variable '{}' is 8 bytes but target register '{}' is only {} bytes",
-                                            value, register, target_reg_size
-                                        );
-                                    }
-                                    asms_main.push(format!("\tmov {}, QWORD [rbp{}]\n", reg_64, s.offset));
-                                }
-                                2 => {
-                                    asms_main.push(format!("\tmov {}, WORD [rbp{}]\n", reg_16, s.offset));
-                                    // Zero-extend to full register size if needed
-                                    if target_reg_size > 2 {
-                                        asms_main.push(format!("\tmovzx {}, {}\n", reg_64, reg_16));
-                                    }
-                                }
-                                1 => {
-                                    asms_main.push(format!("\tmov {}, BYTE [rbp{}]\n", reg_8, s.offset));
-                                    // Zero-extend to full register size if needed
-                                    if target_reg_size > 1 {
-                                        asms_main.push(format!("\tmovzx {}, {}\n", reg_64, reg_8));
-                                    }
-                                }
-                                _ => {
-                                    panic!("Unsupported variable size: {} bytes for variable '{}'", s.size, value);
-                                }
-                            }
-                        }
-                        None => {
-                            let mut function_name: String = "_".to_string();
-                            if let SupportedTargets::Linux = self.current_target {
-                                function_name = value.to_string();
-                            } else {
-                                function_name.push_str(value);
-                            }
-                            asms_main.push(format!("\tlea {}, [rel {}]\n", register, function_name));
-                        }
-                    }
-                }
-                AnyMetadata::String { value } => {
-                    if (**value).contains("\\n") {
-                        let mut splitted_by_new_line: Vec<&str> = value.split("\\n").collect();
-                        if let Some(last) = splitted_by_new_line.last() && last == &"" {
-                            splitted_by_new_line.pop();
-                        }
-                        let mut lc = String::from(format!("\tLC_{} db ", self.data_counter));
-                        let mut x = String::new();
-                        for d in splitted_by_new_line {
-                            if(d != "") {
-                                let newd = d.replace("\"", "");
-                                x.push_str("\"");
-                                x.push_str(&newd);
-                                x.push_str("\"");
-                            }
-                            x.push_str(", 10");
-                        }
-                        x.push_str(", 0\n");
-                        lc.push_str(&x.to_string());
-                        self.data_section.push(lc);
-                        self.data_section.push(format!("\tLC_len_{} equ {}\n", self.data_counter, (*value).len()).to_string());
-                        asms_main.push(format!("\tlea {}, [rel LC_{}]\n", register, self.data_counter).to_string());
-                        self.data_counter += 1; // Don't forget to increment!
-                    } else {
-                        self.data_section.push(format!("\tLC_{} db \"{}\", 0\n", self.data_counter, (*value)).to_string());
-                        self.data_section.push(format!("\tLC_len_{} equ {}\n", self.data_counter, (*value).len()).to_string());
-                        asms_main.push(format!("\tlea {}, [rel LC_{}]\n", register, self.data_counter).to_string());
-                        self.data_counter += 1; // Don't forget to increment!
-                    }
-                }
-                _ => unimplemented!("Only number literals supported for now"),
-            }
-            Expression::Call(c) => {
-                let order = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
-                (0..c.arguments.len()).for_each(|i| {
-                    let value = self.compile_expression(&c.arguments[i], order[i]).expect("WTF");
-                    for v in value {
-                        asms_main.push(v);
-                    }
-                });
-                asms_main.push("\txor rax, rax\n".to_string());
-                if let Expression::Literal(LiteralExpression { value: Token { meta_data: AnyMetadata::Identifier { value }, .. }, .. }) = &*c.callee {
-                    if self.symbol_table.contains_key(value) {
-                        asms_main.extend(self.compile_expression(&c.callee, "rax")?);
-                        asms_main.push("\tcall rax\n".to_string());
-                    } else {
-                        let mut function_name: String = "_".to_string();
-                        if let SupportedTargets::Linux = self.current_target {
-                            function_name = value.to_string();
-                        } else {
-                            function_name.push_str(value);
-                        }
-                        asms_main.push(format!("\tcall {}\n", function_name));
-                    }
-                } else {
-                    asms_main.extend(self.compile_expression(&c.callee, "rax")?);
-                    asms_main.push("\tcall rax\n".to_string());
-                }
-                asms_main.push(format!("\tmov {}, rax\n", register));
-            }
-            Expression::Unary(u) => {
-                match u.operator.token_type {
-                    TokenType::Ampersand => {
-                        return self.compile_address(&u.value, register)
-                    }
-                    TokenType::Star => {
-                        return self.compile_deref(&u.value, register)
-                    }
-                    _ => unimplemented!()
+    fn compile_inst(&mut self, inst: &Inst<'a>) -> Result<(), CompilerError> {
+        // a value nothing reads is not computed, unless computing it does something else
+        if let Some(dst) = inst.dst()
+            && self.location(dst).is_none()
+            && !matches!(inst, Inst::Call { .. } | Inst::Syscall { .. }) {
+            return Ok(());
+        }
+        match inst {
+            Inst::Const { dst, value } => {
+                let target = self.target(*dst, "rax");
+                match self.ty(*dst) {
+                    Ty::I8 | Ty::I32 => self.emit(format!("mov {}, {}", Self::register_of_size(target, 4)?, *value as i32)),
+                    Ty::F64 | Ty::Ptr => self.emit(format!("mov {}, {}", target, value))
                 }
-            }
-            Expression::FieldAccess(_) => {
-                let field_type = self.infer_expression_type(expr);
-                let (size, _) = self.type_size_align(&field_type);
-                let mut res = self.compile_address(expr, "rbx")?;
-                let reg_info = self.get_register_info(register)
-                    .ok_or_else(|| CompilerError::UnknownDataType)?;
-                let (target_reg_size, reg_64, reg_32, _reg_16, reg_8) = reg_info;
-                if size > target_reg_size {
-                    return Err(CompilerError::UnknownDataType);
+                self.assign(*dst, target);
+            }
+            Inst::Float { dst, value } => {
+                // floats travel through general purpose registers as their raw bits
+                let target = self.target(*dst, "rax");
+                self.emit(format!("mov {}, 0x{:x}", target, value.to_bits()));
+                self.assign(*dst, target);
+            }
+            Inst::Str { dst, index } => {
+                let target = self.target(*dst, "rax");
+                self.emit(format!("lea {}, [rel LC_{}]", target, index));
+                self.assign(*dst, target);
+            }
+            Inst::FuncAddr { dst, name } => {
+                let target = self.target(*dst, "rax");
+                self.emit(format!("lea {}, [rel {}]", target, self.compiler.symbol(name)));
+                self.assign(*dst, target);
+            }
+            Inst::SlotAddr { dst, slot } => {
+                let target = self.target(*dst, "rax");
+                self.emit(format!("lea {}, {}", target, self.slot(*slot)));
+                self.assign(*dst, target);
+            }
+            Inst::Offset { dst, base, bytes } => {
+                let base = self.in_register(*base, "rax");
+                let target = self.target(*dst, "rax");
+                self.emit(format!("lea {}, [{}+{}]", target, base, bytes));
+                self.assign(*dst, target);
+            }
+            Inst::Load { dst, addr } => {
+                let addr = self.in_register(*addr, "rax");
+                let target = self.target(*dst, "rax");
+                match self.ty(*dst) {
+                    Ty::I8 => self.emit(format!("movzx {}, BYTE [{}]", Self::register_of_size(target, 4)?, addr)),
+                    Ty::I32 => self.emit(format!("mov {}, DWORD [{}]", Self::register_of_size(target, 4)?, addr)),
+                    Ty::F64 | Ty::Ptr => self.emit(format!("mov {}, QWORD [{}]", target, addr))
                 }
-                match size {
-                    8 => res.push(format!("\tmov {}, QWORD [rbx]\n", reg_64)),
-                    4 => res.push(format!("\tmov {}, DWORD [rbx]\n", reg_32)),
-                    1 => {
-                        res.push(format!("\tmov {}, BYTE [rbx]\n", reg_8));
-                        if target_reg_size > 1 {
-                            res.push(format!("\tmovzx {}, {}\n", reg_64, reg_8));
-                        }
-                    }
-                    _ => return Err(CompilerError::UnknownDataType),
+                self.assign(*dst, target);
+            }
+            Inst::Store { addr, value } => {
+                let addr = self.in_register(*addr, "rax");
+                let value_register = self.in_register(*value, "rcx");
+                let size = self.ty(*value).size();
+                self.emit(format!("mov {} [{}], {}", Self::operand_size(size)?, addr, Self::register_of_size(value_register, size)?));
+            }
+            Inst::Binary { dst, op, lhs, rhs } => self.compile_binary(*dst, *op, *lhs, *rhs)?,
+            Inst::IntToFloat { dst, src } => {
+                self.emit(format!("cvtsi2sd xmm0, {}", self.operand(*src, 4)));
+                let target = self.target(*dst, "rax");
+                self.emit(format!("movq {}, xmm0", target));
+                self.assign(*dst, target);
+            }
+            Inst::BoolToInt { dst, src } => {
+                let source = self.operand(*src, 1);
+                let target = self.target(*dst, "rax");
+                self.emit(format!("movzx {}, {}", Self::register_of_size(target, 4)?, source));
+                self.assign(*dst, target);
+            }
+            Inst::Call { dst, callee, args, variadic } => self.compile_call(*dst, callee, args, *variadic),
+            Inst::Syscall { dst, args } => {
+                let moves = SYSCALL_REGISTERS.iter().zip(args).map(|(register, argument)| (*register, self.source(*argument))).collect();
+                self.move_into_registers(moves);
+                for (register, argument) in SYSCALL_REGISTERS.iter().zip(args) {
+                    self.extend(register, self.ty(*argument))?;
                 }
-                return Ok(res);
-            }
-            Expression::StructLiteral(_) => {
-                return Err(CompilerError::UnknownDataType);
+                // the kernel clobbers rcx and r11, neither ever holds a value
+                self.emit("syscall".to_string());
+                self.assign(*dst, "rax");
             }
-            _ => unimplemented!("Only number literals supported for now found: {:?}", expr),
+            Inst::Asm(asm) => self.compile_asm(asm)?
+        }
+        Ok(())
+    }
+
+    fn compile_binary(&mut self, dst: VReg, op: BinaryOp, lhs: VReg, rhs: VReg) -> Result<(), CompilerError> {
+        if self.ty(lhs) == Ty::F64 {
+            self.emit(format!("movq xmm0, {}", self.operand(lhs, 8)));
+            self.emit(format!("movq xmm1, {}", self.operand(rhs, 8)));
+            let instruction = match op {
+                BinaryOp::Add => "addsd",
+                BinaryOp::Sub => "subsd",
+                BinaryOp::Mul => "mulsd",
+                BinaryOp::Div => "divsd"
+            };
+            self.emit(format!("{} xmm0, xmm1", instruction));
+            let target = self.target(dst, "rax");
+            self.emit(format!("movq {}, xmm0", target));
+            self.assign(dst, target);
+            return Ok(());
+        }
+        if op == BinaryOp::Div {
+            self.emit(format!("mov eax, {}", self.operand(lhs, 4)));
+            self.emit("cdq".to_string());
+            self.emit(format!("idiv {}", self.operand(rhs, 4)));
+            self.assign(dst, "rax");
+            return Ok(());
+        }
+        // ints wrap at 32 bits, the result is computed in place when the right
+        // hand side doesn't share its register
+        let rhs_operand = self.operand(rhs, 4);
+        let mut target = self.target(dst, "rax");
+        if self.location(rhs) == Some(Location::Register(target)) && self.location(lhs) != Some(Location::Register(target)) {
+            target = "rax";
         }
-        Ok(asms_main)
+        let target_32 = Self::register_of_size(target, 4)?;
+        let lhs_operand = self.operand(lhs, 4);
+        if lhs_operand != target_32 {
+            self.emit(format!("mov {}, {}", target_32, lhs_operand));
+        }
+        let instruction = match op {
+            BinaryOp::Add => "add",
+            BinaryOp::Sub => "sub",
+            BinaryOp::Mul => "imul",
+            BinaryOp::Div => unreachable!("handled above")
+        };
+        self.emit(format!("{} {}, {}", instruction, target_32, rhs_operand));
+        self.assign(dst, target);
+        Ok(())
     }
 
-    fn build_struct_def(&self, name: &str, fields: Vec<crate::shared::parser_nodes::StructField<'a>>) -> StructDef {
-        let mut layout_fields = vec![];
-        let mut offset = 0;
-        let mut max_align = 1;
-        for field in fields {
-            let field_type = self.compile_user_defined_type(&field.field_type);
-            let (size, align) = self.type_size_align(&field_type);
-            if align > max_align {
-                max_align = align;
+    fn compile_call(&mut self, dst: Option<VReg>, callee: &Callee<'a>, args: &[VReg], variadic: bool) {
+        // floats go through xmm0-xmm7, everything else through the integer
+        // registers, whatever doesn't fit is passed on the stack in order.
+        let mut locations = vec![];
+        let mut int_index = 0;
+        let mut float_index = 0;
+        let mut stack_index = 0;
+        for argument in args {
+            let is_float = self.ty(*argument) == Ty::F64;
+            if is_float && float_index < 8 {
+                locations.push(ArgumentLocation::Vector(float_index));
+                float_index += 1;
+            } else if !is_float && int_index < ARGUMENT_REGISTERS.len() {
+                locations.push(ArgumentLocation::Integer(ARGUMENT_REGISTERS[int_index]));
+                int_index += 1;
+            } else {
+                locations.push(ArgumentLocation::Stack(stack_index));
+                stack_index += 1;
             }
-            let rem = offset % align;
-            if rem != 0 {
-                offset += align - rem;
+        }
+        // rsp is 16 byte aligned between instructions, keep it that way for the call
+        let stack_arguments_size = (stack_index * 8).next_multiple_of(16);
+        if stack_arguments_size > 0 {
+            self.emit(format!("sub rsp, {}", stack_arguments_size));
+        }
+        // everything is read out of the argument registers before they are written
+        let mut moves = vec![];
+        for (argument, location) in args.iter().zip(&locations) {
+            match location {
+                ArgumentLocation::Stack(n) => {
+                    let register = self.in_register(*argument, "rax");
+                    self.emit(format!("mov QWORD [rsp+{}], {}", n * 8, register));
+                }
+                ArgumentLocation::Vector(n) => self.emit(format!("movq xmm{}, {}", n, self.operand(*argument, 8))),
+                ArgumentLocation::Integer(register) => moves.push((*register, self.source(*argument)))
             }
-            layout_fields.push(StructFieldDef {
-                name: field.name.to_string(),
-                field_type,
-                offset,
-                size,
-                align
-            });
-            offset += size;
-        }
-        let final_rem = offset % max_align;
-        if final_rem != 0 {
-            offset += max_align - final_rem;
-        }
-        StructDef {
-            name: name.to_string(),
-            fields: layout_fields,
-            size: offset,
-            align: max_align
+        }
+        if let Callee::Indirect(target) = callee {
+            // r11 is neither an argument register nor al, so loading the target can't clobber either
+            self.emit(format!("mov r11, {}", self.operand(*target, 8)));
+        }
+        self.move_into_registers(moves);
+        if variadic {
+            // the callee uses al to know how many vector registers hold arguments
+            self.emit(format!("mov eax, {}", float_index));
+        }
+        match callee {
+            Callee::Direct(name) => self.emit(format!("call {}", self.compiler.symbol(name))),
+            Callee::Indirect(_) => self.emit("call r11".to_string())
+        }
+        if stack_arguments_size > 0 {
+            self.emit(format!("add rsp, {}", stack_arguments_size));
+        }
+        let Some(dst) = dst else {
+            return;
+        };
+        if self.ty(dst) == Ty::F64 {
+            let target = self.target(dst, "rax");
+            self.emit(format!("movq {}, xmm0", target));
+            self.assign(dst, target);
+        } else {
+            self.assign(dst, "rax");
         }
     }
 
-    fn emit_struct_literal_init(&mut self, lit: &StructLiteralExpression<'a>, base_offset: isize, asms_main: &mut Vec<String>) -> Result<(), CompilerError> {
-        let struct_fields = {
-            let struct_def = self.struct_defs.get(lit.name)
-                .unwrap_or_else(|| panic!("Unknown struct type {}", lit.name));
-            struct_def.fields.clone()
-        };
-        for field in &lit.fields {
-            let field_def = struct_fields.iter()
-                .find(|f| f.name == field.name)
-                .unwrap_or_else(|| panic!("Unknown field {} for struct {}", field.name, lit.name));
-            let compiled_value = self.compile_expression(&field.value, "rax")?;
-            for asm in compiled_value {
-                asms_main.push(asm);
-            }
-            let field_offset = base_offset + field_def.offset as isize;
-            match field_def.size {
-                8 => asms_main.push(format!("\tmov QWORD [rbp{}], rax\n", field_offset)),
-                4 => asms_main.push(format!("\tmov DWORD [rbp{}], eax\n", field_offset)),
-                1 => asms_main.push(format!("\tmov BYTE [rbp{}], al\n", field_offset)),
-                _ => return Err(CompilerError::UnknownDataType),
+    // nothing is saved around the block itself: the allocator never keeps a
+    // value in a register across an `asm` block, and the callee-saved registers
+    // it clobbers are saved by the prologue. `clobber(...)` only has to name those.
+    fn compile_asm(&mut self, asm: &InlineAsm<'a>) -> Result<(), CompilerError> {
+        self.code.push("\n\t; INLINE ASM\n".to_string());
+        let moves = asm.inputs.iter().map(|(register, value)| (*register, self.source(*value))).collect();
+        self.move_into_registers(moves);
+        for (register, value) in &asm.inputs {
+            self.extend(register, self.ty(*value))?;
+        }
+        let mut bindings = vec![];
+        for (name, binding) in &asm.placeholders {
+            let operand = match binding {
+                Binding::Register(register) => register.to_string(),
+                Binding::Slot(slot) => {
+                    let size = self.function.slots[slot.0].size;
+                    format!("{} {}", Self::operand_size(size)?, self.slot(*slot))
+                }
+            };
+            bindings.push((name, operand));
+        }
+        for line in &asm.template {
+            let mut line = line.to_string();
+            for (name, operand) in &bindings {
+                line = line.replace(&format!("{{{}}}", name), operand);
             }
+            self.emit(line);
+        }
+        for (register, slot) in &asm.outputs {
+            let size = self.function.slots[slot.0].size;
+            self.emit(format!("mov {} {}, {}", Self::operand_size(size)?, self.slot(*slot), Self::register_of_size(register, size)?));
         }
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    // use std::io::Write;
-    // use crate::parser::Parser;
-    // use super::*;
-
-    // #[test]
-    // fn check_simple_arithmetic() {
-    //     let mut p = Parser::new("5 + 4 * 3\0");
-    //     let program = p.parse();
-    //     let mut c = match Compiler::new(program, "test.s") {
-    //         Ok(t) => t,
-    //         Err(e) => panic!("{:?}", e)
-    //     };
-    //     let _ = c.compile();
-    //     dbg!(&c.asm);
-    //     let correct = vec![
-    //         "global _start\n",
-    //         "_start:\n",
-    //         "\tmov rax, 5\n",
-    //         "\tpush rax\n",
-    //         "\tmov rax, 4\n",
-    //         "\tpush rax\n",
-    //         "\tmov rax, 3\n",
-    //         "\tpop rbx\n",
-    //         "\timul rax, rbx\n",
-    //         "\tpop rbx\n",
-    //         "\tadd rax, rbx\n",
-    //         "\tmov rax, 60\n",
-    //         "\tmov rdi, 0\n",
-    //         "\tsyscall\n",
-    //     ];
-    //     assert_eq!(correct, c.asm);
-    //     for asm in c.asm{
-    //         let _ = c.file_handler.write_all(asm.as_bytes());
-    //     }
-    // }
+    fn compile_return(&mut self, value: Option<VReg>) {
+        match value {
+            Some(value) if self.ty(value) == Ty::F64 => self.emit(format!("movq xmm0, {}", self.operand(value, 8))),
+            Some(value) => self.emit(format!("mov rax, {}", self.operand(value, 8))),
+            // falling off the end of a void function returns 0, main included
+            None => self.emit("xor eax, eax".to_string())
+        }
+        self.epilogues.push(self.code.len());
+        self.emit("leave".to_string());
+        self.emit("ret".to_string());
+    }
 }
-
@@ -0,0 +1,208 @@
+use std::collections::HashSet;
+
+use crate::ir::{Function, Inst, Ty, VReg};
+
+use super::ARGUMENT_REGISTERS;
+
+// the registers values are kept in. rax, rcx, rdx and r11 are left to the
+// instruction selection as scratch, floats are kept as their raw bits in
+// general purpose registers like everything else.
+pub const CALLER_SAVED: [&str; 5] = ["rdi", "rsi", "r8", "r9", "r10"];
+pub const CALLEE_SAVED: [&str; 5] = ["rbx", "r12", "r13", "r14", "r15"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Register(&'static str),
+    // the n-th 8 byte spill slot of the frame
+    Spill(usize)
+}
+
+pub struct Allocation {
+    // where every register lives, None for registers that are never read
+    pub locations: Vec<Option<Location>>,
+    pub spills: usize
+}
+
+struct Interval {
+    vreg: VReg,
+    start: usize,
+    end: usize
+}
+
+// linear scan over the live intervals of the registers. the blocks are laid out
+// in order and numbered, parameters are assigned at 0 and every instruction and
+// terminator gets the next number. a register is live from its assignment to
+// its last use, stretched over every block it is live through.
+//
+// calls and syscalls clobber the caller-saved registers, so a value live across
+// one only gets a callee-saved register. an `asm` block can touch any register,
+// a value live across one is always spilled. when nothing fits, whichever of
+// the competing values is needed longest is spilled for its whole lifetime.
+// a value that arrives in or is passed in an argument register gets that
+// register when it is free, which saves shuffling them around a call.
+pub fn allocate(function: &Function) -> Allocation {
+    let (intervals, calls, asm_blocks) = build_intervals(function);
+    let hints = argument_hints(function);
+    let crosses = |interval: &Interval, points: &[usize]| points.iter().any(|p| interval.start < *p && *p < interval.end);
+
+    let mut locations = vec![None; function.vregs.len()];
+    let mut spills = 0;
+    let mut spill = |locations: &mut Vec<Option<Location>>, vreg: VReg| {
+        locations[vreg.0] = Some(Location::Spill(spills));
+        spills += 1;
+    };
+    let mut free: Vec<&'static str> = CALLER_SAVED.iter().chain(&CALLEE_SAVED).copied().collect();
+    // (end, register, vreg) of the intervals holding a register
+    let mut active: Vec<(usize, &'static str, VReg)> = vec![];
+    for interval in &intervals {
+        active.retain(|(end, register, _)| {
+            // a register last read by an instruction can take the value the
+            // instruction assigns
+            if *end <= interval.start {
+                free.push(register);
+                return false;
+            }
+            true
+        });
+        if crosses(interval, &asm_blocks) {
+            spill(&mut locations, interval.vreg);
+            continue;
+        }
+        let allowed: Vec<&str> = if crosses(interval, &calls) {
+            CALLEE_SAVED.to_vec()
+        } else {
+            CALLER_SAVED.iter().chain(&CALLEE_SAVED).copied().collect()
+        };
+        let hint = hints[interval.vreg.0].filter(|hint| allowed.contains(hint) && free.contains(hint));
+        if let Some(register) = hint.or_else(|| allowed.iter().find(|register| free.contains(register)).copied()) {
+            free.retain(|r| *r != register);
+            locations[interval.vreg.0] = Some(Location::Register(register));
+            active.push((interval.end, register, interval.vreg));
+            continue;
+        }
+        let longest = active.iter().enumerate()
+            .filter(|(_, (_, register, _))| allowed.contains(register))
+            .max_by_key(|(_, (end, _, _))| *end)
+            .map(|(i, _)| i);
+        match longest {
+            Some(i) if active[i].0 > interval.end => {
+                let (_, register, vreg) = active[i];
+                spill(&mut locations, vreg);
+                locations[interval.vreg.0] = Some(Location::Register(register));
+                active[i] = (interval.end, register, interval.vreg);
+            }
+            _ => spill(&mut locations, interval.vreg)
+        }
+    }
+    Allocation { locations, spills }
+}
+
+// every register in its own spill slot, what `--no-regalloc` compiles with
+pub fn spill_everything(function: &Function) -> Allocation {
+    let locations = (0..function.vregs.len()).map(|v| Some(Location::Spill(v))).collect();
+    Allocation { locations, spills: function.vregs.len() }
+}
+
+// the argument register every parameter arrives in and every call argument is
+// passed in, the first one wins
+fn argument_hints(function: &Function) -> Vec<Option<&'static str>> {
+    let mut hints = vec![None; function.vregs.len()];
+    let mut hint = |values: &[VReg]| {
+        let integers = values.iter().filter(|value| function.vregs[value.0] != Ty::F64);
+        for (value, register) in integers.zip(ARGUMENT_REGISTERS) {
+            hints[value.0] = hints[value.0].or(Some(register));
+        }
+    };
+    hint(&function.params);
+    for inst in function.blocks.iter().flat_map(|block| &block.insts) {
+        if let Inst::Call { args, .. } = inst {
+            hint(args);
+        }
+    }
+    hints
+}
+
+// the live intervals sorted by their start, and where the calls and asm blocks are
+fn build_intervals(function: &Function) -> (Vec<Interval>, Vec<usize>, Vec<usize>) {
+    let blocks = &function.blocks;
+    let mut successors = vec![vec![]; blocks.len()];
+    let mut ranges = vec![];
+    let mut position = 1;
+    for (i, block) in blocks.iter().enumerate() {
+        if let Some(crate::ir::Terminator::Jump(target)) = block.terminator {
+            successors[i].push(target.0);
+        }
+        let start = position;
+        position += block.insts.len() + 1;
+        ranges.push((start, position - 1));
+    }
+
+    // live in and out of every block, iterated until nothing changes
+    let mut live_in: Vec<HashSet<VReg>> = vec![HashSet::new(); blocks.len()];
+    let mut live_out: Vec<HashSet<VReg>> = vec![HashSet::new(); blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in (0..blocks.len()).rev() {
+            let out: HashSet<VReg> = successors[i].iter().flat_map(|s| live_in[*s].iter().copied()).collect();
+            let mut live = out.clone();
+            let block = &blocks[i];
+            if let Some(terminator) = &block.terminator {
+                live.extend(terminator.uses());
+            }
+            for inst in block.insts.iter().rev() {
+                if let Some(dst) = inst.dst() {
+                    live.remove(&dst);
+                }
+                live.extend(inst.uses());
+            }
+            if out != live_out[i] || live != live_in[i] {
+                live_out[i] = out;
+                live_in[i] = live;
+                changed = true;
+            }
+        }
+    }
+
+    let mut start = vec![usize::MAX; function.vregs.len()];
+    let mut end = vec![None; function.vregs.len()];
+    for param in &function.params {
+        start[param.0] = 0;
+    }
+    let mut calls = vec![];
+    let mut asm_blocks = vec![];
+    for (i, block) in blocks.iter().enumerate() {
+        let (block_start, block_end) = ranges[i];
+        for (offset, inst) in block.insts.iter().enumerate() {
+            let position = block_start + offset;
+            match inst {
+                Inst::Call { .. } | Inst::Syscall { .. } => calls.push(position),
+                Inst::Asm(_) => asm_blocks.push(position),
+                _ => {}
+            }
+            for used in inst.uses() {
+                end[used.0] = end[used.0].max(Some(position));
+            }
+            if let Some(dst) = inst.dst() {
+                start[dst.0] = start[dst.0].min(position);
+            }
+        }
+        if let Some(terminator) = &block.terminator {
+            for used in terminator.uses() {
+                end[used.0] = end[used.0].max(Some(block_end));
+            }
+        }
+        for vreg in &live_in[i] {
+            start[vreg.0] = start[vreg.0].min(block_start);
+        }
+        for vreg in &live_out[i] {
+            end[vreg.0] = end[vreg.0].max(Some(block_end));
+        }
+    }
+
+    let mut intervals: Vec<Interval> = (0..function.vregs.len())
+        .filter_map(|v| end[v].map(|end| Interval { vreg: VReg(v), start: start[v], end }))
+        .collect();
+    intervals.sort_by_key(|interval| (interval.start, interval.vreg.0));
+    (intervals, calls, asm_blocks)
+}
@@ -0,0 +1,621 @@
+use std::collections::HashMap;
+
+use crate::shared::{
+    errors::CompilerError, parser_nodes::{AsmOperandKind, TypedExpression}, positions::Position, tokens::TokenType,
+    typed_nodes::{self, Asm, Block, Expr, ExprKind, Stmt, TypedProgram}
+};
+
+use super::{
+    BasicBlock, BinaryOp, Binding, BlockId, Callee, Function, Inst, InlineAsm, Module, Slot, SlotId, Terminator, Ty, VReg
+};
+
+// the machine type a value of `t` is held in, None for void and structs which
+// only ever live in memory
+pub fn value_type(t: &TypedExpression) -> Option<Ty> {
+    match t {
+        TypedExpression::Integer => Some(Ty::I32),
+        TypedExpression::Float => Some(Ty::F64),
+        TypedExpression::Bool => Some(Ty::I8),
+        TypedExpression::String | TypedExpression::Pointer(_) | TypedExpression::Function { .. } => Some(Ty::Ptr),
+        _ => None
+    }
+}
+
+fn scalar_type(t: &TypedExpression, position: Position) -> Result<Ty, CompilerError> {
+    value_type(t).ok_or(CompilerError::UnknownDataType(position))
+}
+
+pub fn lower<'a>(program: &TypedProgram<'a>) -> Result<Module<'a>, CompilerError> {
+    let mut module = Module { externs: program.externs.clone(), ..Module::default() };
+    for function in &program.functions {
+        let lowered = FunctionBuilder::new(program, &mut module.strings, function)?.lower(function)?;
+        module.functions.push(lowered);
+    }
+    for asm in &program.global_asm {
+        // there are no locals to bind outside of a function
+        if !asm.operands.is_empty() {
+            return Err(CompilerError::UnexpectedStatement(asm.position));
+        }
+        module.global_asm.extend(&asm.template);
+    }
+    Ok(module)
+}
+
+struct FunctionBuilder<'p, 'a> {
+    program: &'p TypedProgram<'a>,
+    strings: &'p mut Vec<&'a str>,
+    function: Function<'a>,
+    current: BlockId,
+    exit: BlockId,
+    return_slot: Option<SlotId>,
+    // one map per open scope, innermost last
+    scopes: Vec<HashMap<&'a str, SlotId>>,
+    // statements registered with `defer` along with the locals visible where
+    // they were deferred, one list per open scope
+    deferred: Vec<Vec<(Stmt<'a>, HashMap<&'a str, SlotId>)>>,
+    // slots of locals whose block has been left, handed out again to the
+    // next local of the same size so sibling blocks share their memory
+    free_slots: Vec<SlotId>,
+    // the slots of the locals declared in every open scope
+    scope_slots: Vec<Vec<SlotId>>
+}
+
+impl<'p, 'a> FunctionBuilder<'p, 'a> {
+    fn new(program: &'p TypedProgram<'a>, strings: &'p mut Vec<&'a str>, function: &typed_nodes::Function<'a>) -> Result<Self, CompilerError> {
+        let return_type = match function.return_type {
+            TypedExpression::Void => None,
+            ref t => Some(scalar_type(t, function.position)?)
+        };
+        let empty = || BasicBlock { insts: vec![], terminator: None };
+        let mut builder = Self {
+            program,
+            strings,
+            function: Function {
+                name: function.name,
+                params: vec![],
+                return_type,
+                slots: vec![],
+                vregs: vec![],
+                blocks: vec![empty(), empty()]
+            },
+            current: BlockId(0),
+            exit: BlockId(1),
+            return_slot: None,
+            scopes: vec![HashMap::new()],
+            deferred: vec![vec![]],
+            free_slots: vec![],
+            scope_slots: vec![vec![]]
+        };
+        if let Some(ty) = return_type {
+            builder.return_slot = Some(builder.new_slot("return", ty.size(), ty.size()));
+        }
+        Ok(builder)
+    }
+
+    fn lower(mut self, function: &typed_nodes::Function<'a>) -> Result<Function<'a>, CompilerError> {
+        // parameters arrive in registers and are stored into slots of their own,
+        // so they can be assigned to and have their address taken like any local
+        for (_, param_type) in &function.parameters {
+            let param = self.new_vreg(scalar_type(param_type, function.position)?);
+            self.function.params.push(param);
+        }
+        for (i, (name, param_type)) in function.parameters.iter().enumerate() {
+            let param = self.function.params[i];
+            let slot = self.declare_local(name, param_type);
+            let addr = self.slot_addr(slot);
+            self.push(Inst::Store { addr, value: param });
+        }
+        self.statements(&function.body.stmts)?;
+        // falling off the end runs the function's deferred statements, a value
+        // returning function never gets here as the checker made sure it returns
+        if !self.terminated() {
+            self.lower_deferred(1)?;
+            self.terminate(Terminator::Jump(self.exit));
+        }
+        self.current = self.exit;
+        // whatever the program left in the STDOUT buffer is written before it
+        // exits, `_start` gets here too as it calls main
+        if function.name == "main" && self.program.signatures.contains_key("flush") {
+            let target = self.print_target();
+            self.runtime_call("flush", vec![target])?;
+        }
+        let value = match self.return_slot {
+            Some(slot) => {
+                let addr = self.slot_addr(slot);
+                let ty = self.function.return_type.expect("a return slot without a return type");
+                Some(self.load(ty, addr))
+            }
+            None => None
+        };
+        self.terminate(Terminator::Return(value));
+        Ok(self.function)
+    }
+
+    fn new_vreg(&mut self, ty: Ty) -> VReg {
+        self.function.vregs.push(ty);
+        VReg(self.function.vregs.len() - 1)
+    }
+
+    fn new_slot(&mut self, name: &'a str, size: usize, align: usize) -> SlotId {
+        self.function.slots.push(Slot { name, size, align });
+        SlotId(self.function.slots.len() - 1)
+    }
+
+    fn declare_local(&mut self, name: &'a str, var_type: &TypedExpression) -> SlotId {
+        let (size, align) = self.program.layouts.size_align(var_type);
+        let reused = self.free_slots.iter()
+            .position(|slot| self.function.slots[slot.0].size == size && self.function.slots[slot.0].align == align);
+        let slot = match reused {
+            Some(index) => {
+                let slot = self.free_slots.remove(index);
+                self.function.slots[slot.0].name = name;
+                slot
+            }
+            None => self.new_slot(name, size, align)
+        };
+        self.scopes.last_mut().expect("declaration outside of a scope").insert(name, slot);
+        self.scope_slots.last_mut().expect("declaration outside of a scope").push(slot);
+        slot
+    }
+
+    fn lookup(&self, name: &str, position: Position) -> Result<SlotId, CompilerError> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name)).copied().ok_or(CompilerError::InvalidLValue(position))
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+        self.scope_slots.push(vec![]);
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+        let released = self.scope_slots.pop().expect("popped the function scope");
+        self.free_slots.extend(released);
+    }
+
+    fn push(&mut self, inst: Inst<'a>) {
+        self.function.blocks[self.current.0].insts.push(inst);
+    }
+
+    fn terminated(&self) -> bool {
+        self.function.blocks[self.current.0].terminator.is_some()
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        self.function.blocks[self.current.0].terminator = Some(terminator);
+    }
+
+    fn define(&mut self, ty: Ty, inst: impl FnOnce(VReg) -> Inst<'a>) -> VReg {
+        let dst = self.new_vreg(ty);
+        self.push(inst(dst));
+        dst
+    }
+
+    fn slot_addr(&mut self, slot: SlotId) -> VReg {
+        self.define(Ty::Ptr, |dst| Inst::SlotAddr { dst, slot })
+    }
+
+    fn load(&mut self, ty: Ty, addr: VReg) -> VReg {
+        self.define(ty, |dst| Inst::Load { dst, addr })
+    }
+
+    fn offset(&mut self, base: VReg, bytes: usize) -> VReg {
+        if bytes == 0 {
+            return base;
+        }
+        self.define(Ty::Ptr, |dst| Inst::Offset { dst, base, bytes })
+    }
+
+    // nothing runs after a return, so the rest of a statement list is dropped
+    // once the current block has been terminated
+    fn statements(&mut self, stmts: &[Stmt<'a>]) -> Result<(), CompilerError> {
+        for stmt in stmts {
+            if self.terminated() {
+                break;
+            }
+            self.statement(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &Stmt<'a>) -> Result<(), CompilerError> {
+        match stmt {
+            Stmt::Let { name, var_type, value, .. } => self.lower_let(name, var_type, value),
+            Stmt::Expr(e) => self.expr(e).map(|_| ()),
+            Stmt::Assign { target, value } if matches!(target.ty, TypedExpression::Struct { .. }) => {
+                let fields = self.struct_fields(value)?;
+                let base = self.address(target)?;
+                self.store_fields(base, fields);
+                Ok(())
+            }
+            Stmt::Assign { target, value } => {
+                if value_type(&target.ty).is_none() {
+                    return Err(CompilerError::UnknownDataType(target.position));
+                }
+                let addr = self.address(target)?;
+                let value = self.value(value)?;
+                self.push(Inst::Store { addr, value });
+                Ok(())
+            }
+            Stmt::Block(block) => self.lower_block(block),
+            Stmt::Return { value, .. } => {
+                let value = value.as_ref().map(|value| self.value(value)).transpose()?;
+                self.lower_deferred(self.deferred.len())?;
+                if let (Some(slot), Some(value)) = (self.return_slot, value) {
+                    let addr = self.slot_addr(slot);
+                    self.push(Inst::Store { addr, value });
+                }
+                self.terminate(Terminator::Jump(self.exit));
+                Ok(())
+            }
+            Stmt::Defer { body, .. } => {
+                // later declarations can shadow what the body names, it has to keep
+                // reading the locals the checker resolved at the `defer`
+                let visible = self.scopes.iter().flatten().map(|(name, slot)| (*name, *slot)).collect();
+                self.deferred.last_mut().expect("defer outside of a block").push(((**body).clone(), visible));
+                Ok(())
+            }
+            Stmt::Asm(asm) => self.lower_asm(asm)
+        }
+    }
+
+    fn lower_let(&mut self, name: &'a str, var_type: &TypedExpression, value: &Expr<'a>) -> Result<(), CompilerError> {
+        // the initializer still sees whatever the new local shadows
+        if let TypedExpression::Struct { .. } = var_type {
+            let fields = self.struct_fields(value)?;
+            let slot = self.declare_local(name, var_type);
+            let base = self.slot_addr(slot);
+            self.store_fields(base, fields);
+            return Ok(());
+        }
+        let value = self.value(value)?;
+        let slot = self.declare_local(name, var_type);
+        let addr = self.slot_addr(slot);
+        self.push(Inst::Store { addr, value });
+        Ok(())
+    }
+
+    // the scalars making up the struct `value` with their offsets into it. all
+    // of them are read before any is stored, so a struct can be rebuilt from
+    // its own fields.
+    fn struct_fields(&mut self, value: &Expr<'a>) -> Result<Vec<(usize, VReg)>, CompilerError> {
+        let mut fields = vec![];
+        self.collect_fields(value, 0, &mut fields)?;
+        Ok(fields)
+    }
+
+    fn collect_fields(&mut self, value: &Expr<'a>, at: usize, out: &mut Vec<(usize, VReg)>) -> Result<(), CompilerError> {
+        match &value.kind {
+            ExprKind::StructLiteral { fields, .. } => {
+                for field in fields {
+                    if let TypedExpression::Struct { .. } = field.value.ty {
+                        self.collect_fields(&field.value, at + field.offset, out)?;
+                    } else {
+                        out.push((at + field.offset, self.value(&field.value)?));
+                    }
+                }
+                Ok(())
+            }
+            // a copy of a struct somewhere in memory
+            _ => {
+                let from = self.address(value)?;
+                self.copy_fields(from, &value.ty, 0, at, out, value.position)
+            }
+        }
+    }
+
+    fn copy_fields(&mut self, from: VReg, ty: &TypedExpression, inner: usize, at: usize, out: &mut Vec<(usize, VReg)>, position: Position) -> Result<(), CompilerError> {
+        let TypedExpression::Struct { name } = ty else {
+            return Err(CompilerError::UnknownDataType(position));
+        };
+        let program = self.program;
+        for field in &program.layouts.struct_def(name).fields {
+            let offset = inner + field.offset;
+            if let TypedExpression::Struct { .. } = field.field_type {
+                self.copy_fields(from, &field.field_type, offset, at, out, position)?;
+                continue;
+            }
+            let addr = self.offset(from, offset);
+            let value = self.load(scalar_type(&field.field_type, position)?, addr);
+            out.push((at + offset, value));
+        }
+        Ok(())
+    }
+
+    fn store_fields(&mut self, base: VReg, fields: Vec<(usize, VReg)>) {
+        for (offset, value) in fields {
+            let addr = self.offset(base, offset);
+            self.push(Inst::Store { addr, value });
+        }
+    }
+
+    fn lower_block(&mut self, block: &Block<'a>) -> Result<(), CompilerError> {
+        self.push_scope();
+        self.deferred.push(vec![]);
+        self.statements(&block.stmts)?;
+        // a return has already run everything deferred in here
+        if !self.terminated() {
+            self.lower_deferred(1)?;
+        }
+        self.deferred.pop();
+        self.pop_scope();
+        Ok(())
+    }
+
+    // lowers the deferred statements of the innermost `scopes` scopes, latest
+    // first, each against the locals that were visible at its `defer`. those
+    // all belong to scopes that are still open, so their slots are still theirs.
+    fn lower_deferred(&mut self, scopes: usize) -> Result<(), CompilerError> {
+        for scope in (self.deferred.len() - scopes..self.deferred.len()).rev() {
+            let pending: Vec<_> = self.deferred[scope].iter().rev().cloned().collect();
+            for (stmt, visible) in pending {
+                let open_scopes = std::mem::replace(&mut self.scopes, vec![visible]);
+                self.push_scope();
+                let lowered = self.statement(&stmt);
+                self.pop_scope();
+                self.scopes = open_scopes;
+                lowered?;
+            }
+        }
+        Ok(())
+    }
+
+    fn lower_asm(&mut self, asm: &Asm<'a>) -> Result<(), CompilerError> {
+        let mut inputs = vec![];
+        for operand in &asm.operands {
+            if matches!(operand.kind, AsmOperandKind::In | AsmOperandKind::InOut) {
+                let register = operand.register.expect("UNREACHABLE");
+                inputs.push((register, self.value(&operand.value)?));
+            }
+        }
+        let mut outputs = vec![];
+        let mut placeholders = vec![];
+        for operand in &asm.operands {
+            // inputs can be any expression and only get a name when they are a plain variable
+            let name = match operand.value.kind {
+                ExprKind::Local(name) => name,
+                _ if operand.kind == AsmOperandKind::In => continue,
+                _ => return Err(CompilerError::InvalidLValue(operand.value.position))
+            };
+            match operand.register {
+                Some(register) => {
+                    placeholders.push((name, Binding::Register(register)));
+                    if matches!(operand.kind, AsmOperandKind::Out | AsmOperandKind::InOut) {
+                        outputs.push((register, self.lookup(name, operand.value.position)?));
+                    }
+                }
+                None => placeholders.push((name, Binding::Slot(self.lookup(name, operand.value.position)?)))
+            }
+        }
+        self.push(Inst::Asm(InlineAsm {
+            template: asm.template.clone(),
+            inputs,
+            outputs,
+            placeholders,
+            clobbers: asm.clobbers.clone()
+        }));
+        Ok(())
+    }
+
+    fn value(&mut self, e: &Expr<'a>) -> Result<VReg, CompilerError> {
+        self.expr(e)?.ok_or(CompilerError::UnknownDataType(e.position))
+    }
+
+    // where an lvalue lives
+    fn address(&mut self, e: &Expr<'a>) -> Result<VReg, CompilerError> {
+        match &e.kind {
+            ExprKind::Local(name) => {
+                let slot = self.lookup(name, e.position)?;
+                Ok(self.slot_addr(slot))
+            }
+            ExprKind::Field { target, offset, .. } => {
+                let base = self.address(target)?;
+                Ok(self.offset(base, *offset))
+            }
+            ExprKind::Deref(pointer) => self.value(pointer),
+            _ => Err(CompilerError::InvalidLValue(e.position))
+        }
+    }
+
+    // None for expressions without a value, void calls and prints
+    fn expr(&mut self, e: &Expr<'a>) -> Result<Option<VReg>, CompilerError> {
+        if e.ty == TypedExpression::Void && !matches!(e.kind, ExprKind::Call { .. } | ExprKind::Print { .. }) {
+            return Ok(None);
+        }
+        let value = match &e.kind {
+            ExprKind::Integer(value) => {
+                let value = *value;
+                self.define(scalar_type(&e.ty, e.position)?, |dst| Inst::Const { dst, value })
+            }
+            ExprKind::Float(value) => {
+                let value = *value;
+                self.define(Ty::F64, |dst| Inst::Float { dst, value })
+            }
+            ExprKind::Bool(value) => {
+                let value = *value as i64;
+                self.define(Ty::I8, |dst| Inst::Const { dst, value })
+            }
+            ExprKind::String(text) => {
+                self.strings.push(text);
+                let index = self.strings.len() - 1;
+                self.define(Ty::Ptr, |dst| Inst::Str { dst, index })
+            }
+            ExprKind::Nil => self.define(Ty::Ptr, |dst| Inst::Const { dst, value: 0 }),
+            ExprKind::Function(name) => {
+                let name = *name;
+                self.define(Ty::Ptr, |dst| Inst::FuncAddr { dst, name })
+            }
+            ExprKind::Local(_) | ExprKind::Field { .. } => {
+                let ty = scalar_type(&e.ty, e.position)?;
+                let addr = self.address(e)?;
+                self.load(ty, addr)
+            }
+            ExprKind::Deref(pointer) => {
+                let ty = scalar_type(&e.ty, e.position)?;
+                let addr = self.value(pointer)?;
+                self.load(ty, addr)
+            }
+            ExprKind::AddressOf(target) => self.address(target)?,
+            ExprKind::Binary { operator, left, right } => self.binary(*operator, left, right, e.position)?,
+            ExprKind::Call { callee, arguments } => return self.call(callee, arguments, &e.ty),
+            ExprKind::Syscall(arguments) => {
+                let args = arguments.iter().map(|argument| self.value(argument)).collect::<Result<_, _>>()?;
+                self.define(Ty::I32, |dst| Inst::Syscall { dst, args })
+            }
+            ExprKind::Print { arguments, newline } => {
+                self.print(arguments, *newline, e.position)?;
+                return Ok(None);
+            }
+            ExprKind::StructLiteral { .. } => return Err(CompilerError::UnknownDataType(e.position)),
+            ExprKind::Error => panic!("error expressions never reach code generation")
+        };
+        Ok(Some(value))
+    }
+
+    fn binary(&mut self, operator: TokenType, left: &Expr<'a>, right: &Expr<'a>, position: Position) -> Result<VReg, CompilerError> {
+        let op = match operator {
+            TokenType::Plus => BinaryOp::Add,
+            TokenType::Minus => BinaryOp::Sub,
+            TokenType::Star => BinaryOp::Mul,
+            TokenType::Slash => BinaryOp::Div,
+            _ => return Err(CompilerError::UnsupportedOperator(position))
+        };
+        let mut lhs = self.value(left)?;
+        let mut rhs = self.value(right)?;
+        let (lhs_type, rhs_type) = (self.function.vregs[lhs.0], self.function.vregs[rhs.0]);
+        // an int meeting a float is converted, nothing else mixes
+        let operand_type = match (lhs_type, rhs_type) {
+            (Ty::I32, Ty::I32) => Ty::I32,
+            (Ty::I32 | Ty::F64, Ty::I32 | Ty::F64) => Ty::F64,
+            _ => return Err(CompilerError::UnsupportedOperator(position))
+        };
+        if lhs_type != operand_type {
+            lhs = self.define(Ty::F64, |dst| Inst::IntToFloat { dst, src: lhs });
+        }
+        if rhs_type != operand_type {
+            rhs = self.define(Ty::F64, |dst| Inst::IntToFloat { dst, src: rhs });
+        }
+        Ok(self.define(operand_type, |dst| Inst::Binary { dst, op, lhs, rhs }))
+    }
+
+    fn call(&mut self, callee: &Expr<'a>, arguments: &[Expr<'a>], ty: &TypedExpression) -> Result<Option<VReg>, CompilerError> {
+        let position = callee.position;
+        let TypedExpression::Function { args: ref parameters, variadic, .. } = callee.ty else {
+            return Err(CompilerError::UnexpectedStatement(position));
+        };
+        let fixed = parameters.len();
+        let callee = match callee.kind {
+            ExprKind::Function(name) => Callee::Direct(name),
+            _ => Callee::Indirect(self.value(callee)?)
+        };
+        let mut args = vec![];
+        for (i, argument) in arguments.iter().enumerate() {
+            let mut value = self.value(argument)?;
+            // C promotes bools passed through `...` to int
+            if i >= fixed && self.function.vregs[value.0] == Ty::I8 {
+                let src = value;
+                value = self.define(Ty::I32, |dst| Inst::BoolToInt { dst, src });
+            }
+            args.push(value);
+        }
+        let dst = match ty {
+            TypedExpression::Void => None,
+            t => Some(self.new_vreg(scalar_type(t, position)?))
+        };
+        self.push(Inst::Call { dst, callee, args, variadic });
+        Ok(dst)
+    }
+
+    // a call to one of the bundled standard library functions
+    fn runtime_call(&mut self, name: &'static str, args: Vec<VReg>) -> Result<(), CompilerError> {
+        // the bundled modules are only built for linux
+        let Some(TypedExpression::Function { return_type, .. }) = self.program.signatures.get(name) else {
+            return Err(CompilerError::UnsupportedTarget("print", None));
+        };
+        let dst = value_type(return_type).map(|ty| self.new_vreg(ty));
+        self.push(Inst::Call { dst, callee: Callee::Direct(name), args, variadic: false });
+        Ok(())
+    }
+
+    fn print_target(&mut self) -> VReg {
+        self.define(Ty::I32, |dst| Inst::Const { dst, value: 1 })
+    }
+
+    fn print_text(&mut self, text: &'a str) -> Result<(), CompilerError> {
+        let target = self.print_target();
+        self.strings.push(text);
+        let index = self.strings.len() - 1;
+        let text = self.define(Ty::Ptr, |dst| Inst::Str { dst, index });
+        self.runtime_call("write_string", vec![target, text])
+    }
+
+    fn print(&mut self, arguments: &[Expr<'a>], newline: bool, position: Position) -> Result<(), CompilerError> {
+        if !self.program.signatures.contains_key("flush") {
+            return Err(CompilerError::UnsupportedTarget("print", Some(position)));
+        }
+        for (i, argument) in arguments.iter().enumerate() {
+            if newline && i > 0 {
+                self.print_text(" ")?;
+            }
+            self.print_argument(argument, position)?;
+        }
+        if newline {
+            self.print_text("\n")?;
+        }
+        // print goes through the buffered STDOUT writer, flushing keeps the
+        // output ordered with anything written by libc or the kernel directly
+        let target = self.print_target();
+        self.runtime_call("flush", vec![target])
+    }
+
+    // picks the writer from the argument's static type, structs are written as
+    // `Name { field: value, ... }` field by field from their address, which is
+    // worked out once so the argument is only evaluated once.
+    fn print_argument(&mut self, argument: &Expr<'a>, position: Position) -> Result<(), CompilerError> {
+        if let TypedExpression::Struct { name } = &argument.ty {
+            let base = self.address(argument)?;
+            return self.print_struct(name, base, position);
+        }
+        let value = self.value(argument)?;
+        self.print_value(&argument.ty, value, position)
+    }
+
+    fn print_struct(&mut self, name: &str, base: VReg, position: Position) -> Result<(), CompilerError> {
+        let program = self.program;
+        let def = program.layouts.struct_def(name);
+        self.print_text(def.name)?;
+        self.print_text(" { ")?;
+        for (i, field) in def.fields.iter().enumerate() {
+            if i > 0 {
+                self.print_text(", ")?;
+            }
+            self.print_text(field.name)?;
+            self.print_text(": ")?;
+            let addr = self.offset(base, field.offset);
+            if let TypedExpression::Struct { name } = &field.field_type {
+                self.print_struct(name, addr, position)?;
+                continue;
+            }
+            let value = self.load(scalar_type(&field.field_type, position)?, addr);
+            self.print_value(&field.field_type, value, position)?;
+        }
+        self.print_text(" }")
+    }
+
+    fn print_value(&mut self, ty: &TypedExpression, value: VReg, position: Position) -> Result<(), CompilerError> {
+        let writer = match ty {
+            TypedExpression::Integer => "write_int",
+            TypedExpression::Float => "write_float",
+            TypedExpression::Bool => "write_bool",
+            TypedExpression::String => "write_string",
+            TypedExpression::Pointer(_) | TypedExpression::Function { .. } => "write_pointer",
+            _ => return Err(CompilerError::UnknownDataType(position))
+        };
+        let target = self.print_target();
+        let mut args = vec![target, value];
+        if writer == "write_float" {
+            args.push(self.define(Ty::I32, |dst| Inst::Const { dst, value: 6 }));
+        }
+        self.runtime_call(writer, args)
+    }
+}
@@ -0,0 +1,348 @@
+use std::fmt;
+
+pub mod lower;
+pub mod promote;
+pub mod verify;
+
+// the target independent form a checked program is lowered to before the
+// backend picks instructions for it. locals live in stack slots which are only
+// touched through explicit loads and stores, everything else is a virtual
+// register that is assigned exactly once. nerv has no branches yet, so a
+// function is its entry block and the exit block every `return` jumps to.
+#[derive(Debug, Clone, Default)]
+pub struct Module<'a> {
+    pub functions: Vec<Function<'a>>,
+    pub externs: Vec<&'a str>,
+    // top level `asm` lines, emitted verbatim
+    pub global_asm: Vec<&'a str>,
+    // string literals, `str` instructions refer to them by index
+    pub strings: Vec<&'a str>
+}
+
+#[derive(Debug, Clone)]
+pub struct Function<'a> {
+    pub name: &'a str,
+    pub params: Vec<VReg>,
+    // None for void functions
+    pub return_type: Option<Ty>,
+    pub slots: Vec<Slot<'a>>,
+    // the type of every virtual register, indexed by its number
+    pub vregs: Vec<Ty>,
+    pub blocks: Vec<BasicBlock<'a>>
+}
+
+// machine level types, what a virtual register holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Ty {
+    // bool
+    I8,
+    // int
+    I32,
+    F64,
+    // strings, pointers and functions
+    Ptr
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VReg(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SlotId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockId(pub usize);
+
+// stack memory for a local, a parameter or the return value
+#[derive(Debug, Clone)]
+pub struct Slot<'a> {
+    pub name: &'a str,
+    pub size: usize,
+    pub align: usize
+}
+
+#[derive(Debug, Clone)]
+pub struct BasicBlock<'a> {
+    pub insts: Vec<Inst<'a>>,
+    // None only while the block is being built
+    pub terminator: Option<Terminator>
+}
+
+#[derive(Debug, Clone)]
+pub enum Inst<'a> {
+    // integers, bools and nil
+    Const { dst: VReg, value: i64 },
+    Float { dst: VReg, value: f64 },
+    // the address of a string literal
+    Str { dst: VReg, index: usize },
+    FuncAddr { dst: VReg, name: &'a str },
+    SlotAddr { dst: VReg, slot: SlotId },
+    // a pointer `bytes` past `base`
+    Offset { dst: VReg, base: VReg, bytes: usize },
+    Load { dst: VReg, addr: VReg },
+    Store { addr: VReg, value: VReg },
+    Binary { dst: VReg, op: BinaryOp, lhs: VReg, rhs: VReg },
+    IntToFloat { dst: VReg, src: VReg },
+    // a bool zero extended to an int
+    BoolToInt { dst: VReg, src: VReg },
+    Call { dst: Option<VReg>, callee: Callee<'a>, args: Vec<VReg>, variadic: bool },
+    // the first argument is the syscall number
+    Syscall { dst: VReg, args: Vec<VReg> },
+    Asm(InlineAsm<'a>)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div
+}
+
+#[derive(Debug, Clone)]
+pub enum Callee<'a> {
+    Direct(&'a str),
+    Indirect(VReg)
+}
+
+// an `asm` block. inputs are loaded into their registers right before the
+// template and outputs are stored into their locals right after it.
+#[derive(Debug, Clone)]
+pub struct InlineAsm<'a> {
+    pub template: Vec<&'a str>,
+    pub inputs: Vec<(&'a str, VReg)>,
+    pub outputs: Vec<(&'a str, SlotId)>,
+    // what `{name}` in the template expands to
+    pub placeholders: Vec<(&'a str, Binding<'a>)>,
+    pub clobbers: Vec<&'a str>
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Binding<'a> {
+    Register(&'a str),
+    Slot(SlotId)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Terminator {
+    Jump(BlockId),
+    Return(Option<VReg>)
+}
+
+impl Ty {
+    pub fn size(self) -> usize {
+        match self {
+            Self::I8 => 1,
+            Self::I32 => 4,
+            Self::F64 | Self::Ptr => 8
+        }
+    }
+}
+
+impl Inst<'_> {
+    // the register the instruction assigns, if any
+    pub fn dst(&self) -> Option<VReg> {
+        match self {
+            Self::Const { dst, .. } | Self::Float { dst, .. } | Self::Str { dst, .. } | Self::FuncAddr { dst, .. }
+            | Self::SlotAddr { dst, .. } | Self::Offset { dst, .. } | Self::Load { dst, .. } | Self::Binary { dst, .. }
+            | Self::IntToFloat { dst, .. } | Self::BoolToInt { dst, .. } | Self::Syscall { dst, .. } => Some(*dst),
+            Self::Call { dst, .. } => *dst,
+            Self::Store { .. } | Self::Asm(_) => None
+        }
+    }
+
+    // the registers the instruction reads, in operand order
+    pub fn uses(&self) -> Vec<VReg> {
+        match self {
+            Self::Const { .. } | Self::Float { .. } | Self::Str { .. } | Self::FuncAddr { .. } | Self::SlotAddr { .. } => vec![],
+            Self::Offset { base, .. } => vec![*base],
+            Self::Load { addr, .. } => vec![*addr],
+            Self::Store { addr, value } => vec![*addr, *value],
+            Self::Binary { lhs, rhs, .. } => vec![*lhs, *rhs],
+            Self::IntToFloat { src, .. } | Self::BoolToInt { src, .. } => vec![*src],
+            Self::Call { callee, args, .. } => {
+                let mut uses = vec![];
+                if let Callee::Indirect(target) = callee {
+                    uses.push(*target);
+                }
+                uses.extend(args);
+                uses
+            }
+            Self::Syscall { args, .. } => args.clone(),
+            Self::Asm(asm) => asm.inputs.iter().map(|(_, value)| *value).collect()
+        }
+    }
+
+    // every register the instruction assigns or reads, for passes renaming them
+    pub fn vregs_mut(&mut self) -> Vec<&mut VReg> {
+        match self {
+            Self::Const { dst, .. } | Self::Float { dst, .. } | Self::Str { dst, .. } | Self::FuncAddr { dst, .. }
+            | Self::SlotAddr { dst, .. } => vec![dst],
+            Self::Offset { dst, base, .. } => vec![dst, base],
+            Self::Load { dst, addr } => vec![dst, addr],
+            Self::Store { addr, value } => vec![addr, value],
+            Self::Binary { dst, lhs, rhs, .. } => vec![dst, lhs, rhs],
+            Self::IntToFloat { dst, src } | Self::BoolToInt { dst, src } => vec![dst, src],
+            Self::Call { dst, callee, args, .. } => {
+                let mut vregs: Vec<&mut VReg> = dst.iter_mut().collect();
+                if let Callee::Indirect(target) = callee {
+                    vregs.push(target);
+                }
+                vregs.extend(args.iter_mut());
+                vregs
+            }
+            Self::Syscall { dst, args } => std::iter::once(dst).chain(args.iter_mut()).collect(),
+            Self::Asm(asm) => asm.inputs.iter_mut().map(|(_, value)| value).collect()
+        }
+    }
+}
+
+impl Terminator {
+    pub fn vregs_mut(&mut self) -> Vec<&mut VReg> {
+        match self {
+            Self::Return(Some(value)) => vec![value],
+            Self::Jump(_) | Self::Return(None) => vec![]
+        }
+    }
+
+    pub fn uses(&self) -> Vec<VReg> {
+        match self {
+            Self::Return(Some(value)) => vec![*value],
+            Self::Jump(_) | Self::Return(None) => vec![]
+        }
+    }
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::I8 => write!(f, "i8"),
+            Self::I32 => write!(f, "i32"),
+            Self::F64 => write!(f, "f64"),
+            Self::Ptr => write!(f, "ptr")
+        }
+    }
+}
+
+impl fmt::Display for VReg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for SlotId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "${}", self.0)
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Add => write!(f, "add"),
+            Self::Sub => write!(f, "sub"),
+            Self::Mul => write!(f, "mul"),
+            Self::Div => write!(f, "div")
+        }
+    }
+}
+
+fn join<T: fmt::Display>(items: &[T]) -> String {
+    items.iter().map(|item| item.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+impl Function<'_> {
+    fn fmt_inst(&self, f: &mut fmt::Formatter<'_>, inst: &Inst, strings: &[&str]) -> fmt::Result {
+        if let Some(dst) = inst.dst() {
+            write!(f, "{} = ", dst)?;
+        }
+        let ty = |vreg: &VReg| self.vregs[vreg.0];
+        match inst {
+            Inst::Const { dst, value } => write!(f, "const {} {}", ty(dst), value),
+            Inst::Float { value, .. } => write!(f, "const f64 {:?}", value),
+            Inst::Str { index, .. } => write!(f, "str {:?}", strings[*index]),
+            Inst::FuncAddr { name, .. } => write!(f, "func @{}", name),
+            Inst::SlotAddr { slot, .. } => write!(f, "addr {}", slot),
+            Inst::Offset { base, bytes, .. } => write!(f, "offset {}, {}", base, bytes),
+            Inst::Load { dst, addr } => write!(f, "load {} {}", ty(dst), addr),
+            Inst::Store { addr, value } => write!(f, "store {} {}, {}", ty(value), value, addr),
+            Inst::Binary { op, lhs, rhs, .. } => write!(f, "{} {} {}, {}", op, ty(lhs), lhs, rhs),
+            Inst::IntToFloat { src, .. } => write!(f, "itof {}", src),
+            Inst::BoolToInt { src, .. } => write!(f, "zext {}", src),
+            Inst::Call { dst, callee, args, variadic } => {
+                let return_type = dst.map_or("void".to_string(), |dst| ty(&dst).to_string());
+                let callee = match callee {
+                    Callee::Direct(name) => format!("@{}", name),
+                    Callee::Indirect(target) => target.to_string()
+                };
+                write!(f, "call {} {}({}{})", return_type, callee, join(args), if *variadic { ", ..." } else { "" })
+            }
+            Inst::Syscall { args, .. } => write!(f, "syscall({})", join(args)),
+            Inst::Asm(asm) => {
+                write!(f, "asm {:?}", asm.template)?;
+                for (register, value) in &asm.inputs {
+                    write!(f, ", in({}) {}", register, value)?;
+                }
+                for (register, slot) in &asm.outputs {
+                    write!(f, ", out({}) {}", register, slot)?;
+                }
+                for (name, binding) in &asm.placeholders {
+                    match binding {
+                        Binding::Register(register) => write!(f, ", {{{}}} = {}", name, register)?,
+                        Binding::Slot(slot) => write!(f, ", {{{}}} = {}", name, slot)?
+                    }
+                }
+                if !asm.clobbers.is_empty() {
+                    write!(f, ", clobber({})", asm.clobbers.join(", "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn fmt_with(&self, f: &mut fmt::Formatter<'_>, strings: &[&str]) -> fmt::Result {
+        let params: Vec<String> = self.params.iter().map(|param| format!("{} {}", self.vregs[param.0], param)).collect();
+        let return_type = self.return_type.map_or("void".to_string(), |ty| ty.to_string());
+        writeln!(f, "fn {}({}) -> {} {{", self.name, params.join(", "), return_type)?;
+        for (i, slot) in self.slots.iter().enumerate() {
+            writeln!(f, "    {} = slot {}, align {} ; {}", SlotId(i), slot.size, slot.align, slot.name)?;
+        }
+        for (i, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "{}:", BlockId(i))?;
+            for inst in &block.insts {
+                write!(f, "    ")?;
+                self.fmt_inst(f, inst, strings)?;
+                writeln!(f)?;
+            }
+            match block.terminator {
+                Some(Terminator::Jump(target)) => writeln!(f, "    jump {}", target)?,
+                Some(Terminator::Return(Some(value))) => writeln!(f, "    ret {}", value)?,
+                Some(Terminator::Return(None)) => writeln!(f, "    ret")?,
+                None => writeln!(f, "    <no terminator>")?
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
+
+// the `--emit=ir` dump
+impl fmt::Display for Module<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for name in &self.externs {
+            writeln!(f, "extern @{}", name)?;
+        }
+        for function in &self.functions {
+            writeln!(f)?;
+            function.fmt_with(f, &self.strings)?;
+        }
+        for line in &self.global_asm {
+            writeln!(f, "asm {:?}", line)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{Binding, Function, Inst, SlotId, Terminator, Ty, VReg};
+
+// keeps locals in registers. a slot whose address only ever feeds loads and
+// stores of a single type never needs to be in memory, every load from it is
+// replaced by the value last stored into it and the slot goes away. locals
+// that have their address taken or are bound to an `asm` block stay in their
+// slots. registers are renumbered afterwards so the dump has no gaps.
+pub fn promote_locals(function: &mut Function) {
+    let mut candidates = promotable_slots(function);
+    // a load can only be forwarded when the block has a single way in, a slot
+    // with a load that can't be forwarded is left in memory
+    let replacements = loop {
+        match forward_loads(function, &candidates) {
+            Ok(replacements) => break replacements,
+            Err(slot) => {
+                candidates.remove(&slot);
+            }
+        }
+    };
+    if candidates.is_empty() {
+        return;
+    }
+
+    let promoted_addresses: HashSet<VReg> = function.blocks.iter()
+        .flat_map(|block| &block.insts)
+        .filter_map(|inst| match inst {
+            Inst::SlotAddr { dst, slot } if candidates.contains(slot) => Some(*dst),
+            _ => None
+        })
+        .collect();
+    for block in &mut function.blocks {
+        block.insts.retain(|inst| match inst {
+            Inst::SlotAddr { dst, .. } | Inst::Load { addr: dst, .. } | Inst::Store { addr: dst, .. } => {
+                !promoted_addresses.contains(dst)
+            }
+            _ => true
+        });
+        for inst in &mut block.insts {
+            for vreg in inst.vregs_mut() {
+                *vreg = *replacements.get(vreg).unwrap_or(vreg);
+            }
+        }
+        if let Some(terminator) = &mut block.terminator {
+            for vreg in terminator.vregs_mut() {
+                *vreg = *replacements.get(vreg).unwrap_or(vreg);
+            }
+        }
+    }
+    remove_slots(function, &candidates);
+    renumber(function);
+}
+
+// slots only touched through loads and stores of one type
+fn promotable_slots(function: &Function) -> HashSet<SlotId> {
+    let mut address_of = HashMap::new();
+    for inst in function.blocks.iter().flat_map(|block| &block.insts) {
+        if let Inst::SlotAddr { dst, slot } = inst {
+            address_of.insert(*dst, *slot);
+        }
+    }
+    let mut escaped = HashSet::new();
+    let mut types: HashMap<SlotId, HashSet<Ty>> = HashMap::new();
+    for inst in function.blocks.iter().flat_map(|block| &block.insts) {
+        match inst {
+            Inst::Load { dst, addr } if address_of.contains_key(addr) => {
+                types.entry(address_of[addr]).or_default().insert(function.vregs[dst.0]);
+            }
+            Inst::Store { addr, value } if address_of.contains_key(addr) => {
+                types.entry(address_of[addr]).or_default().insert(function.vregs[value.0]);
+                if let Some(slot) = address_of.get(value) {
+                    escaped.insert(*slot);
+                }
+            }
+            Inst::Asm(asm) => {
+                escaped.extend(asm.outputs.iter().map(|(_, slot)| *slot));
+                escaped.extend(asm.placeholders.iter().filter_map(|(_, binding)| match binding {
+                    Binding::Slot(slot) => Some(*slot),
+                    Binding::Register(_) => None
+                }));
+                escaped.extend(inst.uses().iter().filter_map(|used| address_of.get(used)));
+            }
+            _ => escaped.extend(inst.uses().iter().filter_map(|used| address_of.get(used)))
+        }
+    }
+    for block in &function.blocks {
+        if let Some(terminator) = &block.terminator {
+            escaped.extend(terminator.uses().iter().filter_map(|used| address_of.get(used)));
+        }
+    }
+    (0..function.slots.len()).map(SlotId)
+        .filter(|slot| !escaped.contains(slot) && types.get(slot).is_none_or(|types| types.len() == 1))
+        .collect()
+}
+
+// what every load from a candidate slot is replaced by, or a slot with a load
+// that doesn't see a store
+fn forward_loads(function: &Function, candidates: &HashSet<SlotId>) -> Result<HashMap<VReg, VReg>, SlotId> {
+    let mut predecessors = vec![vec![]; function.blocks.len()];
+    for (i, block) in function.blocks.iter().enumerate() {
+        if let Some(Terminator::Jump(target)) = block.terminator {
+            predecessors[target.0].push(i);
+        }
+    }
+    let mut address_of = HashMap::new();
+    let mut replacements = HashMap::new();
+    let mut stored_at_exit: Vec<Option<HashMap<SlotId, VReg>>> = vec![None; function.blocks.len()];
+    for (i, block) in function.blocks.iter().enumerate() {
+        let mut stored = match predecessors[i].as_slice() {
+            [single] => stored_at_exit[*single].clone().unwrap_or_default(),
+            _ => HashMap::new()
+        };
+        for inst in &block.insts {
+            match inst {
+                Inst::SlotAddr { dst, slot } if candidates.contains(slot) => {
+                    address_of.insert(*dst, *slot);
+                }
+                Inst::Store { addr, value } if address_of.contains_key(addr) => {
+                    let value = *replacements.get(value).unwrap_or(value);
+                    stored.insert(address_of[addr], value);
+                }
+                Inst::Load { dst, addr } if address_of.contains_key(addr) => {
+                    let slot = address_of[addr];
+                    let value = stored.get(&slot).ok_or(slot)?;
+                    replacements.insert(*dst, *value);
+                }
+                _ => {}
+            }
+        }
+        stored_at_exit[i] = Some(stored);
+    }
+    Ok(replacements)
+}
+
+fn remove_slots(function: &mut Function, removed: &HashSet<SlotId>) {
+    let mut renumbered = HashMap::new();
+    let mut slots = vec![];
+    for (i, slot) in function.slots.drain(..).enumerate() {
+        if !removed.contains(&SlotId(i)) {
+            renumbered.insert(SlotId(i), SlotId(slots.len()));
+            slots.push(slot);
+        }
+    }
+    function.slots = slots;
+    for inst in function.blocks.iter_mut().flat_map(|block| &mut block.insts) {
+        match inst {
+            Inst::SlotAddr { slot, .. } => *slot = renumbered[slot],
+            Inst::Asm(asm) => {
+                for (_, slot) in &mut asm.outputs {
+                    *slot = renumbered[slot];
+                }
+                for (_, binding) in &mut asm.placeholders {
+                    if let Binding::Slot(slot) = binding {
+                        *slot = renumbered[slot];
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// parameters first, then every other register in the order it is assigned
+fn renumber(function: &mut Function) {
+    let mut renumbered = HashMap::new();
+    let mut vregs = vec![];
+    let definitions = function.params.iter().copied()
+        .chain(function.blocks.iter().flat_map(|block| block.insts.iter().filter_map(Inst::dst)));
+    for vreg in definitions {
+        renumbered.insert(vreg, VReg(vregs.len()));
+        vregs.push(function.vregs[vreg.0]);
+    }
+    function.vregs = vregs;
+    for param in &mut function.params {
+        *param = renumbered[param];
+    }
+    for block in &mut function.blocks {
+        for inst in &mut block.insts {
+            for vreg in inst.vregs_mut() {
+                *vreg = renumbered[vreg];
+            }
+        }
+        if let Some(terminator) = &mut block.terminator {
+            for vreg in terminator.vregs_mut() {
+                *vreg = renumbered[vreg];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{BasicBlock, BlockId, Callee, Slot};
+
+    fn function(vregs: Vec<Ty>, blocks: Vec<BasicBlock<'static>>) -> Function<'static> {
+        Function {
+            name: "f",
+            params: vec![],
+            return_type: Some(Ty::I32),
+            slots: vec![Slot { name: "x", size: 4, align: 4 }],
+            vregs,
+            blocks
+        }
+    }
+
+    fn block(insts: Vec<Inst<'static>>, terminator: Terminator) -> BasicBlock<'static> {
+        BasicBlock { insts, terminator: Some(terminator) }
+    }
+
+    #[test]
+    fn loads_are_replaced_by_the_stored_value() {
+        let mut f = function(vec![Ty::I32, Ty::Ptr, Ty::Ptr, Ty::I32], vec![
+            block(vec![
+                Inst::Const { dst: VReg(0), value: 5 },
+                Inst::SlotAddr { dst: VReg(1), slot: SlotId(0) },
+                Inst::Store { addr: VReg(1), value: VReg(0) },
+            ], Terminator::Jump(BlockId(1))),
+            block(vec![
+                Inst::SlotAddr { dst: VReg(2), slot: SlotId(0) },
+                Inst::Load { dst: VReg(3), addr: VReg(2) },
+            ], Terminator::Return(Some(VReg(3)))),
+        ]);
+        promote_locals(&mut f);
+        assert!(f.slots.is_empty());
+        assert_eq!(f.vregs, [Ty::I32]);
+        assert!(matches!(f.blocks[0].insts[..], [Inst::Const { dst: VReg(0), value: 5 }]));
+        assert!(f.blocks[1].insts.is_empty());
+        assert!(matches!(f.blocks[1].terminator, Some(Terminator::Return(Some(VReg(0))))));
+    }
+
+    #[test]
+    fn a_load_after_a_join_keeps_the_slot() {
+        let mut f = function(vec![Ty::I32, Ty::Ptr, Ty::Ptr, Ty::I32], vec![
+            block(vec![
+                Inst::Const { dst: VReg(0), value: 5 },
+                Inst::SlotAddr { dst: VReg(1), slot: SlotId(0) },
+                Inst::Store { addr: VReg(1), value: VReg(0) },
+            ], Terminator::Jump(BlockId(2))),
+            block(vec![], Terminator::Jump(BlockId(2))),
+            block(vec![
+                Inst::SlotAddr { dst: VReg(2), slot: SlotId(0) },
+                Inst::Load { dst: VReg(3), addr: VReg(2) },
+            ], Terminator::Return(Some(VReg(3)))),
+        ]);
+        promote_locals(&mut f);
+        assert_eq!(f.slots.len(), 1);
+        assert_eq!(f.vregs.len(), 4);
+    }
+
+    #[test]
+    fn a_slot_whose_address_escapes_stays() {
+        let mut f = function(vec![Ty::I32, Ty::Ptr, Ty::I32], vec![block(vec![
+            Inst::Const { dst: VReg(0), value: 5 },
+            Inst::SlotAddr { dst: VReg(1), slot: SlotId(0) },
+            Inst::Store { addr: VReg(1), value: VReg(0) },
+            Inst::Call { dst: Some(VReg(2)), callee: Callee::Direct("g"), args: vec![VReg(1)], variadic: false },
+        ], Terminator::Return(Some(VReg(2))))]);
+        promote_locals(&mut f);
+        assert_eq!(f.slots.len(), 1);
+        assert_eq!(f.blocks[0].insts.len(), 4);
+    }
+}
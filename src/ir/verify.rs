@@ -0,0 +1,332 @@
+use std::collections::HashSet;
+
+use super::{Binding, BlockId, Callee, Function, Inst, Module, Terminator, Ty, VReg};
+
+// checks the invariants the backend relies on: every block is terminated and
+// only jumps to blocks that exist, every register is assigned exactly once and
+// before any use on every path, slots exist and operand types line up. a
+// module failing this is a bug in the lowering, not in the program.
+pub fn verify(module: &Module) -> Result<(), Vec<String>> {
+    let mut errors = vec![];
+    for function in &module.functions {
+        Verifier { function, strings: module.strings.len(), errors: &mut errors }.verify();
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+struct Verifier<'f, 'a> {
+    function: &'f Function<'a>,
+    strings: usize,
+    errors: &'f mut Vec<String>
+}
+
+impl Verifier<'_, '_> {
+    fn error(&mut self, message: String) {
+        self.errors.push(format!("in `{}`: {}", self.function.name, message));
+    }
+
+    fn verify(&mut self) {
+        if self.function.blocks.is_empty() {
+            self.error("there is no entry block".to_string());
+            return;
+        }
+        self.check_single_assignment();
+        let available = self.available_on_entry();
+        for (i, block) in self.function.blocks.iter().enumerate() {
+            let mut defined = available[i].clone();
+            for inst in &block.insts {
+                for used in inst.uses() {
+                    self.check_use(used, &defined, i);
+                }
+                self.check_inst(inst);
+                if let Some(dst) = inst.dst() {
+                    defined.insert(dst);
+                }
+            }
+            match block.terminator {
+                None => self.error(format!("{} has no terminator", BlockId(i))),
+                Some(terminator) => {
+                    for used in terminator.uses() {
+                        self.check_use(used, &defined, i);
+                    }
+                    self.check_terminator(terminator, i);
+                }
+            }
+        }
+    }
+
+    fn ty(&self, vreg: VReg) -> Option<Ty> {
+        self.function.vregs.get(vreg.0).copied()
+    }
+
+    fn check_single_assignment(&mut self) {
+        let mut assigned = HashSet::new();
+        let definitions = self.function.params.iter().copied()
+            .chain(self.function.blocks.iter().flat_map(|block| block.insts.iter().filter_map(Inst::dst)));
+        let mut twice = vec![];
+        for dst in definitions {
+            if self.ty(dst).is_none() {
+                twice.push(format!("{} has no type", dst));
+            } else if !assigned.insert(dst) {
+                twice.push(format!("{} is assigned more than once", dst));
+            }
+        }
+        for message in twice {
+            self.error(message);
+        }
+    }
+
+    // registers assigned on every path into each block, parameters are
+    // available in the entry block and blocks nothing jumps to see nothing
+    fn available_on_entry(&self) -> Vec<HashSet<VReg>> {
+        let blocks = &self.function.blocks;
+        let mut predecessors = vec![vec![]; blocks.len()];
+        for (i, block) in blocks.iter().enumerate() {
+            if let Some(Terminator::Jump(target)) = block.terminator
+                && let Some(list) = predecessors.get_mut(target.0) {
+                list.push(i);
+            }
+        }
+        let every: HashSet<VReg> = (0..self.function.vregs.len()).map(VReg).collect();
+        let mut available_out = vec![every; blocks.len()];
+        let mut available_in = vec![HashSet::new(); blocks.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (i, block) in blocks.iter().enumerate() {
+                let entry: HashSet<VReg> = if i == 0 {
+                    self.function.params.iter().copied().collect()
+                } else if predecessors[i].is_empty() {
+                    HashSet::new()
+                } else {
+                    let mut sets = predecessors[i].iter().map(|p| &available_out[*p]);
+                    let first = sets.next().expect("checked above").clone();
+                    sets.fold(first, |acc, set| acc.intersection(set).copied().collect())
+                };
+                let mut out = entry.clone();
+                out.extend(block.insts.iter().filter_map(Inst::dst));
+                available_in[i] = entry;
+                if out != available_out[i] {
+                    available_out[i] = out;
+                    changed = true;
+                }
+            }
+        }
+        available_in
+    }
+
+    fn check_use(&mut self, used: VReg, defined: &HashSet<VReg>, block: usize) {
+        if !defined.contains(&used) {
+            self.error(format!("{} is used in {} before it is assigned", used, BlockId(block)));
+        }
+    }
+
+    fn expect(&mut self, vreg: VReg, expected: Ty, role: &str) {
+        if let Some(ty) = self.ty(vreg) && ty != expected {
+            self.error(format!("{} of type {} used as {}, expected {}", vreg, ty, role, expected));
+        }
+    }
+
+    fn check_slot(&mut self, slot: super::SlotId) {
+        if slot.0 >= self.function.slots.len() {
+            self.error(format!("{} does not exist", slot));
+        }
+    }
+
+    fn check_inst(&mut self, inst: &Inst) {
+        match inst {
+            Inst::Const { dst, value } => {
+                match self.ty(*dst) {
+                    Some(Ty::F64) => self.error(format!("{} is a float, use a float constant", dst)),
+                    Some(Ty::I32) if i32::try_from(*value).is_err() => self.error(format!("{} does not fit the i32 {}", value, dst)),
+                    _ => {}
+                }
+            }
+            Inst::Float { dst, .. } => self.expect(*dst, Ty::F64, "a float constant"),
+            Inst::Str { dst, index } => {
+                self.expect(*dst, Ty::Ptr, "a string");
+                if *index >= self.strings {
+                    self.error(format!("string {} does not exist", index));
+                }
+            }
+            Inst::FuncAddr { dst, .. } => self.expect(*dst, Ty::Ptr, "a function address"),
+            Inst::SlotAddr { dst, slot } => {
+                self.expect(*dst, Ty::Ptr, "a slot address");
+                self.check_slot(*slot);
+            }
+            Inst::Offset { dst, base, .. } => {
+                self.expect(*dst, Ty::Ptr, "an offset pointer");
+                self.expect(*base, Ty::Ptr, "an offset base");
+            }
+            Inst::Load { addr, .. } => self.expect(*addr, Ty::Ptr, "a load address"),
+            Inst::Store { addr, .. } => self.expect(*addr, Ty::Ptr, "a store address"),
+            Inst::Binary { dst, op, lhs, rhs } => {
+                let (Some(lhs_type), Some(rhs_type)) = (self.ty(*lhs), self.ty(*rhs)) else {
+                    return;
+                };
+                if lhs_type != rhs_type {
+                    self.error(format!("`{}` of {} and {}", op, lhs_type, rhs_type));
+                }
+                if !matches!(lhs_type, Ty::I32 | Ty::F64) {
+                    self.error(format!("`{}` of {}", op, lhs_type));
+                }
+                self.expect(*dst, lhs_type, "an arithmetic result");
+            }
+            Inst::IntToFloat { dst, src } => {
+                self.expect(*src, Ty::I32, "a conversion source");
+                self.expect(*dst, Ty::F64, "a conversion result");
+            }
+            Inst::BoolToInt { dst, src } => {
+                self.expect(*src, Ty::I8, "a conversion source");
+                self.expect(*dst, Ty::I32, "a conversion result");
+            }
+            Inst::Call { callee, .. } => {
+                if let Callee::Indirect(target) = callee {
+                    self.expect(*target, Ty::Ptr, "a call target");
+                }
+            }
+            Inst::Syscall { args, .. } => {
+                if args.is_empty() || args.len() > 7 {
+                    self.error(format!("a syscall takes a number and up to 6 arguments, found {}", args.len()));
+                }
+            }
+            Inst::Asm(asm) => {
+                for (_, slot) in &asm.outputs {
+                    self.check_slot(*slot);
+                }
+                for (_, binding) in &asm.placeholders {
+                    if let Binding::Slot(slot) = binding {
+                        self.check_slot(*slot);
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_terminator(&mut self, terminator: Terminator, block: usize) {
+        match (terminator, self.function.return_type) {
+            (Terminator::Jump(target), _) => {
+                if target.0 >= self.function.blocks.len() {
+                    self.error(format!("{} jumps to {} which does not exist", BlockId(block), target));
+                }
+            }
+            (Terminator::Return(None), None) => {}
+            (Terminator::Return(Some(value)), Some(ty)) => self.expect(value, ty, "the return value"),
+            (Terminator::Return(Some(value)), None) => self.error(format!("{} returns {} from a void function", BlockId(block), value)),
+            (Terminator::Return(None), Some(ty)) => self.error(format!("{} returns nothing, expected {}", BlockId(block), ty))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::BinaryOp;
+    use crate::ir::{BasicBlock, Slot, SlotId};
+
+    fn function(vregs: Vec<Ty>, return_type: Option<Ty>, blocks: Vec<BasicBlock<'static>>) -> Module<'static> {
+        Module {
+            functions: vec![Function {
+                name: "f",
+                params: vec![],
+                return_type,
+                slots: vec![Slot { name: "x", size: 4, align: 4 }],
+                vregs,
+                blocks
+            }],
+            ..Module::default()
+        }
+    }
+
+    fn block(insts: Vec<Inst<'static>>, terminator: Option<Terminator>) -> BasicBlock<'static> {
+        BasicBlock { insts, terminator }
+    }
+
+    fn errors(module: &Module) -> Vec<String> {
+        verify(module).err().unwrap_or_default()
+    }
+
+    #[test]
+    fn accepts_loads_and_stores_through_a_slot() {
+        let module = function(vec![Ty::I32, Ty::Ptr, Ty::I32], Some(Ty::I32), vec![
+            block(vec![
+                Inst::Const { dst: VReg(0), value: 5 },
+                Inst::SlotAddr { dst: VReg(1), slot: SlotId(0) },
+                Inst::Store { addr: VReg(1), value: VReg(0) },
+            ], Some(Terminator::Jump(BlockId(1)))),
+            block(vec![Inst::Load { dst: VReg(2), addr: VReg(1) }], Some(Terminator::Return(Some(VReg(2))))),
+        ]);
+        assert_eq!(verify(&module), Ok(()));
+    }
+
+    #[test]
+    fn registers_are_assigned_once() {
+        let module = function(vec![Ty::I32], Some(Ty::I32), vec![block(vec![
+            Inst::Const { dst: VReg(0), value: 1 },
+            Inst::Const { dst: VReg(0), value: 2 },
+        ], Some(Terminator::Return(Some(VReg(0)))))]);
+        assert_eq!(errors(&module), vec!["in `f`: %0 is assigned more than once"]);
+    }
+
+    #[test]
+    fn registers_are_assigned_before_use() {
+        let module = function(vec![Ty::I32, Ty::I32], Some(Ty::I32), vec![block(vec![
+            Inst::Binary { dst: VReg(0), op: BinaryOp::Add, lhs: VReg(1), rhs: VReg(1) },
+            Inst::Const { dst: VReg(1), value: 2 },
+        ], Some(Terminator::Return(Some(VReg(0)))))]);
+        assert_eq!(errors(&module), vec![
+            "in `f`: %1 is used in bb0 before it is assigned",
+            "in `f`: %1 is used in bb0 before it is assigned",
+        ]);
+    }
+
+    #[test]
+    fn unreachable_blocks_see_nothing_from_other_blocks() {
+        let module = function(vec![Ty::I32], Some(Ty::I32), vec![
+            block(vec![Inst::Const { dst: VReg(0), value: 1 }], Some(Terminator::Return(Some(VReg(0))))),
+            block(vec![], Some(Terminator::Return(Some(VReg(0))))),
+        ]);
+        assert_eq!(errors(&module), vec!["in `f`: %0 is used in bb1 before it is assigned"]);
+    }
+
+    #[test]
+    fn operand_types_have_to_agree() {
+        let module = function(vec![Ty::I32, Ty::F64, Ty::I32], Some(Ty::I32), vec![block(vec![
+            Inst::Const { dst: VReg(0), value: 1 },
+            Inst::Float { dst: VReg(1), value: 1.5 },
+            Inst::Binary { dst: VReg(2), op: BinaryOp::Add, lhs: VReg(0), rhs: VReg(1) },
+            Inst::Load { dst: VReg(2), addr: VReg(0) },
+        ], Some(Terminator::Return(Some(VReg(2)))))]);
+        let errors = errors(&module);
+        assert!(errors.contains(&"in `f`: `add` of i32 and f64".to_string()), "{errors:?}");
+        assert!(errors.contains(&"in `f`: %0 of type i32 used as a load address, expected ptr".to_string()), "{errors:?}");
+    }
+
+    #[test]
+    fn integer_constants_fit_their_type() {
+        let module = function(vec![Ty::I32, Ty::I32, Ty::Ptr], Some(Ty::I32), vec![block(vec![
+            Inst::Const { dst: VReg(0), value: i32::MIN as i64 },
+            Inst::Const { dst: VReg(1), value: 9_000_000_000 },
+            Inst::Const { dst: VReg(2), value: 9_000_000_000 },
+        ], Some(Terminator::Return(Some(VReg(0)))))]);
+        assert_eq!(errors(&module), vec!["in `f`: 9000000000 does not fit the i32 %1"]);
+    }
+
+    #[test]
+    fn blocks_end_in_a_terminator_matching_the_function() {
+        let module = function(vec![], Some(Ty::I32), vec![
+            block(vec![], Some(Terminator::Jump(BlockId(3)))),
+            block(vec![], None),
+            block(vec![], Some(Terminator::Return(None))),
+        ]);
+        assert_eq!(errors(&module), vec![
+            "in `f`: bb0 jumps to bb3 which does not exist",
+            "in `f`: bb1 has no terminator",
+            "in `f`: bb2 returns nothing, expected i32",
+        ]);
+    }
+}
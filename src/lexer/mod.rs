@@ -1,14 +1,19 @@
 use crate::shared::{
-    errors::LexerError, meta::{AnyMetadata, NumberType}, positions::Position, tokens::{
+    arena::StringArena, errors::{codes, Diagnostic, LexerError}, meta::{AnyMetadata, NumberType}, positions::{Position, Span}, tokens::{
         Token,
         TokenType
     }
 };
 
+// walks the source once, `position` is a byte offset into it while
+// `current_line`/`current_column` are 1-based and count characters, so a
+// token's `span` slices the source and its `position` is what an editor shows.
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
 pub struct Lexer<'a> {
     source_code: &'a str,
+    // where string literals with escapes in them are decoded into
+    strings: &'a StringArena,
     position: usize,
     current_line: usize,
     current_column: usize,
@@ -17,315 +22,465 @@ pub struct Lexer<'a> {
 
 #[allow(dead_code)]
 impl<'a> Lexer<'a> {
-    pub fn new(source_code: &'a str) -> Self {
+    pub fn new(source_code: &'a str, strings: &'a StringArena) -> Self {
         Self {
             source_code,
+            strings,
             position: 0,
             current_line: 1,
-            current_column: 0
+            current_column: 1
         }
     }
 
     fn can_move(&self) -> bool {
-        if self.position < self.source_code.len() {
-            return true;
-        }
-        false
+        self.position < self.source_code.len()
     }
 
-    fn advance(&mut self) -> Result<(), LexerError> {
-        if self.can_move() {
-            self.position += 1;
-            self.current_column += 1;
-            return Ok(())
-        }
-        Err(LexerError::CalledNextAfterExhaustion)
+    fn here(&self) -> Position {
+        Position::new(self.current_line, self.current_column)
     }
 
-    fn generate_operator(&mut self, lexeme_start: usize, tt: TokenType) -> Option<Token<AnyMetadata<'a>>> {
-        self.advance().ok()?;
-        let lexeme_ending = self.position;
-        Some(Token {
-            token_type: tt,
-            position: Position::new(self.current_line, self.current_column),
-            lexeme: (lexeme_start, lexeme_ending),
-            meta_data: AnyMetadata::None
-        })
+    fn rest(&self) -> &'a str {
+        &self.source_code[self.position..]
     }
 
     fn get_current_character(&self) -> Result<char, LexerError> {
-        self.source_code.chars().nth(self.position).ok_or(LexerError::IllegalCharacterAccess)
+        self.rest().chars().next().ok_or(LexerError::IllegalCharacterAccess)
     }
 
-    fn generate_string(&mut self, lexeme_start: usize) -> Result<Token<AnyMetadata<'a>>, LexerError> {
-        self.advance().map_err(|_| LexerError::UnexpectedEof)?;
-        let starting_column = self.current_column;
-        while self.can_move() && self.get_current_character().map_err(|_| LexerError::UnexpectedEof)? != '"' {
-            self.advance().map_err(|_| LexerError::UnexpectedEof)?;
+    fn advance(&mut self) -> Result<(), LexerError> {
+        let ch = self.get_current_character().map_err(|_| LexerError::CalledNextAfterExhaustion)?;
+        self.position += ch.len_utf8();
+        if ch == '\n' {
+            self.current_line += 1;
+            self.current_column = 1;
+        } else {
+            self.current_column += 1;
         }
-        self.advance().map_err(|_| LexerError::UnexpectedEof)?;
-        let lexeme_end = self.position;
-        Ok(Token {
-            token_type: TokenType::String,
-            position: Position::new(self.current_line, starting_column),
-            lexeme: (lexeme_start, lexeme_end),
-            meta_data: AnyMetadata::String {
-                value: &self.source_code[lexeme_start+1..lexeme_end-1]
-            }
-        })
-    }
-
-    fn get_keyword_type(&self, lexeme_start: usize, lexeme_end: usize) -> Result<TokenType, LexerError> {
-        match &self.source_code[lexeme_start..lexeme_end] {
-            "and" => Ok(TokenType::And),
-            "else" => Ok(TokenType::Else),
-            "false" => Ok(TokenType::False),
-            "fun" => Ok(TokenType::Fun),
-            "fn" => Ok(TokenType::Fun),
-            "for" => Ok(TokenType::For),
-            "if" => Ok(TokenType::If),
-            "nil" => Ok(TokenType::Nil),
-            "or" => Ok(TokenType::Or),
-            "print" => Ok(TokenType::Print),
-            "return" => Ok(TokenType::Return),
-            "super" => Ok(TokenType::Super),
-            "this" => Ok(TokenType::This),
-            "true" => Ok(TokenType::True),
-            "var" => Ok(TokenType::Var),
-            "dec" => Ok(TokenType::Dec),
-            "while" => Ok(TokenType::While),
-            "int" => Ok(TokenType::DInteger),
-            "string" => Ok(TokenType::DString),
-            "char" => Ok(TokenType::DChar),
-            "float" => Ok(TokenType::DFloat),
-            "void" => Ok(TokenType::DVoid),
-            "extern" => Ok(TokenType::Extern),
-            "unit" => Ok(TokenType::Void),
-            "type" => Ok(TokenType::Type),
-            "struct" => Ok(TokenType::Struct),
-            _ => Err(LexerError::IllegalKeyword),
+        Ok(())
+    }
+
+    fn advance_while(&mut self, accept: impl Fn(char) -> bool) -> Result<(), LexerError> {
+        while let Ok(ch) = self.get_current_character() && accept(ch) {
+            self.advance()?;
         }
+        Ok(())
     }
 
-    fn generate_keyword(&mut self, lexeme_start: usize) -> Result<Token<AnyMetadata<'a>>, LexerError> {
-        self.advance().map_err(|_| LexerError::UnexpectedEof)?;
-        let starting_column = self.current_column;
-        while self.can_move(){
-            let ch = self.get_current_character().map_err(|_| LexerError::UnexpectedEof)?;
-            if !ch.is_ascii_alphabetic() {
-                break
-            }
-            self.advance().map_err(|_| LexerError::UnexpectedEof)?;
+    // everything from `start` up to the current byte
+    fn token(&self, token_type: TokenType, start: usize, position: Position, meta_data: AnyMetadata<'a>) -> Token<AnyMetadata<'a>> {
+        Token {
+            token_type,
+            position,
+            span: Span::new(start, self.position),
+            meta_data
+        }
+    }
+
+    fn generate_operator(&mut self, tt: TokenType, width: usize) -> Token<AnyMetadata<'a>> {
+        let (start, position) = (self.position, self.here());
+        for _ in 0..width {
+            self.advance().expect("operators are only generated for characters which are there");
         }
-        let lexeme_end = self.position;
-        match self.get_keyword_type(lexeme_start, lexeme_end) {
-            Ok(tt) => {
-                Ok(Token {
-                    token_type: tt,
-                    position: Position::new(self.current_line, starting_column),
-                    lexeme: (lexeme_start, lexeme_end),
-                    meta_data: AnyMetadata::None
-                })
+        self.token(tt, start, position, AnyMetadata::None)
+    }
+
+    // `r"..."`, `r#"..."#`, ... the number of hashes when a raw string starts here
+    fn raw_string_hashes(&self) -> Option<usize> {
+        let rest = self.rest();
+        let hashes = rest[1..].bytes().take_while(|b| *b == b'#').count();
+        (rest.starts_with('r') && rest.as_bytes().get(hashes + 1) == Some(&b'"')).then_some(hashes)
+    }
+
+    // called right after the backslash at `escape`, None for a line continuation
+    // which produces nothing. `literal` is where the string or char literal started.
+    fn decode_escape(&mut self, literal: Position, escape: Position) -> Result<Option<char>, LexerError> {
+        let kind = self.get_current_character().map_err(|_| LexerError::UnterminatedString(literal))?;
+        self.advance()?;
+        let decoded = match kind {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' => '\\',
+            '"' => '"',
+            '\'' => '\'',
+            // \x only covers ASCII so the text stays valid UTF-8, use \u{...} past that
+            'x' => {
+                let digits = self.rest().get(..2).ok_or(LexerError::IllegalEscape(escape))?;
+                let value = u8::from_str_radix(digits, 16).ok().filter(|v| *v < 0x80 && !digits.starts_with('+'))
+                    .ok_or(LexerError::IllegalEscape(escape))?;
+                self.advance()?;
+                self.advance()?;
+                value as char
             }
-            Err(LexerError::IllegalKeyword) => {
-                Ok(Token {
-                    token_type: TokenType::Identifier,
-                    position: Position::new(self.current_line, starting_column),
-                    lexeme: (lexeme_start, lexeme_end),
-                    meta_data: AnyMetadata::Identifier {
-                        value: &self.source_code[lexeme_start..lexeme_end]
-                    }
-                })
+            'u' => {
+                let rest = self.rest();
+                let close = rest.find('}').filter(|close| rest.starts_with('{') && (2..=7).contains(close) && !rest[1..].starts_with('+'))
+                    .ok_or(LexerError::IllegalEscape(escape))?;
+                let value = u32::from_str_radix(&rest[1..close], 16).ok().and_then(char::from_u32)
+                    .ok_or(LexerError::IllegalEscape(escape))?;
+                for _ in 0..=close {
+                    self.advance()?;
+                }
+                value
             }
-            _ => {
-                panic!("Something wen't wrong while lexing.");
+            // a backslash at the end of a line joins it with the next one,
+            // dropping the next line's indentation
+            '\n' => {
+                self.advance_while(char::is_whitespace)?;
+                return Ok(None);
             }
-        }
+            _ => return Err(LexerError::IllegalEscape(escape))
+        };
+        Ok(Some(decoded))
     }
 
-    fn generate_number(&mut self, lexeme_start: usize) -> Result<Token<AnyMetadata<'a>>, LexerError> {
-        let starting_column = self.current_column;
-        let mut dot_count = 0;
-        while self.can_move() {
-            if let Ok(c) = self.get_current_character() {
-                if c.is_ascii_digit() || c == '_' || c == '.' {
-                    if c == '.'{
-                        dot_count += 1;
-                        if dot_count > 1 {
-                            break;
+    // strings may span lines. escapes are decoded here so the rest of the compiler
+    // only sees the final text, raw strings are taken verbatim up to a quote
+    // followed by as many hashes as they were opened with.
+    fn generate_string(&mut self, raw_hashes: Option<usize>) -> Result<Token<AnyMetadata<'a>>, LexerError> {
+        let (start, position) = (self.position, self.here());
+        let source = self.source_code;
+        for _ in 0..raw_hashes.map_or(1, |hashes| hashes + 2) {
+            self.advance()?;
+        }
+        let content_start = self.position;
+        // only allocated once an escape shows up, until then the text is the source slice
+        let mut decoded: Option<String> = None;
+        let content_end = loop {
+            let (offset, here) = (self.position, self.here());
+            let ch = self.get_current_character().map_err(|_| LexerError::UnterminatedString(position))?;
+            self.advance()?;
+            match (ch, raw_hashes) {
+                ('"', None) => break offset,
+                ('"', Some(hashes)) => {
+                    if self.rest().bytes().take(hashes).filter(|b| *b == b'#').count() == hashes {
+                        for _ in 0..hashes {
+                            self.advance()?;
                         }
+                        break offset;
                     }
-                   self.advance().map_err(|_| LexerError::UnexpectedEof)?;
-                } else {
-                    break;
-                }
-            }
-        }
-        if dot_count == 1 {
-            let value = match self.source_code[lexeme_start..self.position].parse::<f64>() {
-                Ok(x) => x,
-                Err(_) => {
-                    return Err(LexerError::IllegalNumber);
                 }
-            };
-            Ok(Token {
-                token_type: TokenType::Integer,
-                position: Position::new(self.current_line, starting_column),
-                lexeme: (lexeme_start, self.position),
-                meta_data: AnyMetadata::Number {
-                    value: NumberType::Float(value),
-                }
-            })
-        } else {
-            let value = match self.source_code[lexeme_start..self.position].parse::<i64>() {
-                Ok(x) => x,
-                Err(_) => {
-                    return Err(LexerError::IllegalNumber);
+                ('\\', None) => {
+                    let text = decoded.get_or_insert_with(|| source[content_start..offset].to_string());
+                    if let Some(ch) = self.decode_escape(position, here)? {
+                        text.push(ch);
+                    }
                 }
-            };
-            Ok(Token {
-                token_type: TokenType::Integer,
-                position: Position::new(self.current_line, starting_column),
-                lexeme: (lexeme_start, self.position),
-                meta_data: AnyMetadata::Number {
-                    value: NumberType::Integer(value),
+                _ => {
+                    if let Some(text) = decoded.as_mut() {
+                        text.push(ch);
+                    }
                 }
-            })
+            }
+        };
+        let value = match decoded {
+            Some(text) => self.strings.alloc(text),
+            None => &source[content_start..content_end]
+        };
+        Ok(self.token(TokenType::String, start, position, AnyMetadata::String { value }))
+    }
+
+    // `// ...` up to the end of the line, the newline itself is left for `next`
+    fn skip_line_comment(&mut self) -> Result<(), LexerError> {
+        self.advance_while(|ch| ch != '\n')
+    }
+
+    // `/* ... */`, block comments nest so commenting out code which already
+    // has a block comment in it does what you'd expect
+    fn skip_block_comment(&mut self) -> Result<(), LexerError> {
+        let start = self.here();
+        self.advance()?;
+        self.advance()?;
+        let mut depth = 1;
+        while depth > 0 {
+            let rest = self.rest();
+            if rest.starts_with("/*") || rest.starts_with("*/") {
+                depth += if rest.starts_with("/*") { 1 } else { -1 };
+                self.advance()?;
+                self.advance()?;
+            } else {
+                self.advance().map_err(|_| LexerError::UnterminatedComment(start))?;
+            }
         }
+        Ok(())
     }
-}
 
-impl<'a> Iterator for Lexer<'a> {
-    type Item = Token<AnyMetadata<'a>>;
+    fn generate_doc_comment(&mut self) -> Result<Token<AnyMetadata<'a>>, LexerError> {
+        let (start, position) = (self.position, self.here());
+        for _ in 0..3 {
+            self.advance()?;
+        }
+        let text_start = self.position;
+        self.skip_line_comment()?;
+        let value = &self.source_code[text_start..self.position];
+        Ok(self.token(TokenType::DocComment, start, position, AnyMetadata::String { value }))
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut lexeme_start = self.position;
-        while self.can_move() {
-            if let Some(ch) = self.source_code.chars().nth(self.position) {
-                match ch {
-                    '\n' => {
-                        self.current_line += 1;
-                        self.current_column = 0;
-                        self.advance().ok()?;
-                        lexeme_start = self.position;
-                    }
+    // a plain `//` comment, unless it is a `// nerv: allow(...)` directive
+    fn line_comment(&mut self) -> Result<Option<Token<AnyMetadata<'a>>>, LexerError> {
+        let (start, position) = (self.position, self.here());
+        self.skip_line_comment()?;
+        let text = self.source_code[start + 2..self.position].trim();
+        Ok(text.strip_prefix("nerv: allow(").and_then(|lints| lints.strip_suffix(')'))
+            .map(|value| self.token(TokenType::LintDirective, start, position, AnyMetadata::String { value })))
+    }
 
+    // the whole source up front, stopping at the first error
+    pub fn tokenize(self) -> Result<Vec<Token<AnyMetadata<'a>>>, Diagnostic> {
+        self.collect::<Result<_, _>>().map_err(|error| Self::diagnostic(&error))
+    }
 
-                    // Operators
-                    '+' => return self.generate_operator(lexeme_start, TokenType::Plus),
-                    '-' => {
-                        if let Some(next_ch) = self.source_code.chars().nth(self.position + 1) {
-                            if next_ch == '>' {
-                                self.advance().ok()?;
-                                self.advance().ok()?;
-                                let lexeme_ending = self.position;
-                                return Some(Token {
-                                    token_type: TokenType::Arrow,
-                                    position: Position::new(self.current_line, self.current_column),
-                                    lexeme: (lexeme_start, lexeme_ending),
-                                    meta_data: AnyMetadata::None
-                                });
-                            }
-                        }
-                        return self.generate_operator(lexeme_start, TokenType::Minus);
-                    }
-                    '/' => return self.generate_operator(lexeme_start, TokenType::Slash),
-                    '*' => return self.generate_operator(lexeme_start, TokenType::Star),
-                    '.' => return self.generate_operator(lexeme_start, TokenType::Dot),
-                    '@' => return self.generate_operator(lexeme_start, TokenType::At),
-                    '&' => return self.generate_operator(lexeme_start, TokenType::Ampersand),
-                    '#' => return self.generate_operator(lexeme_start, TokenType::Pound),
-                    ':' => return self.generate_operator(lexeme_start, TokenType::Colon),
-
-                    // Words
-                    'a' ..= 'z' | 'A' ..= 'Z' | '_' => return self.generate_keyword(lexeme_start).ok(),
-                    '"' => return self.generate_string(lexeme_start).ok(),
-                    '0' ..= '9' => return self.generate_number(lexeme_start).ok(),
-
-                    // Eof
-                    '\0' => {
-                        self.current_column += 1;
-                        return None;
-                    },
-                    ';' => {
-                        self.advance().ok()?;
-                        return Some(Token {
-                            token_type: TokenType::Semicolon,
-                            position: Position::new(self.current_line, self.current_column),
-                            lexeme: (lexeme_start, self.position),
-                            meta_data: AnyMetadata::None
-                        })
-                    }
+    fn diagnostic(error: &LexerError) -> Diagnostic {
+        match *error {
+            LexerError::IllegalEscape(position) => Diagnostic::error(codes::INVALID_ESCAPE, "invalid escape sequence in string literal")
+                .at(position, "unknown escape")
+                .width(2)
+                .note("the supported escapes are \\n \\t \\r \\0 \\\\ \\\" \\' \\xNN (up to \\x7F) and \\u{NNNN}"),
+            LexerError::UnterminatedString(position) => Diagnostic::error(codes::UNTERMINATED_STRING, "unterminated string literal")
+                .at(position, "the string starts here and never ends"),
+            LexerError::IllegalNumber(position) => Diagnostic::error(codes::INVALID_NUMBER, "invalid or out of range number literal")
+                .at(position, "")
+                .note("integers have to fit an int, the i8 to i64 and u8 to u64 suffixes can only narrow that range"),
+            LexerError::IllegalCharacterLiteral(position) => Diagnostic::error(codes::INVALID_CHARACTER_LITERAL, "a character literal holds exactly one character")
+                .at(position, "")
+                .note("use a string for text"),
+            LexerError::UnterminatedComment(position) => Diagnostic::error(codes::UNTERMINATED_COMMENT, "unterminated block comment")
+                .at(position, "the comment starts here and never ends")
+                .note("block comments nest, every `/*` needs its own `*/`"),
+            LexerError::UnexpectedCharacter(position, ch) => Diagnostic::error(codes::UNEXPECTED_CHARACTER, format!("unexpected character `{}`", ch.escape_default()))
+                .at(position, ""),
+            // something went past the end while it still expected more
+            LexerError::CalledNextAfterExhaustion | LexerError::IllegalCharacterAccess => Diagnostic::error(codes::UNEXPECTED_EOF, "unexpected end of file")
+        }
+    }
 
-                    ',' => {
-                        self.advance().ok()?;
-                        return Some(Token {
-                            token_type: TokenType::Comma,
-                            position: Position::new(self.current_line, self.current_column),
-                            lexeme: (lexeme_start, self.position),
-                            meta_data: AnyMetadata::None
-                        })
-                    }
+    fn get_keyword_type(&self, word: &str) -> Option<TokenType> {
+        match word {
+            "and" => Some(TokenType::And),
+            "else" => Some(TokenType::Else),
+            "false" => Some(TokenType::False),
+            "fun" => Some(TokenType::Fun),
+            "fn" => Some(TokenType::Fun),
+            "for" => Some(TokenType::For),
+            "if" => Some(TokenType::If),
+            "nil" => Some(TokenType::Nil),
+            "or" => Some(TokenType::Or),
+            "print" => Some(TokenType::Print),
+            "println" => Some(TokenType::Println),
+            "return" => Some(TokenType::Return),
+            "super" => Some(TokenType::Super),
+            "this" => Some(TokenType::This),
+            "true" => Some(TokenType::True),
+            "var" => Some(TokenType::Var),
+            "dec" => Some(TokenType::Dec),
+            "while" => Some(TokenType::While),
+            "int" => Some(TokenType::DInteger),
+            "string" => Some(TokenType::DString),
+            "char" => Some(TokenType::DChar),
+            "float" => Some(TokenType::DFloat),
+            "bool" => Some(TokenType::DBool),
+            "void" => Some(TokenType::DVoid),
+            "extern" => Some(TokenType::Extern),
+            "unit" => Some(TokenType::Void),
+            "type" => Some(TokenType::Type),
+            "struct" => Some(TokenType::Struct),
+            "defer" => Some(TokenType::Defer),
+            "asm" => Some(TokenType::Asm),
+            "const" => Some(TokenType::Const),
+            "syscall" => Some(TokenType::Syscall),
+            _ => None,
+        }
+    }
 
-                    '=' => {
-                        self.advance().ok()?;
-                        return Some(Token {
-                            token_type: TokenType::Equal,
-                            position: Position::new(self.current_line, self.current_column),
-                            lexeme: (lexeme_start, self.position),
-                            meta_data: AnyMetadata::None
-                        })
-                    }
+    fn generate_keyword(&mut self) -> Token<AnyMetadata<'a>> {
+        let (start, position) = (self.position, self.here());
+        self.advance_while(|ch| ch.is_ascii_alphanumeric() || ch == '_').expect("words are ASCII");
+        let word = &self.source_code[start..self.position];
+        match self.get_keyword_type(word) {
+            Some(tt) => self.token(tt, start, position, AnyMetadata::None),
+            None => self.token(TokenType::Identifier, start, position, AnyMetadata::Identifier { value: word })
+        }
+    }
 
-                    '(' => {
-                        self.advance().ok()?;
-                        return Some(Token {
-                            token_type: TokenType::LeftParen,
-                            position: Position::new(self.current_line, self.current_column),
-                            lexeme: (lexeme_start, self.position),
-                            meta_data: AnyMetadata::None
-                        })
-                    }
+    // `1_000`, `0xFF`, `0b1010`, `0o755`, `2.5`, `1e-9` and a type suffix (`10u8`, `3i64`, `1f32`).
+    // nerv has a single int and float type so far, the suffix only narrows the
+    // range the literal is checked against. every integer has to fit an int.
+    fn generate_number(&mut self) -> Result<Token<AnyMetadata<'a>>, LexerError> {
+        let (start, position) = (self.position, self.here());
+        let source = self.source_code;
+        let illegal = LexerError::IllegalNumber(position);
+        let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        let is_digit = |c: char| c.is_ascii_digit() || c == '_';
 
-                    ')' => {
-                        self.advance().ok()?;
-                        return Some(Token {
-                            token_type: TokenType::RightParen,
-                            position: Position::new(self.current_line, self.current_column),
-                            lexeme: (lexeme_start, self.position),
-                            meta_data: AnyMetadata::None
-                        })
-                    }
+        let radix = match self.rest().get(..2) {
+            Some("0x") => 16,
+            Some("0b") => 2,
+            Some("0o") => 8,
+            _ => 10
+        };
+        let (digits, is_float, suffix) = if radix != 10 {
+            self.advance()?;
+            self.advance()?;
+            let digits_start = self.position;
+            self.advance_while(is_word)?;
+            let run = &source[digits_start..self.position];
+            // hex digits include `f`, so only the integer suffixes are available there
+            let split = run.find(['i', 'u']).filter(|_| radix == 16)
+                .or_else(|| run.find(|c: char| c.is_ascii_alphabetic()).filter(|_| radix != 16))
+                .unwrap_or(run.len());
+            (&run[..split], false, &run[split..])
+        } else {
+            self.advance_while(is_digit)?;
+            let mut is_float = false;
+            let rest = self.rest();
+            if rest.starts_with('.') && rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+                is_float = true;
+                self.advance()?;
+                self.advance_while(is_digit)?;
+            }
+            let rest = self.rest();
+            let exponent = rest.strip_prefix(['e', 'E']).map(|e| e.strip_prefix(['+', '-']).unwrap_or(e));
+            if let Some(exponent) = exponent && exponent.starts_with(|c: char| c.is_ascii_digit()) {
+                is_float = true;
+                for _ in 0..rest.len() - exponent.len() {
+                    self.advance()?;
+                }
+                self.advance_while(is_digit)?;
+            }
+            let digits_end = self.position;
+            self.advance_while(is_word)?;
+            (&source[start..digits_end], is_float, &source[digits_end..self.position])
+        };
 
-                    '{' => {
-                        self.advance().ok()?;
-                        return Some(Token {
-                            token_type: TokenType::LeftBrace,
-                            position: Position::new(self.current_line, self.current_column),
-                            lexeme: (lexeme_start, self.position),
-                            meta_data: AnyMetadata::None
-                        })
-                    }
+        let digits = digits.replace('_', "");
+        if digits.is_empty() {
+            return Err(illegal);
+        }
+        if is_float || matches!(suffix, "f32" | "f64") {
+            let value = digits.parse::<f64>().map_err(|_| illegal)?;
+            let fits = match suffix {
+                "" | "f64" => value.is_finite(),
+                "f32" => value.is_finite() && value.abs() <= f32::MAX as f64,
+                _ => false
+            };
+            if !fits || radix != 10 {
+                return Err(LexerError::IllegalNumber(position));
+            }
+            return Ok(self.token(TokenType::Float, start, position, AnyMetadata::Number { value: NumberType::Float(value) }));
+        }
 
-                    '}' => {
-                        self.advance().ok()?;
-                        return Some(Token {
-                            token_type: TokenType::RightBrace,
-                            position: Position::new(self.current_line, self.current_column),
-                            lexeme: (lexeme_start, self.position),
-                            meta_data: AnyMetadata::None
-                        })
-                    }
+        let value = u64::from_str_radix(&digits, radix).map_err(|_| illegal)?;
+        let (bits, signed) = match suffix {
+            "" | "i32" => (32, true),
+            "i8" => (8, true),
+            "i16" => (16, true),
+            "i64" => (64, true),
+            "u8" => (8, false),
+            "u16" => (16, false),
+            "u32" => (32, false),
+            "u64" => (64, false),
+            _ => return Err(LexerError::IllegalNumber(position))
+        };
+        // decimal literals have to fit the positive half of the range, hex/binary/octal
+        // are bit patterns and may use every bit. the literal is an int in the end,
+        // so wider suffixes still can't go past 32 bits.
+        let int_limit = if radix == 10 { i32::MAX as u64 } else { u32::MAX as u64 };
+        let suffix_limit = match (signed, radix) {
+            (true, 10) => (1u128 << (bits - 1)) - 1,
+            _ => (1u128 << bits) - 1
+        };
+        if value as u128 > suffix_limit || value > int_limit {
+            return Err(LexerError::IllegalNumber(position));
+        }
+        // a bit pattern with the top bit set is a negative int
+        let value = value as u32 as i32;
+        Ok(self.token(TokenType::Integer, start, position, AnyMetadata::Number { value: NumberType::Integer(value as i64) }))
+    }
 
-                    x => {
-                        if [' ', '\t'].contains(&x) {
-                            self.advance().ok();
-                            lexeme_start = self.position;
-                            continue;
-                        } else {
-                            panic!("ILLEGAL CHARACTER : {x} : {:?}:{:?}", self.current_line, self.current_column);
-                        }
-                    }
+    // `'a'`, `'\n'`, `'\u{e9}'`, the code point of a single character as an int
+    fn generate_character(&mut self) -> Result<Token<AnyMetadata<'a>>, LexerError> {
+        let (start, position) = (self.position, self.here());
+        let illegal = LexerError::IllegalCharacterLiteral(position);
+        self.advance()?;
+        let escape = self.here();
+        let ch = self.get_current_character().map_err(|_| LexerError::IllegalCharacterLiteral(position))?;
+        self.advance()?;
+        let value = match ch {
+            '\\' => self.decode_escape(position, escape)?.ok_or(LexerError::IllegalCharacterLiteral(position))?,
+            '\'' | '\n' => return Err(illegal),
+            _ => ch
+        };
+        if self.get_current_character().ok() != Some('\'') {
+            return Err(LexerError::IllegalCharacterLiteral(position));
+        }
+        self.advance()?;
+        Ok(self.token(TokenType::Character, start, position, AnyMetadata::Number { value: NumberType::Integer(value as i64) }))
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token<AnyMetadata<'a>>, LexerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Ok(ch) = self.get_current_character() {
+            let rest = self.rest();
+            let single = match ch {
+                '+' => Some(TokenType::Plus),
+                '*' => Some(TokenType::Star),
+                '@' => Some(TokenType::At),
+                '&' => Some(TokenType::Ampersand),
+                '#' => Some(TokenType::Pound),
+                ':' => Some(TokenType::Colon),
+                ';' => Some(TokenType::Semicolon),
+                ',' => Some(TokenType::Comma),
+                '=' => Some(TokenType::Equal),
+                '(' => Some(TokenType::LeftParen),
+                ')' => Some(TokenType::RightParen),
+                '{' => Some(TokenType::LeftBrace),
+                '}' => Some(TokenType::RightBrace),
+                _ => None
+            };
+            if let Some(tt) = single {
+                return Some(Ok(self.generate_operator(tt, 1)));
+            }
+            let result = match ch {
+                ' ' | '\t' | '\r' | '\n' => {
+                    self.advance().ok()?;
+                    continue;
+                }
+
+                // Operators
+                '-' if rest.starts_with("->") => Ok(Some(self.generate_operator(TokenType::Arrow, 2))),
+                '-' => Ok(Some(self.generate_operator(TokenType::Minus, 1))),
+                '.' if rest.starts_with("...") => Ok(Some(self.generate_operator(TokenType::Ellipsis, 3))),
+                '.' => Ok(Some(self.generate_operator(TokenType::Dot, 1))),
+                // `////` and longer are plain comments, like rustdoc
+                '/' if rest.starts_with("///") && !rest.starts_with("////") => self.generate_doc_comment().map(Some),
+                '/' if rest.starts_with("//") => self.line_comment(),
+                '/' if rest.starts_with("/*") => self.skip_block_comment().map(|_| None),
+                '/' => Ok(Some(self.generate_operator(TokenType::Slash, 1))),
+
+                // Words
+                'r' if let Some(hashes) = self.raw_string_hashes() => self.generate_string(Some(hashes)).map(Some),
+                'a' ..= 'z' | 'A' ..= 'Z' | '_' => Ok(Some(self.generate_keyword())),
+                '"' => self.generate_string(None).map(Some),
+                '0' ..= '9' => self.generate_number().map(Some),
+                '\'' => self.generate_character().map(Some),
+
+                // Eof
+                '\0' => return None,
+                x => Err(LexerError::UnexpectedCharacter(self.here(), x))
+            };
+            match result {
+                // a comment, keep going
+                Ok(None) => continue,
+                Ok(Some(token)) => return Some(Ok(token)),
+                Err(error) => {
+                    // nothing is lexed after an error
+                    self.position = self.source_code.len();
+                    return Some(Err(error));
                 }
             }
         }
@@ -335,7 +490,178 @@ impl<'a> Iterator for Lexer<'a> {
 
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
+
+    fn tokens<'a>(source: &'a str, strings: &'a StringArena) -> Vec<Token<AnyMetadata<'a>>> {
+        Lexer::new(source, strings).tokenize().unwrap()
+    }
+
+    fn token_types(source: &str) -> Vec<TokenType> {
+        tokens(source, &StringArena::default()).into_iter().map(|t| t.token_type).collect()
+    }
+
+    fn error(source: &str) -> Diagnostic {
+        Lexer::new(source, &StringArena::default()).tokenize().unwrap_err()
+    }
+
+    #[test]
+    fn spans_are_bytes_and_columns_are_characters() {
+        let source = "\"é\" = \"héllo\";\n  x";
+        let strings = StringArena::default();
+        let tokens = tokens(source, &strings);
+        assert_eq!(tokens[2].span.text(source), "\"héllo\"");
+        // `é` is one column but two bytes
+        assert_eq!(tokens[1].position, Position::new(1, 5));
+        assert_eq!(tokens[1].span, Span::new(5, 6));
+        let x = tokens.last().unwrap();
+        assert_eq!((x.span.text(source), x.position), ("x", Position::new(2, 3)));
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        let source = "1 // one\n/* two /* nested */ still two */ 2 / 3 //// four\n";
+        assert_eq!(token_types(source), [TokenType::Integer, TokenType::Integer, TokenType::Slash, TokenType::Integer]);
+    }
+
+    #[test]
+    fn doc_comments_are_kept() {
+        let strings = StringArena::default();
+        let tokens = tokens("/// adds things\n@add", &strings);
+        assert_eq!(tokens[0].token_type, TokenType::DocComment);
+        assert!(matches!(tokens[0].meta_data, AnyMetadata::String { value: " adds things" }));
+        assert_eq!(tokens[1].token_type, TokenType::At);
+    }
+
+    #[test]
+    fn allow_comments_are_kept() {
+        let strings = StringArena::default();
+        let tokens = tokens("dec x int = 1; // nerv: allow(unused_variable)\n// allow(unused_variable)", &strings);
+        let directive = tokens.last().unwrap();
+        assert_eq!((directive.token_type, directive.position), (TokenType::LintDirective, Position::new(1, 16)));
+        assert!(matches!(directive.meta_data, AnyMetadata::String { value: "unused_variable" }));
+    }
+
+    // only literals with escapes in them are copied, the others borrow the source
+    #[test]
+    fn escaped_strings_are_decoded_into_the_arena() {
+        let strings = StringArena::default();
+        let source = "\"plain\" \"tab\\there\" r\"raw\\t\"".repeat(20);
+        let tokens = tokens(&source, &strings);
+        let values: Vec<&str> = tokens.iter().map(|t| match t.meta_data {
+            AnyMetadata::String { value } => value,
+            other => panic!("expected a string literal, got {other:?}")
+        }).collect();
+        assert_eq!(values[..3], ["plain", "tab\there", "raw\\t"]);
+        assert!(values.chunks(3).all(|chunk| *chunk == values[..3]));
+        assert!(source.as_bytes().as_ptr_range().contains(&values[0].as_ptr()));
+        assert!(!source.as_bytes().as_ptr_range().contains(&values[1].as_ptr()));
+    }
+
+    fn integer(source: &str) -> i64 {
+        match tokens(source, &StringArena::default()).first().map(|t| (t.token_type, t.meta_data)) {
+            Some((TokenType::Integer | TokenType::Character, AnyMetadata::Number { value: NumberType::Integer(value) })) => value,
+            other => panic!("expected an integer literal, got {other:?}")
+        }
+    }
+
+    #[test]
+    fn integer_literal_forms() {
+        assert_eq!(integer("1_000_000"), 1_000_000);
+        assert_eq!(integer("0xFf"), 255);
+        assert_eq!(integer("0b1010_1010"), 170);
+        assert_eq!(integer("0o755"), 493);
+        assert_eq!(integer("255u8"), 255);
+        assert_eq!(integer("0xFFFF_FFFF"), -1);
+        assert_eq!(integer("0x7FFF_FFFFi64"), i32::MAX as i64);
+    }
+
+    // the suffixes wider than an int are still checked against the int range
+    #[test]
+    fn suffixed_literals_at_the_int_boundary() {
+        for suffix in ["", "i32", "i64", "u32", "u64"] {
+            assert_eq!(integer(&format!("2147483647{suffix}")), i32::MAX as i64, "{suffix}");
+            assert_eq!(error(&format!("2147483648{suffix}")).code, codes::INVALID_NUMBER, "{suffix}");
+        }
+        assert_eq!(integer("0xFFFF_FFFFu64"), -1);
+        for source in ["9000000000i64", "4000000000u32", "0x1_0000_0000i64", "0x1_0000_0000u64"] {
+            assert_eq!(error(source).code, codes::INVALID_NUMBER, "{source}");
+        }
+    }
+
+    #[test]
+    fn float_literals_are_floats() {
+        for (source, expected) in [("2.5", 2.5), ("1e-9", 1e-9), ("6.02E+23", 6.02e23), ("3f32", 3.0)] {
+            let strings = StringArena::default();
+            let token = tokens(source, &strings)[0];
+            assert_eq!(token.token_type, TokenType::Float);
+            assert!(matches!(token.meta_data, AnyMetadata::Number { value: NumberType::Float(value) } if value == expected), "{source}");
+        }
+        assert_eq!(token_types("p.x"), [TokenType::Identifier, TokenType::Dot, TokenType::Identifier]);
+    }
+
+    #[test]
+    fn character_literals() {
+        assert_eq!(integer("'a'"), 97);
+        assert_eq!(integer("'\\n'"), 10);
+        assert_eq!(integer("'\\''"), 39);
+        assert_eq!(integer("'é'"), 0xe9);
+        assert_eq!(integer("'\\u{1F600}'"), 0x1F600);
+    }
+
+    #[test]
+    fn out_of_range_literals() {
+        for source in ["300u8", "2147483649", "0x1_0000_0000", "1e999", "12abc", "0b102", "0x", "1.5i32", "0b1f32"] {
+            assert!(Lexer::new(source, &StringArena::default()).tokenize().is_err(), "{source} should not lex");
+        }
+    }
+
+    #[test]
+    fn illegal_number_position() {
+        let error = error("1 + 300i8");
+        assert_eq!(error.code, codes::INVALID_NUMBER);
+        assert_eq!(error.primary.unwrap().position, Position::new(1, 5));
+    }
+
+    #[test]
+    fn multi_character_literal() {
+        assert_eq!(error("'ab'").code, codes::INVALID_CHARACTER_LITERAL);
+    }
+
+    #[test]
+    fn unexpected_character() {
+        let error = error("1 $ 2");
+        assert_eq!(error.code, codes::UNEXPECTED_CHARACTER);
+        assert_eq!(error.primary.unwrap().position, Position::new(1, 3));
+    }
+
+    // cargo test --release -- --ignored --nocapture lexing_scales_linearly
+    #[test]
+    #[ignore = "benchmark, run it in release mode"]
+    fn lexing_scales_linearly() {
+        let unit = "/// doc\n@f(int a, float b) int {\n    dec s string = \"tab\\t é\";\n    return a * 0xFF + 1_000 / 3; // done\n}\n";
+        let mut timings = vec![];
+        for megabytes in [2, 4, 8, 16] {
+            let source = unit.repeat(megabytes * 1024 * 1024 / unit.len());
+            let mut tokens = 0;
+            let elapsed = (0..3).map(|_| {
+                let started = std::time::Instant::now();
+                tokens = Lexer::new(&source, &StringArena::default()).count();
+                started.elapsed()
+            }).min().unwrap();
+            println!("{megabytes:>3} MB  {tokens:>9} tokens  {elapsed:>10.2?}  {:>7.1} MB/s", megabytes as f64 / elapsed.as_secs_f64());
+            timings.push(elapsed.as_secs_f64());
+        }
+        // 8 times the input should take about 8 times as long, a quadratic lexer takes 64
+        let growth = timings[3] / timings[0];
+        assert!(growth < 16.0, "16 MB took {growth:.1}x as long as 2 MB");
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        let error = error("1 /* /* */ 2\n");
+        assert_eq!(error.code, codes::UNTERMINATED_COMMENT);
+        assert_eq!(error.primary.unwrap().position, Position::new(1, 3));
+    }
 
     // #[test]
     // fn lexing_operators() {
@@ -1,41 +1,137 @@
-use parser::Parser;
+use shared::{arena::StringArena, errors::Diagnostic, lints::{Level, Lint, LintLevels}};
 use typechecker::TypeChecker;
 use std::env;
 use std::fs;
 use std::io::Write;
+use std::panic;
+use std::process;
 
 mod lexer;
 mod parser;
 mod compiler;
+mod ir;
 mod typechecker;
 mod shared;
+mod standard_library;
+
+// anything that still panics is a bug in the compiler rather than in the
+// program being compiled, report it as such instead of with a backtrace.
+fn install_panic_hook() {
+    panic::set_hook(Box::new(|info| {
+        let message = info.payload().downcast_ref::<&str>().map(|s| s.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        eprintln!("error: internal compiler error: {}", message);
+        if let Some(location) = info.location() {
+            eprintln!(" = note: raised at {}:{}", location.file(), location.line());
+        }
+        eprintln!(" = note: this is a bug in the compiler, please report it with the program that triggered it");
+    }));
+}
+
+// prints the diagnostics and stops the compilation if any of them is an error
+fn emit(diagnostics: &[Diagnostic], path: &str, source: &str) {
+    if diagnostics.is_empty() {
+        return;
+    }
+    let rendered: Vec<String> = diagnostics.iter().map(|d| d.render(path, source)).collect();
+    eprint!("{}", rendered.join("\n"));
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    let warnings = diagnostics.len() - errors;
+    let plural = |n: usize| if n == 1 { "" } else { "s" };
+    let warnings_emitted = format!("{} warning{} emitted", warnings, plural(warnings));
+    if errors == 0 {
+        eprintln!("\nwarning: {}", warnings_emitted);
+        return;
+    }
+    if diagnostics.len() > 1 {
+        eprint!("\nerror: aborting due to {} previous error{}", errors, plural(errors));
+        if warnings > 0 {
+            eprint!("; {}", warnings_emitted);
+        }
+        eprintln!();
+    }
+    process::exit(1);
+}
+
+fn report(diagnostics: &[Diagnostic], path: &str, source: &str) -> ! {
+    emit(diagnostics, path, source);
+    process::exit(1);
+}
 
 fn main() {
+    install_panic_hook();
     let args: Vec<String> = env::args().collect();
 
-    if args.len() != 3 {
-        eprintln!("Usage: {} <input_file> <output_file>", args[0]);
-        std::process::exit(1);
+    let mut freestanding = false;
+    let mut emit_ir = false;
+    let mut allocate_registers = true;
+    let mut lint_levels = LintLevels::default();
+    let mut paths = vec![];
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        let level = match arg.get(..2) {
+            Some("-A") => Level::Allow,
+            Some("-W") => Level::Warn,
+            Some("-D") => Level::Deny,
+            _ => {
+                match arg.as_str() {
+                    "--no-libc" => freestanding = true,
+                    "--emit=ir" => emit_ir = true,
+                    "--emit=asm" => emit_ir = false,
+                    "--no-regalloc" => allocate_registers = false,
+                    _ => paths.push(arg),
+                }
+                continue;
+            }
+        };
+        // `-D name` and `-Dname` both work
+        let name = if arg.len() > 2 { Some(&arg[2..]) } else { rest.next().map(String::as_str) };
+        let Some(name) = name else {
+            eprintln!("error: `{}` needs a lint name", arg);
+            process::exit(1);
+        };
+        if !lint_levels.set(name, level) {
+            eprintln!("error: unknown lint `{}`", name);
+            eprintln!(" = note: the lints are {} and `warnings` for all of them", Lint::names());
+            process::exit(1);
+        }
     }
 
-    let input_path = &args[1];
-    let output_path = &args[2];
+    if paths.len() != 2 {
+        eprintln!("Usage: {} [--no-libc] [--emit=asm|ir] [--no-regalloc] [-A|-W|-D lint]... <input_file> <output_file>", args[0]);
+        process::exit(1);
+    }
 
-    let source_code = fs::read_to_string(input_path)
-        .expect("Error while reading the input file.");
+    let input_path = paths[0];
+    let output_path = paths[1];
 
-    let mut parser = Parser::new(&source_code);
-    let program = parser.parse();
+    let source_code = match fs::read_to_string(input_path) {
+        Ok(source_code) => source_code,
+        Err(error) => {
+            eprintln!("error: can not read `{}`: {}", input_path, error);
+            process::exit(1);
+        }
+    };
 
-    let mut type_checker = TypeChecker::new(program.clone());
-    type_checker.check();
+    let strings = StringArena::default();
+    let program = standard_library::parse_with_bundled_modules(&source_code, &strings)
+        .unwrap_or_else(|diagnostics| report(&diagnostics, input_path, &source_code));
 
-    let mut compiler = match compiler::Compiler::new(program, output_path) {
-        Ok(c) => c,
-        Err(e) => panic!("{:?}", e),
-    };
+    let allows = program.allows.clone();
+    let mut type_checker = TypeChecker::new(program);
+    emit(&lint_levels.apply(type_checker.check(), &allows), input_path, &source_code);
+    let program = type_checker.into_typed_program();
+
+    let mut compiler = compiler::Compiler::new(program, output_path)
+        .unwrap_or_else(|e| report(&[e.diagnostic(output_path)], input_path, &source_code));
 
-    let _ = compiler.compile();
+    compiler.freestanding = freestanding;
+    compiler.emit_ir = emit_ir;
+    compiler.allocate_registers = allocate_registers;
+    if let Err(e) = compiler.compile() {
+        report(&[e.diagnostic(output_path)], input_path, &source_code);
+    }
 
     for asm in compiler.asm {
         let _ = compiler.file_handler.write_all(asm.as_bytes());
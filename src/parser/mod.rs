@@ -1,433 +1,690 @@
 use crate::{
     lexer::Lexer,
     shared::{
-        meta::AnyMetadata, parser_nodes::{
-            Argument, BinaryExpression, BlockStatement, CallExpression, Expression, ExpressionStatement, ExternFunctionStatement, FieldAccessExpression, FunctionDeclaration, FunctionSignatureDeclaration, LiteralExpression, Program, ReturnStatement, Statement, StructDeclaration, StructField, StructLiteralExpression, StructLiteralField, TypeDeclarationStatement, TypedExpression, UnaryExpression, VarDeclarationStatement, VariableReassignmentStatement
+        arena::StringArena, errors::{codes, Diagnostic}, lints::{Allow, Lint}, meta::AnyMetadata, parser_nodes::{
+            Argument, AsmOperand, AsmOperandKind, AsmStatement, BinaryExpression, BlockStatement, CallExpression, ConstDeclarationStatement, DeferStatement, ErrorStatement, Expression, ExpressionStatement, ExternFunctionStatement, FieldAccessExpression, FunctionDeclaration, FunctionSignatureDeclaration, LiteralExpression, PrintExpression, Program, ReturnStatement, Statement, StructDeclaration, StructField, StructLiteralExpression, StructLiteralField, SyscallExpression, TypeDeclarationStatement, TypedExpression, UnaryExpression, VarDeclarationStatement, VariableReassignmentStatement
         }, positions::Position, tokens::{
             Token,
             TokenType
         }
     }
 };
-use core::panic;
-use std::{collections::HashMap, iter::Peekable};
+use std::{collections::HashMap, iter::Peekable, vec::IntoIter};
+
+type ParseResult<T> = Result<T, Diagnostic>;
 
 #[allow(dead_code)]
 pub struct Parser<'a> {
-    pub lexer: Peekable<Lexer<'a>>,
+    pub tokens: Peekable<IntoIter<Token<AnyMetadata<'a>>>>,
     pub previous_token: Option<Token<AnyMetadata<'a>>>,
-    pub custom_types: HashMap<String, TypedExpression>
+    pub custom_types: HashMap<String, TypedExpression>,
+    // reported by `parse` before anything else, the parser then sees no tokens
+    lex_error: Option<Diagnostic>,
+    // just past the last character, where running out of tokens is reported
+    end_of_file: Position,
+    // syntax errors recorded so far, parsing carries on after each of them
+    errors: Vec<Diagnostic>,
+    // `{` consumed minus `}` consumed, tells recovery which braces a broken
+    // statement left open
+    open_braces: usize,
+    // `// nerv: allow(...)` comments with the line each one covers
+    directives: Vec<(Token<AnyMetadata<'a>>, usize)>
 }
 
 
 #[allow(dead_code)]
 impl<'a> Parser<'a> {
-    pub fn new(source_code: &'a str) -> Self {
-        let lexer = Lexer::new(source_code).peekable();
+    pub fn new(source_code: &'a str, strings: &'a StringArena) -> Self {
+        let (tokens, lex_error) = match Lexer::new(source_code, strings).tokenize() {
+            Ok(tokens) => (tokens, None),
+            Err(error) => (vec![], Some(error))
+        };
+        // allow comments are not part of the grammar. one after code covers its
+        // own line, one on a line of its own covers the next token's line
+        let (directives, tokens): (Vec<_>, Vec<_>) = tokens.into_iter()
+            .partition(|t| t.token_type == TokenType::LintDirective);
+        let directives = directives.into_iter().map(|directive| {
+            let next = tokens.partition_point(|t| t.span.start < directive.span.start);
+            let line = match (next.checked_sub(1).map(|i| &tokens[i]), tokens.get(next)) {
+                (Some(before), _) if before.position.line == directive.position.line => before.position.line,
+                (_, Some(after)) => after.position.line,
+                _ => directive.position.line
+            };
+            (directive, line)
+        }).collect();
+        let trimmed = source_code.trim_end();
+        let end_of_file = Position {
+            line: trimmed.lines().count().max(1),
+            column: trimmed.lines().last().map_or(0, |line| line.chars().count()) + 1
+        };
         Self {
-            lexer,
+            tokens: tokens.into_iter().peekable(),
             previous_token: None,
-            custom_types: HashMap::new()
+            custom_types: HashMap::new(),
+            lex_error,
+            end_of_file,
+            errors: vec![],
+            open_braces: 0,
+            directives
+        }
+    }
+
+    pub fn parse(&mut self) -> Result<Program<'a>, Vec<Diagnostic>> {
+        let (program, errors) = self.parse_partial();
+        if errors.is_empty() {
+            Ok(program)
+        } else {
+            Err(errors)
         }
     }
 
-    pub fn parse(&mut self) -> Program<'a> {
+    // every syntax error in the source along with what could be made of it,
+    // statements that did not parse are left in as `Statement::Error`.
+    pub fn parse_partial(&mut self) -> (Program<'a>, Vec<Diagnostic>) {
+        if let Some(error) = self.lex_error.take() {
+            return (Program { stmts: vec![], allows: vec![], bundled: 0 }, vec![error]);
+        }
         let mut stmts: Vec<Statement> = vec![];
-        while self.lexer.peek().is_some() {
-            stmts.push(self.parse_statement());
+        while self.tokens.peek().is_some() {
+            stmts.push(self.parse_statement_or_recover());
         }
 
-        Program {
-            stmts
+        let allows = self.allows();
+        (Program {
+            stmts,
+            allows,
+            bundled: 0
+        }, std::mem::take(&mut self.errors))
+    }
+
+    fn allows(&mut self) -> Vec<Allow> {
+        let mut allows = vec![];
+        let reported = self.errors.len();
+        for (directive, line) in std::mem::take(&mut self.directives) {
+            let AnyMetadata::String { value } = directive.meta_data else {
+                continue;
+            };
+            for name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+                match Lint::from_name(name) {
+                    Some(lint) => allows.push(Allow { lint, line }),
+                    None => self.errors.push(Diagnostic::error(codes::UNKNOWN_LINT, format!("unknown lint `{}`", name))
+                        .at(directive.position, "in this allow comment")
+                        .width(2)
+                        .note(format!("the lints are {}", Lint::names())))
+                }
+            }
+        }
+        // in source order with the syntax errors
+        if self.errors.len() != reported {
+            self.errors.sort_by_key(|e| e.primary.as_ref().map(|l| (l.position.line, l.position.column)));
         }
+        allows
     }
 
-    fn tt_to_typed(&mut self, t: Token<AnyMetadata>) -> TypedExpression {
+    fn parse_statement_or_recover(&mut self) -> Statement<'a> {
+        let position = self.tokens.peek().map_or(self.end_of_file, |t| t.position);
+        let remaining = self.tokens.len();
+        let open_braces = self.open_braces;
+        match self.parse_statement() {
+            Ok(statement) => statement,
+            Err(error) => {
+                self.record(error);
+                // a statement that failed on its very first token still has to move the parser
+                if self.tokens.len() == remaining {
+                    self.advance();
+                }
+                self.synchronize(open_braces);
+                Statement::Error(ErrorStatement { position })
+            }
+        }
+    }
+
+    // an error at the same spot as the previous one is a consequence of it,
+    // like every unclosed block reporting the end of the file.
+    fn record(&mut self, error: Diagnostic) {
+        let position = |d: &Diagnostic| d.primary.as_ref().map(|l| l.position);
+        if self.errors.last().is_none_or(|last| position(last) != position(&error)) {
+            self.errors.push(error);
+        }
+    }
+
+    // skips to where the next statement most likely starts: past a `;`, before
+    // the `}` of the enclosing block or before a keyword that starts one. braces
+    // the broken statement opened are skipped up to their `}`, a declaration
+    // keyword ends the skipping even when some of them were never closed.
+    fn synchronize(&mut self, open_braces: usize) {
+        let mut depth = self.open_braces.saturating_sub(open_braces);
+        while let Some(token) = self.tokens.peek() {
+            match token.token_type {
+                TokenType::At | TokenType::Struct | TokenType::Extern | TokenType::Type => {
+                    self.open_braces = open_braces;
+                    return;
+                }
+                TokenType::Dec | TokenType::Const | TokenType::Return | TokenType::Defer | TokenType::Asm if depth == 0 => return,
+                TokenType::Semicolon if depth == 0 => {
+                    self.advance();
+                    return;
+                }
+                // closes the enclosing block, at the top level it is a stray one
+                TokenType::RightBrace if depth == 0 => {
+                    if open_braces == 0 {
+                        self.advance();
+                    }
+                    return;
+                }
+                TokenType::RightBrace => {
+                    self.advance();
+                    depth -= 1;
+                    if depth == 0 {
+                        let _ = self.match_tokens(&[TokenType::Semicolon]);
+                        return;
+                    }
+                }
+                TokenType::LeftBrace => {
+                    self.advance();
+                    depth += 1;
+                }
+                _ => {
+                    self.advance();
+                }
+            }
+        }
+    }
+
+    // every token the parser takes goes through here
+    fn advance(&mut self) -> Option<Token<AnyMetadata<'a>>> {
+        let token = self.tokens.next()?;
+        match token.token_type {
+            TokenType::LeftBrace => self.open_braces += 1,
+            TokenType::RightBrace => self.open_braces = self.open_braces.saturating_sub(1),
+            _ => {}
+        }
+        self.previous_token = Some(token);
+        Some(token)
+    }
+
+    fn tt_to_typed(&mut self, t: Token<AnyMetadata<'a>>) -> ParseResult<TypedExpression> {
         match t.token_type {
             TokenType::DVoid => {
-                TypedExpression::Void
+                Ok(TypedExpression::Void)
             },
             TokenType::DInteger => {
-                TypedExpression::Integer
+                Ok(TypedExpression::Integer)
             },
             TokenType::DString => {
-                TypedExpression::String
+                Ok(TypedExpression::String)
             },
             TokenType::DFloat => {
-                TypedExpression::Float
+                Ok(TypedExpression::Float)
+            },
+            TokenType::DBool => {
+                Ok(TypedExpression::Bool)
             },
             TokenType::Identifier => {
-                if let AnyMetadata::Identifier { value } = t.meta_data {
-                    return self.custom_types.get(value).expect("Unknown Type, you might want to define it before hand.").clone();
-                }
-                panic!("UNREACHABLE")
+                let name = Self::identifier_value(&t);
+                self.custom_types.get(name).cloned().ok_or_else(|| {
+                    Diagnostic::error(codes::UNKNOWN_TYPE, format!("unknown type `{}`", name))
+                        .at(t.position, "not declared")
+                        .note("types have to be declared with `type` or `struct` before they are used")
+                })
             },
             TokenType::Ampersand => {
-                let pointer_to = self.parse_type_expression();
-                TypedExpression::Pointer(Box::new(pointer_to))
+                let pointer_to = self.parse_type_expression()?;
+                Ok(TypedExpression::Pointer(Box::new(pointer_to)))
             },
             TokenType::Fun => {
-                self.consume(TokenType::LeftParen);
+                self.consume(TokenType::LeftParen)?;
                 let mut args = vec![];
+                let mut variadic = false;
                 if !self.match_tokens(&[TokenType::RightParen]) {
                     loop {
-                        args.push(self.parse_type_expression());
+                        if self.match_tokens(&[TokenType::Ellipsis]) {
+                            variadic = true;
+                            self.consume(TokenType::RightParen)?;
+                            break;
+                        }
+                        args.push(self.parse_type_expression()?);
                         if self.match_tokens(&[TokenType::Comma]) {
                             continue;
                         }
-                        self.consume(TokenType::RightParen);
+                        self.consume(TokenType::RightParen)?;
                         break;
                     }
                 }
-                self.consume(TokenType::Arrow);
-                let return_type = self.parse_type_expression();
-                TypedExpression::Function {
+                self.consume(TokenType::Arrow)?;
+                let return_type = self.parse_type_expression()?;
+                Ok(TypedExpression::Function {
                     args,
-                    return_type: Box::new(return_type)
-                }
+                    return_type: Box::new(return_type),
+                    variadic
+                })
             }
             _ => {
-                panic!("");
+                Err(Self::unexpected_token(&t, "a type"))
             }
         }
     }
 
-    pub fn compile_user_defined_type(&self, user_defined_type: TypedExpression) -> TypedExpression {
-        match user_defined_type {
-            TypedExpression::UserDefinedTypeAlias { identifier, .. } => {
-                if let Some(ut) = self.custom_types.get(&identifier) {
-                    ut.clone()
-                } else {
-                    panic!("Unknown Type");
-                }
-            },
-            TypedExpression::Pointer(x) => {
-                TypedExpression::Pointer(Box::new(self.compile_user_defined_type(*x)))
+    // doc comments are trivia, they are collected here and handed to the
+    // declaration that follows them. anything else just drops them.
+    fn parse_doc_comments(&mut self) -> ParseResult<Vec<&'a str>> {
+        let mut docs = vec![];
+        while self.match_tokens(&[TokenType::DocComment]) {
+            if let Some(Token { meta_data: AnyMetadata::String { value }, .. }) = self.previous_token {
+                docs.push(value);
             }
-            _ => user_defined_type
         }
+        if let Some(doc) = self.previous_token.filter(|_| !docs.is_empty())
+            && matches!(self.tokens.peek().map(|t| t.token_type), None | Some(TokenType::RightBrace)) {
+            return Err(Diagnostic::error(codes::DANGLING_DOC_COMMENT, "doc comment is not followed by anything it could document")
+                .at(doc.position, "")
+                .width(3)
+                .note("use `//` for a plain comment"));
+        }
+        Ok(docs)
     }
 
-    fn parse_statement(&mut self) -> Statement<'a> {
-        if let Some(current_token) = self.lexer.peek() {
-            let starting_position = current_token.position;
-            match current_token.token_type {
-                TokenType::Dec => {
-
-                    // Variable Declaration Statement
-                    self.consume(TokenType::Dec);
-                    if let Some(t) = self.lexer.next() {
-                        if t.token_type != TokenType::Identifier {
-                            panic!("Expected a Identifier after 'dec' {:?}", self.previous_token);
-                        }
-                        if let AnyMetadata::Identifier{ value } = t.meta_data {
-                            let data_type = self.parse_type_expression();
-                            let after_data_type = self.lexer.next();
-                            if let Some(Token { token_type: TokenType::Equal, .. }) = after_data_type {
-                                let expr = self.parse_expression();
-                                self.consume(TokenType::Semicolon);
-                                return Statement::VarDeclaration(VarDeclarationStatement {
-                                    value: expr,
-                                    name: value,
-                                    variable_type: data_type,
-                                    position: starting_position
-                                });
-                            } else {
-                                println!("UNEXPECTED: {:?}", after_data_type);
-                            }
-                        }
+    fn parse_statement(&mut self) -> ParseResult<Statement<'a>> {
+        let docs = self.parse_doc_comments()?;
+        let Some(current_token) = self.tokens.peek() else {
+            return Err(self.unexpected("a statement"));
+        };
+        let starting_position = current_token.position;
+        match current_token.token_type {
+            TokenType::Dec => {
+                // Variable Declaration Statement
+                self.consume(TokenType::Dec)?;
+                let name = self.identifier("a variable name after `dec`")?;
+                // `dec x = expr;` leaves the type to the type checker
+                let variable_type = match self.tokens.peek() {
+                    Some(Token { token_type: TokenType::Equal, .. }) => None,
+                    _ => Some(self.parse_type_expression()?)
+                };
+                self.consume(TokenType::Equal)?;
+                let value = self.parse_expression()?;
+                self.consume(TokenType::Semicolon)?;
+                Ok(Statement::VarDeclaration(VarDeclarationStatement {
+                    value,
+                    name,
+                    variable_type,
+                    position: starting_position
+                }))
+            }
+            TokenType::Const => {
+                self.consume(TokenType::Const)?;
+                let name = self.identifier("a constant name after `const`")?;
+                let const_type = self.parse_type_expression()?;
+                self.consume(TokenType::Equal)?;
+                let value = self.parse_expression()?;
+                self.consume(TokenType::Semicolon)?;
+                Ok(Statement::ConstDeclaration(ConstDeclarationStatement {
+                    name,
+                    value,
+                    const_type,
+                    position: starting_position,
+                    docs
+                }))
+            }
+            TokenType::At => {
+                self.consume(TokenType::At)?;
+                self.parse_function(starting_position, docs)
+            }
+            TokenType::LeftBrace => {
+                self.parse_block_statement()
+            }
+            // blocks take their own `}`, this one has nothing to close
+            TokenType::RightBrace => {
+                Err(Diagnostic::error(codes::UNEXPECTED_TOKEN, "unexpected `}`")
+                    .at(starting_position, "there is no block to close"))
+            }
+            TokenType::Asm => {
+                self.consume(TokenType::Asm)?;
+                self.parse_asm_statement(starting_position)
+            }
+            TokenType::Defer => {
+                self.consume(TokenType::Defer)?;
+                let body = self.parse_statement()?;
+                Ok(Statement::DeferStatement(DeferStatement {
+                    body: Box::new(body),
+                    position: starting_position
+                }))
+            }
+            TokenType::Return => {
+                self.consume(TokenType::Return)?;
+                let value = if self.match_tokens(&[TokenType::Semicolon]) {
+                    None
+                } else {
+                    let value = self.parse_expression()?;
+                    self.consume(TokenType::Semicolon)?;
+                    Some(value)
+                };
+                Ok(Statement::ReturnStatement(ReturnStatement {
+                    value,
+                    position: starting_position
+                }))
+            }
+            TokenType::Extern => {
+                self.consume(TokenType::Extern)?;
+                let fx_name = self.identifier("a function name after `extern`")?;
+
+                self.consume(TokenType::LeftParen)?;
+                let mut args: Vec<TypedExpression> = vec![];
+                let mut variadic = false;
+                while !self.match_tokens(&[TokenType::RightParen]) {
+                    if self.match_tokens(&[TokenType::Ellipsis]) {
+                        // `...` has to be the last thing in the parameter list
+                        variadic = true;
+                        self.consume(TokenType::RightParen)?;
+                        break;
                     }
-                }
-                TokenType::At => {
-                    self.consume(TokenType::At);
-                    return self.parse_function(starting_position);
-                }
-                TokenType::Return => {
-                    self.consume(TokenType::Return);
-                    let value = self.parse_expression();
-                    self.consume(TokenType::Semicolon);
-                    return Statement::ReturnStatement(ReturnStatement {
-                        value,
-                        position: starting_position
-                    });
-                }
-                TokenType::Extern => {
-                    self.consume(TokenType::Extern);
-                    let fx_name = if self.match_tokens(&[TokenType::Identifier]) {
-                        let fx_token = self.previous_token.clone().unwrap();
-                        if let AnyMetadata::Identifier { value } = fx_token.meta_data {
-                            value
-                        } else {
-                            panic!("Expected {:?} got {:?}", TokenType::Identifier, fx_token.token_type);
-                        }
-                    } else {
-                        panic!("Expected Identifier got {:?}", self.previous_token);
-                    };
-
-                    self.consume(TokenType::LeftParen);
-                    let mut args: Vec<TypedExpression> = vec![];
-                    while !self.match_tokens(&[TokenType::RightParen]) {
-                        args.push(self.parse_type_expression());
-                        if self.match_tokens(&[TokenType::Comma]) {
-                            continue;
-                        }
+                    args.push(self.parse_type_expression()?);
+                    if self.list_separator(TokenType::RightParen)? {
+                        break;
                     }
-
-                    let return_type: TypedExpression = self.parse_type_expression();
-
-                    self.consume(TokenType::Semicolon);
-                    let fx_sig = FunctionSignatureDeclaration {
-                        fx_name,
-                        args,
-                        return_type,
-                    };
-                    return Statement::ExternStatement(ExternFunctionStatement {
-                        fx_name,
-                        fx_sig
-                    });
                 }
 
-                TokenType::Type => {
-                    self.consume(TokenType::Type);
-                    self.consume(TokenType::Identifier);
-                    let alias = self.previous_token.expect("UNREACHABLE");
-                    self.consume(TokenType::Colon);
-                    if self.match_tokens(&[TokenType::DInteger, TokenType::DString, TokenType::DFloat, TokenType::DVoid, TokenType::Identifier]){
-                        let alias_for = self.tt_to_typed(self.previous_token.expect("UNREACHABLE"));
-                        self.consume(TokenType::Semicolon);
-                        let t = TypeDeclarationStatement {
-                            alias,
-                            alias_for: alias_for.clone()
-                        };
-                        if let AnyMetadata::Identifier { value } = alias.meta_data {
-                            self.custom_types.insert(value.to_string(), TypedExpression::UserDefinedTypeAlias { identifier: value.to_string(), alias_for: Box::new(alias_for) });
-                        };
-                        return Statement::TypeDeclarationStatement(t)
-                    } else {
-                        panic!("Unexpected Type: {:?} expected a predefined type", self.lexer.peek());
-                    }
+                let return_type: TypedExpression = self.parse_type_expression()?;
+
+                self.consume(TokenType::Semicolon)?;
+                let fx_sig = FunctionSignatureDeclaration {
+                    fx_name,
+                    args,
+                    return_type,
+                    variadic
+                };
+                Ok(Statement::ExternStatement(ExternFunctionStatement {
+                    fx_name,
+                    fx_sig,
+                    position: starting_position,
+                    docs
+                }))
+            }
+
+            TokenType::Type => {
+                self.consume(TokenType::Type)?;
+                let alias = self.consume(TokenType::Identifier)?;
+                self.consume(TokenType::Colon)?;
+                if !self.match_tokens(&[TokenType::DInteger, TokenType::DString, TokenType::DFloat, TokenType::DBool, TokenType::DVoid, TokenType::Identifier]) {
+                    return Err(self.unexpected("a type"));
                 }
-                TokenType::Struct => {
-                    self.consume(TokenType::Struct);
-                    self.consume(TokenType::Identifier);
-                    let name_token = self.previous_token.expect("UNREACHABLE");
-                    let name = if let AnyMetadata::Identifier { value } = name_token.meta_data {
-                        value
-                    } else {
-                        panic!("Expected identifier");
-                    };
-                    self.consume(TokenType::LeftBrace);
-                    let mut fields = vec![];
-                    while !self.match_tokens(&[TokenType::RightBrace]) {
-                        self.consume(TokenType::Identifier);
-                        let field_name_token = self.previous_token.expect("UNREACHABLE");
-                        let field_name = if let AnyMetadata::Identifier { value } = field_name_token.meta_data {
-                            value
-                        } else {
-                            panic!("Expected identifier");
-                        };
-                        self.consume(TokenType::Colon);
-                        let field_type = self.parse_type_expression();
-                        fields.push(StructField {
-                            name: field_name,
-                            field_type
-                        });
-                        if self.match_tokens(&[TokenType::Comma]) {
-                            continue;
-                        }
-                    }
-                    self.custom_types.insert(name.to_string(), TypedExpression::Struct { name: name.to_string() });
-                    return Statement::StructDeclaration(StructDeclaration {
-                        name,
-                        fields
+                let alias_for = self.tt_to_typed(self.previous_token.expect("UNREACHABLE"))?;
+                self.consume(TokenType::Semicolon)?;
+                let value = Self::identifier_value(&alias);
+                self.custom_types.insert(value.to_string(), TypedExpression::UserDefinedTypeAlias { identifier: value.to_string(), alias_for: Box::new(alias_for.clone()) });
+                Ok(Statement::TypeDeclarationStatement(TypeDeclarationStatement {
+                    alias,
+                    alias_for,
+                    docs
+                }))
+            }
+            TokenType::Struct => {
+                let position = self.consume(TokenType::Struct)?.position;
+                let name = self.identifier("a struct name after `struct`")?;
+                // known before the fields, so a broken field does not make every later use an error too
+                self.custom_types.insert(name.to_string(), TypedExpression::Struct { name: name.to_string() });
+                self.consume(TokenType::LeftBrace)?;
+                let mut fields = vec![];
+                while !self.match_tokens(&[TokenType::RightBrace]) {
+                    let field_docs = self.parse_doc_comments()?;
+                    let field_name = self.identifier("a field name")?;
+                    let field_position = self.previous_token.expect("UNREACHABLE").position;
+                    self.consume(TokenType::Colon)?;
+                    let field_type = self.parse_type_expression()?;
+                    fields.push(StructField {
+                        name: field_name,
+                        field_type,
+                        position: field_position,
+                        docs: field_docs
                     });
+                    if self.list_separator(TokenType::RightBrace)? {
+                        break;
+                    }
                 }
+                Ok(Statement::StructDeclaration(StructDeclaration {
+                    name,
+                    fields,
+                    position,
+                    docs
+                }))
+            }
 
-                _ => {
-                    let expr = self.parse_expression();
-                    if self.match_tokens(&[TokenType::Equal]) {
-                        let rhs = self.parse_expression();
-                        self.consume(TokenType::Semicolon);
-                        if expr.is_lvalue() {
-                            return Statement::VariableReassignmentStatement(VariableReassignmentStatement {
-                                lhs: expr,
-                                rhs
-                            })
-                        } else {
-                            let position = self.previous_token.unwrap().position;
-                            panic!("Trying to assign to something which can not be assigned, not an lvalue: {}:{}", position.line, position.column);
-                        }
+            _ => {
+                let expr = self.parse_expression()?;
+                if self.match_tokens(&[TokenType::Equal]) {
+                    if !expr.is_lvalue() {
+                        return Err(Diagnostic::error(codes::INVALID_ASSIGNMENT_TARGET, "invalid left-hand side of assignment")
+                            .at(expr.position(), "can not be assigned to")
+                            .note("only variables, struct fields and dereferenced pointers can be assigned to"));
                     }
-                    self.consume(TokenType::Semicolon);
-                    return Statement::ExpressionStatement(ExpressionStatement {
-                        value: expr,
-                        position: starting_position
-                    });
+                    let rhs = self.parse_expression()?;
+                    self.consume(TokenType::Semicolon)?;
+                    return Ok(Statement::VariableReassignmentStatement(VariableReassignmentStatement {
+                        lhs: expr,
+                        rhs
+                    }));
                 }
+                self.consume(TokenType::Semicolon)?;
+                Ok(Statement::ExpressionStatement(ExpressionStatement {
+                    value: expr,
+                    position: starting_position
+                }))
             }
         }
-        panic!("UNREACHABLE");
     }
 
-    fn parse_type_expression(&mut self) -> TypedExpression {
-        let current_token = self.lexer.next().unwrap();
+    fn parse_type_expression(&mut self) -> ParseResult<TypedExpression> {
+        let Some(current_token) = self.advance() else {
+            return Err(self.unexpected("a type"));
+        };
         self.tt_to_typed(current_token)
     }
 
-    fn consume(&mut self, tt: TokenType) {
-        if let Some(Token { token_type, position, meta_data, .. }) = self.lexer.peek() {
-            if *token_type == tt {
-                self.previous_token = self.lexer.next();
-            } else {
-                panic!("Expected a {:?} found {:?} {:?}:{:?}", tt, *token_type, position.line, position.column);
-            }
+    fn consume(&mut self, tt: TokenType) -> ParseResult<Token<AnyMetadata<'a>>> {
+        if self.match_tokens(&[tt]) {
+            Ok(self.previous_token.expect("UNREACHABLE"))
+        } else {
+            Err(self.unexpected(tt.describe()))
         }
     }
 
-    fn parse_function(&mut self, starting_position: Position) -> Statement<'a> {
-        let name = if self.match_tokens(&[TokenType::Identifier]) {
-            if let Some(prev) = &self.previous_token {
-                if let AnyMetadata::Identifier{ value } = &prev.meta_data {
-                    *value
-                } else {
-                    panic!("Expected identifier metadata");
-                }
-            } else {
-                panic!("No previous token");
-            }
+    fn identifier(&mut self, expected: &str) -> ParseResult<&'a str> {
+        if self.match_tokens(&[TokenType::Identifier]) {
+            Ok(Self::identifier_value(&self.previous_token.expect("UNREACHABLE")))
         } else {
-            panic!("Expected function name identifier");
-        };
-
-        self.consume(TokenType::LeftParen);
-        let mut args = Vec::new();
+            Err(self.unexpected(expected))
+        }
+    }
 
-        while !self.match_tokens(&[TokenType::RightParen]) {
-            args.push(self.parse_args());
-        }
-
-        let return_type = self.parse_type_expression();
-
-        let body = self.parse_block_statement();
-        if let Statement::BlockStatement(body) = body {
-            Statement::FunctionDeclaration(FunctionDeclaration {
-                name,
-                arity: args.len(),
-                arguments: args,
-                body,
-                return_type,
-                position: starting_position,
-                variable_size: 0
-            })
+    fn identifier_value(token: &Token<AnyMetadata<'a>>) -> &'a str {
+        if let AnyMetadata::Identifier { value } = token.meta_data {
+            value
         } else {
-            panic!("UNREACHABLE");
+            panic!("identifier token without a name {:?}", token);
         }
-
     }
 
-    fn parse_block_statement(&mut self) -> Statement<'a> {
-        self.consume(TokenType::LeftBrace);
-        let mut stmts = vec![];
-        let current_position = self.lexer.peek().unwrap().position.clone();
-        while !self.match_tokens(&[TokenType::RightBrace]) {
-            stmts.push(self.parse_statement());
+    // the token the parser could not make sense of, or the end of the file
+    // when it ran out of them.
+    fn unexpected(&mut self, expected: &str) -> Diagnostic {
+        match self.tokens.peek() {
+            Some(token) => Self::unexpected_token(token, expected),
+            None => Diagnostic::error(codes::UNEXPECTED_EOF, format!("expected {}, found the end of the file", expected))
+                .at(self.end_of_file, format!("expected {}", expected))
+                .width(1)
         }
+    }
 
-        Statement::BlockStatement(BlockStatement { values: stmts, position: current_position })
+    fn unexpected_token(token: &Token<AnyMetadata>, expected: &str) -> Diagnostic {
+        Diagnostic::error(codes::UNEXPECTED_TOKEN, format!("expected {}, found {}", expected, token.token_type.describe()))
+            .at(token.position, format!("expected {}", expected))
     }
 
-    fn parse_args(&mut self) -> Argument<'a> {
-        let arg_type = self.parse_type_expression();
-        let previous_token = self.previous_token.clone().expect("UNREACHABLE");
+    fn parse_function(&mut self, starting_position: Position, docs: Vec<&'a str>) -> ParseResult<Statement<'a>> {
+        let name = self.identifier("a function name after `@`")?;
 
-        if self.match_tokens(&[TokenType::Identifier]) {
-            let prev = self.previous_token.clone().expect("UNREACHABLE");
-            if let AnyMetadata::Identifier { value: name } = &prev.meta_data {
-                let _ = self.match_tokens(&[TokenType::Comma]);
-                Argument {
-                    name,
-                    arg_type
-                }
-            } else {
-                panic!("Expected identifier metadata");
+        self.consume(TokenType::LeftParen)?;
+        let mut args = Vec::new();
+
+        while !self.match_tokens(&[TokenType::RightParen]) {
+            args.push(self.parse_args()?);
+            if self.list_separator(TokenType::RightParen)? {
+                break;
             }
-        } else {
-            panic!("Expected an Identifier {}:{}", previous_token.position.line, previous_token.position.column);
         }
 
+        let return_type = self.parse_type_expression()?;
+
+        let body = self.parse_block()?;
+        Ok(Statement::FunctionDeclaration(FunctionDeclaration {
+            name,
+            arity: args.len(),
+            arguments: args,
+            body,
+            return_type,
+            position: starting_position,
+            docs
+        }))
+    }
+
+    fn parse_asm_statement(&mut self, starting_position: Position) -> ParseResult<Statement<'a>> {
+        self.consume(TokenType::LeftBrace)?;
+        let mut template = vec![];
+        let mut operands = vec![];
+        let mut clobbers = vec![];
+        while !self.match_tokens(&[TokenType::RightBrace]) {
+            if self.match_tokens(&[TokenType::String]) {
+                template.push(self.previous_string());
+            } else {
+                let expected = "a string, `in`, `out`, `inout`, `mem` or `clobber` inside asm";
+                let keyword = self.identifier(expected)?;
+                let keyword_token = self.previous_token.expect("UNREACHABLE");
+                let kind = match keyword {
+                    "in" => AsmOperandKind::In,
+                    "out" => AsmOperandKind::Out,
+                    "inout" => AsmOperandKind::InOut,
+                    "mem" => AsmOperandKind::Memory,
+                    "clobber" => {
+                        self.consume(TokenType::LeftParen)?;
+                        while !self.match_tokens(&[TokenType::RightParen]) {
+                            let register = self.consume(TokenType::String)?;
+                            clobbers.push((self.previous_string(), register.position));
+                            if self.list_separator(TokenType::RightParen)? {
+                                break;
+                            }
+                        }
+                        if self.list_separator(TokenType::RightBrace)? {
+                            break;
+                        }
+                        continue;
+                    }
+                    _ => {
+                        return Err(Diagnostic::error(codes::UNEXPECTED_TOKEN, format!("expected {}, found `{}`", expected, keyword))
+                            .at(keyword_token.position, "not an operand kind"));
+                    }
+                };
+                let register = if kind == AsmOperandKind::Memory {
+                    None
+                } else {
+                    self.consume(TokenType::LeftParen)?;
+                    self.consume(TokenType::String)?;
+                    let register = self.previous_string();
+                    self.consume(TokenType::RightParen)?;
+                    Some(register)
+                };
+                let value = self.parse_expression()?;
+                operands.push(AsmOperand {
+                    kind,
+                    register,
+                    value,
+                    position: keyword_token.position
+                });
+            }
+            if self.list_separator(TokenType::RightBrace)? {
+                break;
+            }
+        }
+        Ok(Statement::AsmStatement(AsmStatement {
+            template,
+            operands,
+            clobbers,
+            position: starting_position
+        }))
     }
 
-    pub fn calculate_size_from_type(&self, t: &TypedExpression) -> usize {
-        match t {
-            TypedExpression::Integer => 4,
-            TypedExpression::String => 8,
-            TypedExpression::Float => 8,
-            TypedExpression::Void => 1,
-            TypedExpression::Pointer(_) => 8,
-            TypedExpression::UserDefinedTypeAlias{ identifier: _, alias_for: u } => self.calculate_size_from_type(u),
-            TypedExpression::Struct { .. } => 8,
-            TypedExpression::Function { .. } => 8,
+    fn previous_string(&self) -> &'a str {
+        if let Some(Token { meta_data: AnyMetadata::String { value }, .. }) = self.previous_token {
+            value
+        } else {
+            panic!("Expected a string literal {:?}", self.previous_token);
         }
     }
 
-    pub fn calculate_variables_size(&self, bs: &BlockStatement<'a>) -> usize {
-        let mut size = 8;
-        for stmt in &bs.values {
-            if let Statement::VarDeclaration(VarDeclarationStatement { variable_type, .. }) = stmt {
-                size += self.calculate_size_from_type(variable_type);
+    fn parse_block_statement(&mut self) -> ParseResult<Statement<'a>> {
+        self.parse_block().map(Statement::BlockStatement)
+    }
+
+    fn parse_block(&mut self) -> ParseResult<BlockStatement<'a>> {
+        let left_brace = self.consume(TokenType::LeftBrace)?;
+        let mut stmts = vec![];
+        let current_position = self.tokens.peek().map_or(left_brace.position, |t| t.position);
+        while !self.match_tokens(&[TokenType::RightBrace]) {
+            if self.tokens.peek().is_none() {
+                return Err(self.unexpected("`}`")
+                    .label(left_brace.position, "to close this block"));
             }
+            stmts.push(self.parse_statement_or_recover());
         }
-        size
+
+        let end = self.previous_token.expect("UNREACHABLE").position;
+        Ok(BlockStatement { values: stmts, position: current_position, end })
     }
 
-    fn parse_expression(&mut self) -> Expression<'a> {
+    fn parse_args(&mut self) -> ParseResult<Argument<'a>> {
+        let arg_type = self.parse_type_expression()?;
+        let name = self.identifier("a parameter name after its type")?;
+        Ok(Argument {
+            name,
+            arg_type
+        })
+    }
+
+    fn parse_expression(&mut self) -> ParseResult<Expression<'a>> {
         self.equality()
     }
 
-    fn equality(&mut self) -> Expression<'a> {
+    fn equality(&mut self) -> ParseResult<Expression<'a>> {
         self.create_binary_expr(vec![TokenType::BangEqual, TokenType::EqualEqual], Self::comparison)
     }
 
-    fn comparison(&mut self) -> Expression<'a> {
+    fn comparison(&mut self) -> ParseResult<Expression<'a>> {
         self.create_binary_expr(vec![TokenType::Greater, TokenType::GreaterEqual, TokenType::Less, TokenType::LessEqual], Self::term)
     }
 
-    fn term(&mut self) -> Expression<'a> {
+    fn term(&mut self) -> ParseResult<Expression<'a>> {
         self.create_binary_expr(vec![TokenType::Minus, TokenType::Plus], Self::factor)
     }
 
-    fn factor(&mut self) -> Expression<'a> {
+    fn factor(&mut self) -> ParseResult<Expression<'a>> {
         self.create_binary_expr(vec![TokenType::Slash, TokenType::Star], Self::unary)
     }
     
-    fn unary(&mut self) -> Expression<'a> {
+    fn unary(&mut self) -> ParseResult<Expression<'a>> {
         if self.match_tokens(&[TokenType::Bang, TokenType::Minus, TokenType::Ampersand, TokenType::Star]) {
             let operator = self.previous_token.expect("No Previous token given.");
-            return Expression::Unary(UnaryExpression{
+            return Ok(Expression::Unary(UnaryExpression{
                 operator,
-                value: Box::from(self.unary())
-            });
+                value: Box::from(self.unary()?)
+            }));
         }
         self.postfix()
     }
 
-    fn postfix(&mut self) -> Expression<'a> {
-        let mut expr = self.primary();
+    fn postfix(&mut self) -> ParseResult<Expression<'a>> {
+        let mut expr = self.primary()?;
         loop {
             if self.match_tokens(&[TokenType::LeftParen]) {
                 let call_position = self.previous_token.expect("UNREACHABLE").position;
-                let mut args = vec![];
-                while let Some(t) = self.lexer.peek() {
-                    if t.token_type == TokenType::RightParen {
-                        break;
-                    }
-                    let arg = self.parse_expression();
-                    args.push(arg);
-                    if !self.match_tokens(&[TokenType::Comma]) {
-                        continue;
-                    }
-                }
-                let _ = self.lexer.next();
+                let args = self.arguments()?;
                 expr = Expression::Call(CallExpression {
                     callee: Box::new(expr),
                     arguments: args,
@@ -436,85 +693,105 @@ impl<'a> Parser<'a> {
                 continue;
             }
             if self.match_tokens(&[TokenType::Dot]) {
-                self.consume(TokenType::Identifier);
-                let field_token = self.previous_token.expect("UNREACHABLE");
-                let field = if let AnyMetadata::Identifier { value } = field_token.meta_data {
-                    value
-                } else {
-                    panic!("Expected identifier after '.'");
-                };
+                let field = self.identifier("a field name after `.`")?;
                 expr = Expression::FieldAccess(FieldAccessExpression {
                     target: Box::new(expr),
                     field,
-                    position: field_token.position
+                    position: self.previous_token.expect("UNREACHABLE").position
                 });
                 continue;
             }
             break;
         }
-        expr
+        Ok(expr)
     }
 
-    fn primary(&mut self) -> Expression<'a> {
-        if let Some(token) = self.lexer.peek() {
-            self.previous_token = Some(*token);
-            let tok = self.lexer.next().expect("UNREACHABLE");
-            let pos = tok.position;
-            if tok.token_type == TokenType::Pound {
-                self.consume(TokenType::Identifier);
-                let struct_name_token = self.previous_token.expect("UNREACHABLE");
-                let struct_name = if let AnyMetadata::Identifier { value } = struct_name_token.meta_data {
-                    value
-                } else {
-                    panic!("Expected struct name after '#'");
-                };
-                self.consume(TokenType::LeftBrace);
-                let mut fields = vec![];
-                while !self.match_tokens(&[TokenType::RightBrace]) {
-                    self.consume(TokenType::Identifier);
-                    let field_name_token = self.previous_token.expect("UNREACHABLE");
-                    let field_name = if let AnyMetadata::Identifier { value } = field_name_token.meta_data {
-                        value
-                    } else {
-                        panic!("Expected field name");
-                    };
-                    self.consume(TokenType::Colon);
-                    let field_value = self.parse_expression();
-                    fields.push(StructLiteralField {
-                        name: field_name,
-                        value: field_value
-                    });
-                    if self.match_tokens(&[TokenType::Comma]) {
-                        continue;
-                    }
-                }
-                return Expression::StructLiteral(StructLiteralExpression {
-                    name: struct_name,
-                    fields,
-                    position: pos
+    fn primary(&mut self) -> ParseResult<Expression<'a>> {
+        let literals = [TokenType::Integer, TokenType::Float, TokenType::Character, TokenType::String, TokenType::Identifier, TokenType::Void, TokenType::Nil, TokenType::True, TokenType::False];
+        // left in place when it can not start an expression, it is often where the next statement starts
+        let Some(tok) = self.tokens.peek().copied()
+            .filter(|t| literals.contains(&t.token_type) || [TokenType::Syscall, TokenType::Print, TokenType::Println, TokenType::Pound].contains(&t.token_type)) else {
+            return Err(self.unexpected("an expression"));
+        };
+        self.advance();
+        let pos = tok.position;
+        if tok.token_type == TokenType::Syscall {
+            self.consume(TokenType::LeftParen)?;
+            let arguments = self.arguments()?;
+            return Ok(Expression::Syscall(SyscallExpression {
+                arguments,
+                position: pos
+            }));
+        }
+        if tok.token_type == TokenType::Print || tok.token_type == TokenType::Println {
+            self.consume(TokenType::LeftParen)?;
+            let arguments = self.arguments()?;
+            return Ok(Expression::Print(PrintExpression {
+                arguments,
+                newline: tok.token_type == TokenType::Println,
+                position: pos
+            }));
+        }
+        if tok.token_type == TokenType::Pound {
+            let struct_name = self.identifier("a struct name after `#`")?;
+            self.consume(TokenType::LeftBrace)?;
+            let mut fields = vec![];
+            while !self.match_tokens(&[TokenType::RightBrace]) {
+                let field_name = self.identifier("a field name")?;
+                self.consume(TokenType::Colon)?;
+                let field_value = self.parse_expression()?;
+                fields.push(StructLiteralField {
+                    name: field_name,
+                    value: field_value
                 });
+                if self.list_separator(TokenType::RightBrace)? {
+                    break;
+                }
             }
-            if ([TokenType::Integer, TokenType::String, TokenType::Identifier, TokenType::String, TokenType::Void]).contains(&tok.token_type) {
-                Expression::Literal(LiteralExpression {
-                    value: tok
-                })
-            } else {
-                panic!("INVALID Primary Type: {:?} {}:{} ", tok, pos.line, pos.column);
+            return Ok(Expression::StructLiteral(StructLiteralExpression {
+                name: struct_name,
+                fields,
+                position: pos
+            }));
+        }
+        Ok(Expression::Literal(LiteralExpression {
+            value: tok
+        }))
+    }
+
+    // comma separated expressions up to the closing `)`, the `(` is already consumed
+    fn arguments(&mut self) -> ParseResult<Vec<Expression<'a>>> {
+        let mut arguments = vec![];
+        while !self.match_tokens(&[TokenType::RightParen]) {
+            arguments.push(self.parse_expression()?);
+            if self.list_separator(TokenType::RightParen)? {
+                break;
             }
-        } else {
-            panic!("UNREACHABLE");
         }
+        Ok(arguments)
+    }
+
+    // what follows an item of a comma separated list, true when it was the
+    // `close` ending the list. a trailing comma is fine.
+    fn list_separator(&mut self, close: TokenType) -> ParseResult<bool> {
+        if self.match_tokens(&[TokenType::Comma]) {
+            return Ok(false);
+        }
+        if self.match_tokens(&[close]) {
+            return Ok(true);
+        }
+        Err(self.unexpected(&format!("`,` or {}", close.describe())))
     }
 
     fn create_binary_expr(
         &mut self,
         match_tokens: Vec<TokenType>,
-        precedent_function: fn(&mut Self) -> Expression<'a>,
-    ) -> Expression<'a> {
-        let mut expr = precedent_function(self);
+        precedent_function: fn(&mut Self) -> ParseResult<Expression<'a>>,
+    ) -> ParseResult<Expression<'a>> {
+        let mut expr = precedent_function(self)?;
         while self.match_tokens(&match_tokens) {
             let operator = self.previous_token.expect("Token must exist here");
-            let right_expression = precedent_function(self);
+            let right_expression = precedent_function(self)?;
 
             expr = Expression::Binary(BinaryExpression {
                 left: Box::new(expr),
@@ -522,14 +799,158 @@ impl<'a> Parser<'a> {
                 right: Box::new(right_expression),
             });
         }
-        expr
+        Ok(expr)
     }
 
     fn match_tokens(&mut self, to_match: &[TokenType]) -> bool {
-        if let Some(tok) = self.lexer.peek() && to_match.contains(&tok.token_type) {
-            self.previous_token = self.lexer.next();
+        if let Some(tok) = self.tokens.peek() && to_match.contains(&tok.token_type) {
+            self.advance();
             return true;
         }
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Parser;
+    use crate::shared::{arena::StringArena, errors::{codes, Diagnostic}, parser_nodes::Statement};
+
+    #[test]
+    fn doc_comments_attach_to_the_next_declaration() {
+        let source = "/// a point\n/// on the grid\nstruct Point {\n    /// across\n    x: int,\n    y: int\n}\n\n// not documentation\n@main() int {\n    /// dropped, locals are not documented\n    dec a int = 1;\n    return a;\n}\n";
+        let strings = StringArena::default();
+        let program = Parser::new(source, &strings).parse().unwrap();
+        let Statement::StructDeclaration(point) = &program.stmts[0] else { panic!("expected a struct") };
+        assert_eq!(point.docs, [" a point", " on the grid"]);
+        assert_eq!(point.fields[0].docs, [" across"]);
+        assert!(point.fields[1].docs.is_empty());
+        let Statement::FunctionDeclaration(main) = &program.stmts[1] else { panic!("expected a function") };
+        assert!(main.docs.is_empty());
+    }
+
+    fn errors(source: &str) -> Vec<Diagnostic> {
+        Parser::new(source, &StringArena::default()).parse().unwrap_err()
+    }
+
+    #[test]
+    fn allow_comments_cover_their_line_or_the_next() {
+        let source = "@main() int {\n    // nerv: allow(unused_variable, shadowed_variable)\n\n    dec a int = 1;\n    dec b int = 2; // nerv: allow(unused_variable)\n    return 0;\n}\n";
+        let strings = StringArena::default();
+        let program = Parser::new(source, &strings).parse().unwrap();
+        let allows: Vec<(&str, usize)> = program.allows.iter().map(|allow| (allow.lint.name(), allow.line)).collect();
+        assert_eq!(allows, [("unused_variable", 4), ("shadowed_variable", 4), ("unused_variable", 5)]);
+        let errors = errors("// nerv: allow(unused_varible)\n@main() int {\n    return 0;\n}\n");
+        assert_eq!((errors[0].code, errors[0].message.as_str()), (codes::UNKNOWN_LINT, "unknown lint `unused_varible`"));
+    }
+
+    fn error(source: &str) -> Diagnostic {
+        let mut errors = errors(source);
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        errors.remove(0)
+    }
+
+    fn lines(errors: &[Diagnostic]) -> Vec<usize> {
+        errors.iter().map(|e| e.primary.as_ref().unwrap().position.line).collect()
+    }
+
+    #[test]
+    fn dangling_doc_comment() {
+        let error = error("@main() int {\n    return 0;\n    /// nothing below\n}\n");
+        assert_eq!(error.code, codes::DANGLING_DOC_COMMENT);
+        assert_eq!(error.primary.unwrap().position.line, 3);
+    }
+
+    #[test]
+    fn unexpected_token() {
+        let error = error("@main() int {\n    dec x int = 1\n    return x;\n}\n");
+        assert_eq!(error.code, codes::UNEXPECTED_TOKEN);
+        assert_eq!(error.message, "expected `;`, found `return`");
+        let position = error.primary.unwrap().position;
+        assert_eq!((position.line, position.column), (3, 5));
+    }
+
+    #[test]
+    fn unexpected_end_of_file() {
+        let error = error("@main() int {\n    return 0;\n");
+        assert_eq!(error.code, codes::UNEXPECTED_EOF);
+        assert_eq!(error.labels.len(), 1);
+    }
+
+    #[test]
+    fn invalid_assignment_target() {
+        let error = error("@main() int {\n    1 + 2 = 3;\n    return 0;\n}\n");
+        assert_eq!(error.code, codes::INVALID_ASSIGNMENT_TARGET);
+    }
+
+    #[test]
+    fn every_syntax_error_is_reported() {
+        let source = "struct P {\n    x int\n}\n\n@f() int {\n    dec a int = 1 +;\n    return a\n}\n\n@main() int {\n    dec p P = #P{x: 1 2};\n    { dec b int = ; }\n    return 0;\n}\n";
+        assert_eq!(lines(&errors(source)), [2, 6, 8, 11, 12]);
+    }
+
+    #[test]
+    fn broken_statements_become_error_nodes() {
+        let strings = StringArena::default();
+        let (program, errors) = Parser::new("@main() int {\n    dec a int = ;\n    dec b int = 2;\n    return b;\n}\n", &strings).parse_partial();
+        assert_eq!(errors.len(), 1);
+        let Statement::FunctionDeclaration(main) = &program.stmts[0] else { panic!("expected a function") };
+        assert!(matches!(main.body.values[..], [Statement::Error(_), Statement::VarDeclaration(_), Statement::ReturnStatement(_)]));
+    }
+
+    #[test]
+    fn unclosed_blocks_report_once() {
+        let error = error("@main() int {\n    {\n        {\n            dec x int = 1;\n");
+        assert_eq!(error.code, codes::UNEXPECTED_EOF);
+    }
+
+    #[test]
+    fn a_broken_header_skips_the_body() {
+        let errors = errors("@f(int) int {\n    dec a int = 1;\n    return a;\n}\n\n@main() int {\n    return 0\n}\n");
+        assert_eq!(lines(&errors), [1, 8]);
+    }
+
+    #[test]
+    fn print_arguments_are_separated_by_commas() {
+        let error = error("@main() int {\n    println(1 2 3);\n    return 0;\n}\n");
+        assert_eq!(error.message, "expected `,` or `)`, found an integer literal");
+        assert_eq!(error.primary.unwrap().position.column, 15);
+    }
+
+    #[test]
+    fn syscall_arguments_are_separated_by_commas() {
+        let error = error("@main() int {\n    syscall(60 0);\n    return 0;\n}\n");
+        assert_eq!(error.message, "expected `,` or `)`, found an integer literal");
+        assert_eq!(error.primary.unwrap().position.column, 16);
+    }
+
+    #[test]
+    fn every_list_is_separated_by_commas() {
+        let lists = [
+            ("extern f(int int) int;\n", "`)`", "`int`"),
+            ("extern printf(string ...) int;\n", "`)`", "`...`"),
+            ("@f(int a int b) int {\n    return a;\n}\n", "`)`", "`int`"),
+            ("struct P {\n    x: int\n    y: int\n}\n", "`}`", "an identifier"),
+            ("struct P {\n    x: int,\n    y: int\n}\n\n@main() int {\n    dec p P = #P{x: 1 y: 2};\n    return 0;\n}\n", "`}`", "an identifier")
+        ];
+        for (source, close, found) in lists {
+            let error = error(source);
+            assert_eq!(error.message, format!("expected `,` or {}, found {}", close, found), "{source}");
+        }
+        assert!(Parser::new("extern printf(string, ...) int;\n\nstruct P {\n    x: int,\n    y: int,\n}\n", &StringArena::default()).parse().is_ok());
+    }
+
+    #[test]
+    fn asm_items_are_separated_by_commas() {
+        let template = error("@main() int {\n    asm {\n        \"nop\"\n        \"nop\"\n    }\n    return 0;\n}\n");
+        assert_eq!(template.message, "expected `,` or `}`, found a string literal");
+        let clobbers = error("@main() int {\n    asm {\n        \"nop\",\n        clobber(\"rax\" \"rdx\")\n    }\n    return 0;\n}\n");
+        assert_eq!(clobbers.message, "expected `,` or `)`, found a string literal");
+    }
+
+    #[test]
+    fn stray_closing_brace() {
+        let error = error("@main() int {\n    return 0;\n}\n}\n");
+        assert_eq!(error.message, "unexpected `}`");
+    }
+}
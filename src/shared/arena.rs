@@ -0,0 +1,39 @@
+use std::cell::{Cell, OnceCell};
+
+// the text of string literals with escapes in them. tokens are Copy and borrow
+// what they point at, so a decoded literal has to live next to the source it
+// came from. strings are only ever added, each chunk is twice the size of the
+// one before it and a stored string stays where it is until the arena is
+// dropped.
+#[derive(Debug)]
+pub struct StringArena {
+    chunk: Box<[OnceCell<String>]>,
+    used: Cell<usize>,
+    next: OnceCell<Box<StringArena>>
+}
+
+impl StringArena {
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            chunk: (0..capacity).map(|_| OnceCell::new()).collect(),
+            used: Cell::new(0),
+            next: OnceCell::new()
+        }
+    }
+
+    pub fn alloc(&self, text: String) -> &str {
+        let mut arena = self;
+        while arena.used.get() == arena.chunk.len() {
+            arena = arena.next.get_or_init(|| Box::new(Self::with_capacity(arena.chunk.len() * 2)));
+        }
+        let cell = &arena.chunk[arena.used.get()];
+        arena.used.set(arena.used.get() + 1);
+        cell.get_or_init(|| text)
+    }
+}
+
+impl Default for StringArena {
+    fn default() -> Self {
+        Self::with_capacity(16)
+    }
+}
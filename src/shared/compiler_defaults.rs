@@ -12,3 +12,10 @@ pub const SIZES: Sizes = Sizes {
     d_ptr: 8,
 };
 
+
+// registers inline assembly may bind operands to or clobber, rsp and rbp hold
+// the frame and are left out on purpose.
+pub const ASM_REGISTERS: [&str; 14] = [
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi",
+    "r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15",
+];
@@ -1,10 +1,17 @@
+use std::fmt::Write;
+
+use crate::shared::positions::Position;
+
 #[derive(Debug)]
 pub enum LexerError {
     CalledNextAfterExhaustion,
     IllegalCharacterAccess,
-    IllegalNumber,
-    UnexpectedEof,
-    IllegalKeyword
+    IllegalNumber(Position),
+    IllegalEscape(Position),
+    UnterminatedString(Position),
+    UnterminatedComment(Position),
+    IllegalCharacterLiteral(Position),
+    UnexpectedCharacter(Position, char)
 }
 
 #[allow(dead_code)]
@@ -12,9 +19,242 @@ pub enum LexerError {
 pub enum CompilerError {
     IllegalOutputFile,
     CanNotWrite,
-    UnknownDataType,
-    UnsupportedOperator,
-    UnexpectedStatement,
-    InvalidLValue
+    // the lowering ones are at the position of what was being lowered
+    UnknownDataType(Position),
+    UnsupportedOperator(Position),
+    UnexpectedStatement(Position),
+    InvalidLValue(Position),
+    // what isn't available on this target, and where the program uses it
+    UnsupportedTarget(&'static str, Option<Position>),
+    MissingEntryPoint,
+    // instruction selection asked for something x86_64 doesn't have
+    InvalidRegister(String),
+    InvalidOperandSize(usize)
+}
+
+impl CompilerError {
+    pub fn diagnostic(&self, output_path: &str) -> Diagnostic {
+        let unsupported = |message: &str, position: Position| Diagnostic::error(codes::UNSUPPORTED_PROGRAM, message)
+            .at(position, "")
+            .note("the type checker accepted this, so this is a bug in the code generator");
+        match self {
+            Self::IllegalOutputFile | Self::CanNotWrite => Diagnostic::error(codes::CAN_NOT_WRITE_OUTPUT, format!("can not write the output file `{}`", output_path)),
+            Self::UnknownDataType(position) => unsupported("the code generator can not hold a value of this type", *position),
+            Self::UnsupportedOperator(position) => unsupported("the code generator does not support this operator", *position),
+            Self::UnexpectedStatement(position) => unsupported("the code generator does not support this here", *position),
+            Self::InvalidLValue(position) => unsupported("the code generator can not find where this is stored", *position),
+            Self::UnsupportedTarget(feature, position) => {
+                let diagnostic = Diagnostic::error(codes::UNSUPPORTED_TARGET, format!("{} is only supported on linux", feature));
+                match position {
+                    Some(position) => diagnostic.at(*position, "")
+                        .note("it is lowered onto the bundled standard library, which is only built for linux"),
+                    None => diagnostic
+                }
+            }
+            Self::MissingEntryPoint => Diagnostic::error(codes::MISSING_ENTRY_POINT, "no `main` function to start the program from")
+                .note("--no-libc programs start at `@main() int`, its return value is the exit status"),
+            Self::InvalidRegister(register) => Diagnostic::error(codes::UNSUPPORTED_PROGRAM, format!("the code generator picked `{}`, which is not a general purpose register", register))
+                .note("this is a bug in the code generator"),
+            Self::InvalidOperandSize(size) => Diagnostic::error(codes::UNSUPPORTED_PROGRAM, format!("the code generator can not move a {} byte value", size))
+                .note("this is a bug in the code generator")
+        }
+    }
+}
+
+// every diagnostic carries one of these, grouped by the stage reporting it:
+// E00xx lexer, E01xx parser, E02xx type checker, E03xx code generation.
+// warnings are numbered separately as W0xxx.
+pub mod codes {
+    pub const UNEXPECTED_CHARACTER: &str = "E0001";
+    pub const INVALID_ESCAPE: &str = "E0002";
+    pub const UNTERMINATED_STRING: &str = "E0003";
+    pub const INVALID_NUMBER: &str = "E0004";
+    pub const INVALID_CHARACTER_LITERAL: &str = "E0005";
+    pub const UNTERMINATED_COMMENT: &str = "E0006";
+
+    pub const UNEXPECTED_TOKEN: &str = "E0100";
+    pub const UNEXPECTED_EOF: &str = "E0101";
+    pub const UNKNOWN_TYPE: &str = "E0102";
+    pub const INVALID_ASSIGNMENT_TARGET: &str = "E0103";
+    pub const DANGLING_DOC_COMMENT: &str = "E0104";
+    pub const UNKNOWN_LINT: &str = "E0105";
+
+    pub const MISMATCHED_TYPES: &str = "E0200";
+    pub const UNKNOWN_VARIABLE: &str = "E0201";
+    pub const REDECLARED_VARIABLE: &str = "E0202";
+    pub const WRONG_ARGUMENT_COUNT: &str = "E0203";
+    pub const NOT_CALLABLE: &str = "E0204";
+    pub const INVALID_OPERAND: &str = "E0205";
+    pub const UNKNOWN_STRUCT: &str = "E0206";
+    pub const UNKNOWN_FIELD: &str = "E0207";
+    pub const MISSING_FIELDS: &str = "E0208";
+    pub const NOT_PRINTABLE: &str = "E0209";
+    pub const INVALID_CONSTANT: &str = "E0210";
+    pub const INVALID_ASM_OPERAND: &str = "E0211";
+    pub const RETURN_IN_DEFER: &str = "E0212";
+    pub const INVALID_SYSCALL: &str = "E0213";
+    pub const MISSING_RETURN: &str = "E0214";
+    pub const CAN_NOT_INFER: &str = "E0215";
+    pub const REDEFINED_ITEM: &str = "E0216";
+    pub const RETURN_OUTSIDE_FUNCTION: &str = "E0217";
+    pub const RECURSIVE_STRUCT: &str = "E0218";
+    pub const STRUCT_BY_VALUE: &str = "E0219";
+
+    pub const MISSING_ENTRY_POINT: &str = "E0300";
+    pub const UNSUPPORTED_TARGET: &str = "E0301";
+    pub const CAN_NOT_WRITE_OUTPUT: &str = "E0302";
+    pub const UNSUPPORTED_PROGRAM: &str = "E0303";
+
+    // one per lint, a lint denied with `-D` is reported as an error under the same code
+    pub const UNREACHABLE_CODE: &str = "W0001";
+    pub const DANGLING_POINTER: &str = "W0002";
+    pub const UNUSED_VARIABLE: &str = "W0003";
+    pub const UNUSED_FUNCTION: &str = "W0004";
+    pub const UNUSED_EXTERN: &str = "W0005";
+    pub const SHADOWED_VARIABLE: &str = "W0006";
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning
+}
+
+// underlines `width` characters from `position`, a width of 0 underlines
+// whatever token starts there.
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub position: Position,
+    pub width: usize,
+    pub message: String
 }
 
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    // None for problems with the program as a whole, like a missing `main`.
+    // boxed to keep the Err side of every parser and checker Result small
+    pub primary: Option<Box<Label>>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            primary: None,
+            labels: vec![],
+            notes: vec![]
+        }
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(code, message)
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    pub fn at(mut self, position: Position, message: impl Into<String>) -> Self {
+        self.primary = Some(Box::new(Label { position, width: 0, message: message.into() }));
+        self
+    }
+
+    // an explicit width for the primary label
+    pub fn width(mut self, width: usize) -> Self {
+        if let Some(primary) = self.primary.as_mut() {
+            primary.width = width;
+        }
+        self
+    }
+
+    pub fn label(mut self, position: Position, message: impl Into<String>) -> Self {
+        self.labels.push(Label { position, width: 0, message: message.into() });
+        self
+    }
+
+    pub fn note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    // rustc style, the offending lines with the primary label underlined by `^`
+    // and the secondary ones by `-`:
+    //
+    // error[E0201]: unknown variable `y`
+    //  --> main.nerv:5:12
+    //   |
+    // 5 |     return y;
+    //   |            ^ not declared in this scope
+    pub fn render(&self, path: &str, source: &str) -> String {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning"
+        };
+        let mut out = format!("{}[{}]: {}\n", severity, self.code, self.message);
+        let lines: Vec<&str> = source.lines().collect();
+        let mut labels: Vec<(&Label, char)> = self.primary.iter().map(|l| (l.as_ref(), '^'))
+            .chain(self.labels.iter().map(|l| (l, '-')))
+            .filter(|(l, _)| l.position.line >= 1 && l.position.line <= lines.len())
+            .collect();
+        labels.sort_by_key(|(l, _)| (l.position.line, l.position.column));
+        let gutter = labels.iter().map(|(l, _)| l.position.line.to_string().len()).max().unwrap_or(0);
+        let pad = " ".repeat(gutter);
+
+        if let Some(primary) = &self.primary {
+            let _ = writeln!(out, "{}--> {}:{}:{}", pad, path, primary.position.line, primary.position.column);
+        }
+        if !labels.is_empty() {
+            let _ = writeln!(out, "{} |", pad);
+        }
+        let mut previous_line = None;
+        for (label, marker) in &labels {
+            let line = lines[label.position.line - 1];
+            if previous_line != Some(label.position.line) {
+                if previous_line.is_some_and(|previous| previous + 1 < label.position.line) {
+                    let _ = writeln!(out, "{}...", pad);
+                }
+                let _ = writeln!(out, "{:>gutter$} | {}", label.position.line, line);
+                previous_line = Some(label.position.line);
+            }
+            // columns count characters, tabs are kept so the markers line up with the source
+            let before: String = line.chars().take(label.position.column.saturating_sub(1))
+                .map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+            let rest: String = line.chars().skip(label.position.column.saturating_sub(1)).collect();
+            let width = if label.width > 0 { label.width } else { token_width(&rest) };
+            let underline = marker.to_string().repeat(width);
+            let _ = writeln!(out, "{} | {}{}{}{}", pad, before, underline,
+                if label.message.is_empty() { "" } else { " " }, label.message);
+        }
+        if !self.notes.is_empty() && !labels.is_empty() {
+            let _ = writeln!(out, "{} |", pad);
+        }
+        for note in &self.notes {
+            let _ = writeln!(out, "{} = note: {}", pad, note);
+        }
+        out
+    }
+}
+
+// the width of the token at the start of `text`, good enough to underline a
+// word, a literal or an operator without lexing the line again.
+fn token_width(text: &str) -> usize {
+    let mut chars = text.chars();
+    match chars.next() {
+        None => 1,
+        Some('"') => chars.position(|c| c == '"').map_or(text.chars().count(), |end| end + 2),
+        Some(c) if c.is_alphanumeric() || c == '_' => 1 + chars.take_while(|c| c.is_alphanumeric() || *c == '_').count(),
+        Some('-') if text.starts_with("->") => 2,
+        Some('.') if text.starts_with("...") => 3,
+        Some(_) => 1
+    }
+}
@@ -0,0 +1,131 @@
+use crate::shared::errors::{codes, Diagnostic, Severity};
+
+// warnings the checker can emit, each can be allowed, warned about or denied
+// with `-A`, `-W` and `-D` on the command line, and allowed for a single line
+// with a `// nerv: allow(name)` comment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lint {
+    UnreachableCode,
+    DanglingPointer,
+    UnusedVariable,
+    UnusedFunction,
+    UnusedExtern,
+    ShadowedVariable
+}
+
+pub const LINTS: [Lint; 6] = [
+    Lint::UnreachableCode,
+    Lint::DanglingPointer,
+    Lint::UnusedVariable,
+    Lint::UnusedFunction,
+    Lint::UnusedExtern,
+    Lint::ShadowedVariable
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny
+}
+
+impl Lint {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::UnreachableCode => "unreachable_code",
+            Self::DanglingPointer => "dangling_pointer",
+            Self::UnusedVariable => "unused_variable",
+            Self::UnusedFunction => "unused_function",
+            Self::UnusedExtern => "unused_extern",
+            Self::ShadowedVariable => "shadowed_variable"
+        }
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::UnreachableCode => codes::UNREACHABLE_CODE,
+            Self::DanglingPointer => codes::DANGLING_POINTER,
+            Self::UnusedVariable => codes::UNUSED_VARIABLE,
+            Self::UnusedFunction => codes::UNUSED_FUNCTION,
+            Self::UnusedExtern => codes::UNUSED_EXTERN,
+            Self::ShadowedVariable => codes::SHADOWED_VARIABLE
+        }
+    }
+
+    // shadowing is how nerv scopes are meant to be used, it's opt in
+    fn default_level(self) -> Level {
+        match self {
+            Self::ShadowedVariable => Level::Allow,
+            _ => Level::Warn
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        LINTS.into_iter().find(|lint| lint.name() == name)
+    }
+
+    fn from_code(code: &str) -> Option<Self> {
+        LINTS.into_iter().find(|lint| lint.code() == code)
+    }
+
+    pub fn names() -> String {
+        LINTS.map(|lint| format!("`{}`", lint.name())).join(", ")
+    }
+}
+
+// a `// nerv: allow(name)` comment, it covers the line it ends or, on a line
+// of its own, the next line with code on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Allow {
+    pub lint: Lint,
+    pub line: usize
+}
+
+#[derive(Debug, Clone)]
+pub struct LintLevels {
+    levels: [Level; LINTS.len()]
+}
+
+impl Default for LintLevels {
+    fn default() -> Self {
+        Self { levels: LINTS.map(Lint::default_level) }
+    }
+}
+
+impl LintLevels {
+    // `warnings` sets every lint at once, later settings win. false for a
+    // name that isn't a lint
+    pub fn set(&mut self, name: &str, level: Level) -> bool {
+        if name == "warnings" {
+            self.levels = [level; LINTS.len()];
+            return true;
+        }
+        let Some(lint) = Lint::from_name(name) else {
+            return false;
+        };
+        self.levels[lint as usize] = level;
+        true
+    }
+
+    // drops the allowed lints and turns the denied ones into errors, anything
+    // that isn't a lint passes through untouched
+    pub fn apply(&self, diagnostics: Vec<Diagnostic>, allows: &[Allow]) -> Vec<Diagnostic> {
+        diagnostics.into_iter().filter_map(|mut diagnostic| {
+            let Some(lint) = Lint::from_code(diagnostic.code).filter(|_| !diagnostic.is_error()) else {
+                return Some(diagnostic);
+            };
+            let line = diagnostic.primary.as_ref().map(|l| l.position.line);
+            if allows.iter().any(|allow| allow.lint == lint && Some(allow.line) == line) {
+                return None;
+            }
+            match self.levels[lint as usize] {
+                Level::Allow => None,
+                Level::Warn => Some(diagnostic),
+                Level::Deny => {
+                    diagnostic.severity = Severity::Error;
+                    Some(diagnostic.note(format!("`{}` is denied on the command line", lint.name())))
+                }
+            }
+        }).collect()
+    }
+}
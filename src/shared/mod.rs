@@ -1,6 +1,9 @@
 pub mod tokens;
+pub mod arena;
 pub mod positions;
 pub mod errors;
 pub mod meta;
 pub mod parser_nodes;
 pub mod compiler_defaults;
+pub mod lints;
+pub mod typed_nodes;
@@ -1,4 +1,6 @@
-use super::{meta::AnyMetadata, positions::Position, tokens::{ Token, TokenType }};
+use std::fmt;
+
+use super::{lints::Allow, meta::AnyMetadata, positions::Position, tokens::{ Token, TokenType }};
 
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
@@ -8,7 +10,9 @@ pub enum Expression<'a> {
     Literal(LiteralExpression<'a>),
     Call(CallExpression<'a>),
     StructLiteral(StructLiteralExpression<'a>),
-    FieldAccess(FieldAccessExpression<'a>)
+    FieldAccess(FieldAccessExpression<'a>),
+    Syscall(SyscallExpression<'a>),
+    Print(PrintExpression<'a>)
 }
 
 impl Expression<'_> {
@@ -17,6 +21,20 @@ impl Expression<'_> {
                 | Self::Unary(UnaryExpression { operator: Token { token_type: TokenType::Star, ..}, .. })
                 | Self::FieldAccess(_))
     }
+
+    // where the expression starts in the source, used to point diagnostics at it
+    pub fn position(&self) -> Position {
+        match self {
+            Self::Binary(b) => b.left.position(),
+            Self::Unary(u) => u.operator.position,
+            Self::Literal(l) => l.value.position,
+            Self::Call(c) => c.callee.position(),
+            Self::StructLiteral(s) => s.position,
+            Self::FieldAccess(f) => f.target.position(),
+            Self::Syscall(s) => s.position,
+            Self::Print(p) => p.position
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -48,6 +66,24 @@ pub struct CallExpression<'a> {
     pub position: Position
 }
 
+// `print(a, b)` writes its arguments back to back, `println(a, b)` separates
+// them with spaces and ends the line.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct PrintExpression<'a> {
+    pub arguments: Vec<Expression<'a>>,
+    pub newline: bool,
+    pub position: Position
+}
+
+// `syscall(nr, a1, ..., a6)`, the first argument is the syscall number
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct SyscallExpression<'a> {
+    pub arguments: Vec<Expression<'a>>,
+    pub position: Position
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct StructLiteralField<'a> {
@@ -76,10 +112,13 @@ pub struct FieldAccessExpression<'a> {
 pub struct Program<'a> {
     //TODO: until we reach statements this will hold expressions, but after that it shall hold
     //statements
-    pub stmts: Vec<Statement<'a>>
+    pub stmts: Vec<Statement<'a>>,
+    pub allows: Vec<Allow>,
+    // how many of `stmts` come from the bundled modules, they are never linted
+    pub bundled: usize
 }
 
-#[allow(dead_code)]
+#[allow(dead_code, clippy::enum_variant_names)]
 #[derive(Debug, Clone)]
 pub enum Statement<'a> {
     VarDeclaration(VarDeclarationStatement<'a>),
@@ -90,25 +129,37 @@ pub enum Statement<'a> {
     ExternStatement(ExternFunctionStatement<'a>),
     VariableReassignmentStatement(VariableReassignmentStatement<'a>),
     TypeDeclarationStatement(TypeDeclarationStatement<'a>),
-    StructDeclaration(StructDeclaration<'a>)
+    StructDeclaration(StructDeclaration<'a>),
+    DeferStatement(DeferStatement<'a>),
+    AsmStatement(AsmStatement<'a>),
+    ConstDeclaration(ConstDeclarationStatement<'a>),
+    Error(ErrorStatement)
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct TypeDeclarationStatement<'a> {
     pub alias: Token<AnyMetadata<'a>>,
-    pub alias_for: TypedExpression
+    pub alias_for: TypedExpression,
+    pub docs: Vec<&'a str>
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct StructField<'a> {
     pub name: &'a str,
     pub field_type: TypedExpression,
+    pub position: Position,
+    pub docs: Vec<&'a str>
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct StructDeclaration<'a> {
     pub name: &'a str,
     pub fields: Vec<StructField<'a>>,
+    pub position: Position,
+    pub docs: Vec<&'a str>
 }
 
 #[derive(Debug, Clone)]
@@ -133,7 +184,8 @@ pub struct FunctionDeclaration<'a> {
     pub body: BlockStatement<'a>,
     pub return_type: TypedExpression,
     pub position: Position,
-    pub variable_size: usize
+    // the `///` lines right above the declaration, without the slashes
+    pub docs: Vec<&'a str>
 }
 
 #[allow(dead_code)]
@@ -141,10 +193,22 @@ pub struct FunctionDeclaration<'a> {
 pub struct VarDeclarationStatement<'a> {
     pub name: &'a str,
     pub value: Expression<'a>,
-    pub variable_type: TypedExpression,
+    // None for `dec x = expr;`, the type checker infers it
+    pub variable_type: Option<TypedExpression>,
     pub position: Position
 }
 
+// `const NAME int = 1;`, a compile time integer which is substituted at every use
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct ConstDeclarationStatement<'a> {
+    pub name: &'a str,
+    pub value: Expression<'a>,
+    pub const_type: TypedExpression,
+    pub position: Position,
+    pub docs: Vec<&'a str>
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct ExpressionStatement<'a> {
@@ -156,27 +220,83 @@ pub struct ExpressionStatement<'a> {
 #[derive(Debug, Clone)]
 pub struct BlockStatement<'a> {
     pub values: Vec<Statement<'a>>,
-    pub position: Position
+    pub position: Position,
+    // the closing `}`
+    pub end: Position
 }
 
 
 #[derive(Debug, Clone)]
 pub struct ReturnStatement<'a> {
+    // None for a bare `return;` out of a void function
+    pub value: Option<Expression<'a>>,
+    pub position: Position
+}
+
+// runs `body` when the enclosing block is left, deferred statements run in
+// the reverse order of their declaration.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct DeferStatement<'a> {
+    pub body: Box<Statement<'a>>,
+    pub position: Position
+}
+
+// stands in for a statement that did not parse, so the rest of the program
+// can still be checked for more syntax errors.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct ErrorStatement {
+    pub position: Position
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsmOperandKind {
+    // `in("rdi") expr`, loaded into the register before the block
+    In,
+    // `out("rax") local`, stored back into the local after the block
+    Out,
+    // `inout("rcx") local`, both of the above
+    InOut,
+    // `mem local`, `{local}` expands to the local's stack slot
+    Memory
+}
+
+#[derive(Debug, Clone)]
+pub struct AsmOperand<'a> {
+    pub kind: AsmOperandKind,
+    pub register: Option<&'a str>,
     pub value: Expression<'a>,
     pub position: Position
 }
 
+// `asm { "rdtsc", "shl rdx, 32", out("rax") r, clobber("rdx") }`, every string is
+// emitted as one line of NASM with `{name}` replaced by the operand bound to name.
+#[derive(Debug, Clone)]
+pub struct AsmStatement<'a> {
+    pub template: Vec<&'a str>,
+    pub operands: Vec<AsmOperand<'a>>,
+    // each register with the position of its string
+    pub clobbers: Vec<(&'a str, Position)>,
+    pub position: Position
+}
+
 #[derive(Debug, Clone)]
 pub struct FunctionSignatureDeclaration<'a> {
     pub fx_name: &'a str,
     pub args: Vec<TypedExpression>,
-    pub return_type: TypedExpression
+    pub return_type: TypedExpression,
+    // set by a trailing `...`, `args` only holds the fixed prefix
+    pub variadic: bool
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct ExternFunctionStatement<'a> {
     pub fx_name: &'a str,
-    pub fx_sig: FunctionSignatureDeclaration<'a> 
+    pub fx_sig: FunctionSignatureDeclaration<'a>,
+    pub position: Position,
+    pub docs: Vec<&'a str>
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -184,6 +304,7 @@ pub enum TypedExpression {
     Integer,
     String,
     Float,
+    Bool,
     Void,
     Pointer(Box<TypedExpression>),
     Struct {
@@ -191,11 +312,39 @@ pub enum TypedExpression {
     },
     Function {
         args: Vec<TypedExpression>,
-        return_type: Box<TypedExpression>
+        return_type: Box<TypedExpression>,
+        variadic: bool
     },
     UserDefinedTypeAlias {
         identifier: String,
         alias_for: Box<TypedExpression>
+    },
+    // the type checker's stand in for an expression it already reported, it
+    // is compatible with everything so one mistake is only reported once
+    Error
+}
+
+// the way the type is spelled in nerv source, for diagnostics
+impl fmt::Display for TypedExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Integer => write!(f, "int"),
+            Self::String => write!(f, "string"),
+            Self::Float => write!(f, "float"),
+            Self::Bool => write!(f, "bool"),
+            Self::Void => write!(f, "void"),
+            Self::Pointer(to) => write!(f, "&{}", to),
+            Self::Struct { name } => write!(f, "{}", name),
+            Self::Function { args, return_type, variadic } => {
+                let mut params: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                if *variadic {
+                    params.push("...".to_string());
+                }
+                write!(f, "fn({}) -> {}", params.join(", "), return_type)
+            }
+            Self::UserDefinedTypeAlias { identifier, .. } => write!(f, "{}", identifier),
+            Self::Error => write!(f, "{{unknown}}")
+        }
     }
 }
 
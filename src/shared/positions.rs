@@ -14,3 +14,25 @@ impl Position {
         }
     }
 }
+
+// a byte range into the source, `&source[span.start..span.end]` is the token's text
+#[allow(dead_code)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize
+}
+
+#[allow(dead_code)]
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self {
+            start,
+            end
+        }
+    }
+
+    pub fn text<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.start..self.end]
+    }
+}
@@ -1,4 +1,4 @@
-use crate::shared::positions::Position;
+use crate::shared::positions::{Position, Span};
 
 #[allow(dead_code)]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -12,6 +12,7 @@ pub enum TokenType {
     Dot,
     Minus,
     Arrow,
+    Ellipsis,
     Plus,
     Semicolon,
     Slash,
@@ -39,6 +40,12 @@ pub enum TokenType {
     Void,
     Character,
 
+    // Trivia.
+    // `/// text`, kept so declarations can carry their documentation
+    DocComment,
+    // `// nerv: allow(lint, ...)`, holds what is inside the parentheses
+    LintDirective,
+
     // Ffi Stuffs
     Extern,
 
@@ -53,6 +60,7 @@ pub enum TokenType {
     Nil,
     Or,
     Print,
+    Println,
     Return,
     Super,
     This,
@@ -61,6 +69,10 @@ pub enum TokenType {
     Dec,
     While,
     Type,
+    Defer,
+    Asm,
+    Const,
+    Syscall,
 
     // Datatypes
     DInteger,
@@ -68,15 +80,89 @@ pub enum TokenType {
     DChar,
     DString,
     DVoid,
+    DBool,
 
     // End of file.
     Eof,
 }
 
+impl TokenType {
+    // how the token reads in an error message
+    pub fn describe(&self) -> &'static str {
+        match self {
+            TokenType::LeftParen => "`(`",
+            TokenType::RightParen => "`)`",
+            TokenType::LeftBrace => "`{`",
+            TokenType::RightBrace => "`}`",
+            TokenType::Comma => "`,`",
+            TokenType::Dot => "`.`",
+            TokenType::Minus => "`-`",
+            TokenType::Arrow => "`->`",
+            TokenType::Ellipsis => "`...`",
+            TokenType::Plus => "`+`",
+            TokenType::Semicolon => "`;`",
+            TokenType::Slash => "`/`",
+            TokenType::Star => "`*`",
+            TokenType::At => "`@`",
+            TokenType::Ampersand => "`&`",
+            TokenType::Pound => "`#`",
+            TokenType::Colon => "`:`",
+            TokenType::Bang => "`!`",
+            TokenType::BangEqual => "`!=`",
+            TokenType::Equal => "`=`",
+            TokenType::EqualEqual => "`==`",
+            TokenType::Greater => "`>`",
+            TokenType::GreaterEqual => "`>=`",
+            TokenType::Less => "`<`",
+            TokenType::LessEqual => "`<=`",
+            TokenType::Identifier => "an identifier",
+            TokenType::String => "a string literal",
+            TokenType::Integer => "an integer literal",
+            TokenType::Float => "a float literal",
+            TokenType::Void => "`unit`",
+            TokenType::Character => "a character literal",
+            TokenType::DocComment => "a doc comment",
+            TokenType::LintDirective => "an allow comment",
+            TokenType::Extern => "`extern`",
+            TokenType::And => "`and`",
+            TokenType::Else => "`else`",
+            TokenType::False => "`false`",
+            TokenType::Fun => "`fn`",
+            TokenType::Struct => "`struct`",
+            TokenType::For => "`for`",
+            TokenType::If => "`if`",
+            TokenType::Nil => "`nil`",
+            TokenType::Or => "`or`",
+            TokenType::Print => "`print`",
+            TokenType::Println => "`println`",
+            TokenType::Return => "`return`",
+            TokenType::Super => "`super`",
+            TokenType::This => "`this`",
+            TokenType::True => "`true`",
+            TokenType::Var => "`var`",
+            TokenType::Dec => "`dec`",
+            TokenType::While => "`while`",
+            TokenType::Type => "`type`",
+            TokenType::Defer => "`defer`",
+            TokenType::Asm => "`asm`",
+            TokenType::Const => "`const`",
+            TokenType::Syscall => "`syscall`",
+            TokenType::DInteger => "`int`",
+            TokenType::DFloat => "`float`",
+            TokenType::DChar => "`char`",
+            TokenType::DString => "`string`",
+            TokenType::DVoid => "`void`",
+            TokenType::DBool => "`bool`",
+            TokenType::Eof => "the end of the file",
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Token<T: Clone> {
     pub token_type: TokenType,
+    // line and column of the first character
     pub position: Position,
-    pub lexeme: (usize, usize),
+    pub span: Span,
     pub meta_data: T,
 }
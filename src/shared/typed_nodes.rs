@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+
+use super::{
+    compiler_defaults::SIZES, parser_nodes::{AsmOperandKind, TypedExpression}, positions::Position, tokens::TokenType
+};
+
+// what the type checker hands to the code generator. every expression carries
+// its type with aliases expanded, names are resolved to what they refer to,
+// constants are folded and struct layouts are computed once, so the two can
+// never disagree about either.
+#[derive(Debug, Clone, Default)]
+pub struct TypedProgram<'a> {
+    pub functions: Vec<Function<'a>>,
+    pub externs: Vec<&'a str>,
+    // top level `asm` blocks, emitted verbatim
+    pub global_asm: Vec<Asm<'a>>,
+    // the type of every function and extern, for the calls the code generator makes up itself
+    pub signatures: HashMap<&'a str, TypedExpression>,
+    pub layouts: Layouts<'a>
+}
+
+#[derive(Debug, Clone)]
+pub struct Function<'a> {
+    pub name: &'a str,
+    pub parameters: Vec<(&'a str, TypedExpression)>,
+    pub return_type: TypedExpression,
+    pub body: Block<'a>,
+    pub position: Position
+}
+
+#[derive(Debug, Clone)]
+pub struct Block<'a> {
+    pub stmts: Vec<Stmt<'a>>,
+    pub position: Position
+}
+
+#[derive(Debug, Clone)]
+pub enum Stmt<'a> {
+    // `dec name type = value;`, with the type inferred when it was left out
+    Let {
+        name: &'a str,
+        var_type: TypedExpression,
+        value: Expr<'a>,
+        position: Position
+    },
+    Expr(Expr<'a>),
+    Assign {
+        target: Expr<'a>,
+        value: Expr<'a>
+    },
+    Block(Block<'a>),
+    Return {
+        value: Option<Expr<'a>>,
+        position: Position
+    },
+    Defer {
+        body: Box<Stmt<'a>>,
+        position: Position
+    },
+    Asm(Asm<'a>)
+}
+
+impl<'a> Stmt<'a> {
+    // where the statement starts
+    pub fn position(&self) -> Position {
+        match self {
+            Self::Let { position, .. } | Self::Return { position, .. } | Self::Defer { position, .. } => *position,
+            Self::Expr(e) => e.position,
+            Self::Assign { target, .. } => target.position,
+            Self::Block(b) => b.position,
+            Self::Asm(a) => a.position
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Expr<'a> {
+    pub kind: ExprKind<'a>,
+    pub ty: TypedExpression,
+    pub position: Position
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub enum ExprKind<'a> {
+    // integer literals, character literals and constants
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    String(&'a str),
+    Nil,
+    Local(&'a str),
+    // a function or extern by name
+    Function(&'a str),
+    Binary {
+        operator: TokenType,
+        left: Box<Expr<'a>>,
+        right: Box<Expr<'a>>
+    },
+    AddressOf(Box<Expr<'a>>),
+    Deref(Box<Expr<'a>>),
+    Call {
+        callee: Box<Expr<'a>>,
+        arguments: Vec<Expr<'a>>
+    },
+    StructLiteral {
+        name: &'a str,
+        fields: Vec<FieldInit<'a>>
+    },
+    Field {
+        target: Box<Expr<'a>>,
+        name: &'a str,
+        offset: usize
+    },
+    Syscall(Vec<Expr<'a>>),
+    Print {
+        arguments: Vec<Expr<'a>>,
+        newline: bool
+    },
+    // stands in for an expression the checker reported, a program with one
+    // never reaches code generation
+    Error
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct FieldInit<'a> {
+    pub name: &'a str,
+    pub offset: usize,
+    pub value: Expr<'a>
+}
+
+#[derive(Debug, Clone)]
+pub struct Asm<'a> {
+    pub template: Vec<&'a str>,
+    pub operands: Vec<AsmOperand<'a>>,
+    pub clobbers: Vec<&'a str>,
+    pub position: Position
+}
+
+#[derive(Debug, Clone)]
+pub struct AsmOperand<'a> {
+    pub kind: AsmOperandKind,
+    pub register: Option<&'a str>,
+    pub value: Expr<'a>
+}
+
+impl<'a> Expr<'a> {
+    pub fn new(kind: ExprKind<'a>, ty: TypedExpression, position: Position) -> Self {
+        Self { kind, ty, position }
+    }
+
+    // the checker's stand in for something it already reported
+    pub fn error(position: Position) -> Self {
+        Self::new(ExprKind::Error, TypedExpression::Error, position)
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct StructFieldDef<'a> {
+    pub name: &'a str,
+    pub field_type: TypedExpression,
+    pub offset: usize,
+    pub size: usize,
+    pub align: usize
+}
+
+#[derive(Debug, Clone)]
+pub struct StructDef<'a> {
+    pub name: &'a str,
+    pub fields: Vec<StructFieldDef<'a>>,
+    pub size: usize,
+    pub align: usize
+}
+
+// how big every type is and where struct fields sit, only ever asked about
+// resolved types
+#[derive(Debug, Clone, Default)]
+pub struct Layouts<'a> {
+    structs: HashMap<&'a str, StructDef<'a>>
+}
+
+impl<'a> Layouts<'a> {
+    pub fn size_align(&self, t: &TypedExpression) -> (usize, usize) {
+        match t {
+            TypedExpression::Integer => (SIZES.d_int, SIZES.d_int),
+            TypedExpression::Float => (SIZES.d_float, SIZES.d_float),
+            TypedExpression::String | TypedExpression::Pointer(_) | TypedExpression::Function { .. } => (SIZES.d_ptr, SIZES.d_ptr),
+            TypedExpression::Bool | TypedExpression::Void => (SIZES.d_bool, SIZES.d_bool),
+            TypedExpression::Struct { name } => {
+                let def = self.struct_def(name);
+                (def.size, def.align)
+            }
+            TypedExpression::UserDefinedTypeAlias { .. } | TypedExpression::Error => {
+                panic!("`{}` has no layout, only resolved types do", t)
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&StructDef<'a>> {
+        self.structs.get(name)
+    }
+
+    // struct types only come out of declarations the checker has already laid
+    // out, it rejects fields that hold an unfinished struct by value
+    pub fn struct_def(&self, name: &str) -> &StructDef<'a> {
+        self.get(name).unwrap_or_else(|| panic!("Unknown struct type {}", name))
+    }
+
+    // fields are laid out in declaration order, each at the next offset its
+    // alignment allows, the struct is padded to its largest alignment
+    pub fn declare_struct(&mut self, name: &'a str, fields: Vec<(&'a str, TypedExpression)>) {
+        let mut layout_fields = vec![];
+        let mut offset: usize = 0;
+        let mut max_align = 1;
+        for (field_name, field_type) in fields {
+            let (size, align) = self.size_align(&field_type);
+            max_align = max_align.max(align);
+            offset = offset.next_multiple_of(align);
+            layout_fields.push(StructFieldDef { name: field_name, field_type, offset, size, align });
+            offset += size;
+        }
+        let def = StructDef {
+            name,
+            fields: layout_fields,
+            size: offset.next_multiple_of(max_align),
+            align: max_align
+        };
+        self.structs.insert(name, def);
+    }
+}
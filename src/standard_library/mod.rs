@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+use crate::{parser::Parser, shared::{arena::StringArena, errors::Diagnostic, parser_nodes::Program}};
+
+// nerv sources shipped inside the compiler, they are parsed ahead of the user's
+// program so whatever they declare is always in scope.
+//
+// strings.nerv: str_len, str_compare, str_copy, str_offset
+// memory.nerv:  mem_set, mem_copy (counts are in bytes)
+// format.nerv:  int_to_string, float_to_string, pointer_to_string, all write a
+//               terminated string into a caller provided buffer and return its
+//               length
+// io.nerv:      write_bytes, write_string, write_int, write_float, write_bool,
+//               write_pointer and flush. print/println are lowered onto these.
+//               STDOUT is buffered until it fills up or is flushed, STDERR is
+//               flushed on every write and other fds are written through. the
+//               buffers are separate from libc's so flush before mixing them,
+//               STDOUT is flushed when main returns.
+// alloc.nerv:   heap_alloc/heap_free on top of mmap, requests up to 4080 bytes
+//               come from eight power of two size classes (32 to 4096 bytes,
+//               16 of which are a header) carved out of 64KiB chunks and kept on
+//               free lists, anything bigger is mapped on its own and unmapped on
+//               free. heap_mapped_bytes reports what is currently mapped.
+//               `Arena` is a bump allocator over a chain of chunks, arena_reset
+//               keeps the newest chunk and arena_free returns everything at once.
+//
+// everything talks to the kernel through SYS_write, so the same modules work
+// with and without libc.
+#[cfg(target_os = "linux")]
+const BUNDLED_MODULES: &[&str] = &[
+    include_str!("syscalls.nerv"),
+    include_str!("strings.nerv"),
+    include_str!("memory.nerv"),
+    include_str!("format.nerv"),
+    include_str!("io.nerv"),
+    include_str!("alloc.nerv"),
+];
+
+#[cfg(not(target_os = "linux"))]
+const BUNDLED_MODULES: &[&str] = &[];
+
+// parses the bundled modules followed by `source_code`, the types they declare
+// are handed along so the user's program can name them. the bundled modules
+// are part of the compiler, a diagnostic in one of them is a bug in it.
+// decoded string literals are kept in `strings`.
+pub fn parse_with_bundled_modules<'a>(source_code: &'a str, strings: &'a StringArena) -> Result<Program<'a>, Vec<Diagnostic>> {
+    let mut stmts = vec![];
+    let mut custom_types = HashMap::new();
+    for module in BUNDLED_MODULES {
+        let mut parser = Parser::new(module, strings);
+        parser.custom_types = custom_types;
+        match parser.parse() {
+            Ok(program) => stmts.extend(program.stmts),
+            Err(errors) => panic!("a bundled module does not parse\n{}", errors[0].render("<bundled>", module))
+        }
+        custom_types = parser.custom_types;
+    }
+    let mut parser = Parser::new(source_code, strings);
+    parser.custom_types = custom_types;
+    let mut program = parser.parse()?;
+    program.bundled = stmts.len();
+    stmts.append(&mut program.stmts);
+    program.stmts = stmts;
+    Ok(program)
+}
@@ -1,9 +0,0 @@
-#include<stdio.h>
-
-void print_int(int a) {
-  printf("%d", a);
-}
-
-void print_string(char* s) {
-  printf("%s", s);
-}
@@ -0,0 +1,28 @@
+use crate::shared::{
+    errors::{codes, Diagnostic}, positions::Position, typed_nodes::Stmt
+};
+
+// nerv has no branches or loops yet, statements run top to bottom and nested
+// blocks always run, so the only way out of a body besides its end is a
+// `return` somewhere along that single path.
+
+// the `return` every path through `stmts` ends in, or None when the end can be
+// reached. the first statement that can never run after it gets a warning,
+// declarations only name things and aren't in the typed tree to begin with.
+pub fn diverges_at(stmts: &[Stmt], diagnostics: &mut Vec<Diagnostic>) -> Option<Position> {
+    let mut returned_at = None;
+    for stmt in stmts {
+        if let Some(return_position) = returned_at {
+            diagnostics.push(Diagnostic::warning(codes::UNREACHABLE_CODE, "unreachable statement")
+                .at(stmt.position(), "unreachable statement")
+                .label(return_position, "any code following this return is unreachable"));
+            break;
+        }
+        returned_at = match stmt {
+            Stmt::Return { position, .. } => Some(*position),
+            Stmt::Block(b) => diverges_at(&b.stmts, diagnostics),
+            _ => None
+        };
+    }
+    returned_at
+}
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+
+use crate::shared::{
+    errors::{codes, Diagnostic}, positions::Position, typed_nodes::{Expr, ExprKind, Function, Stmt}
+};
+
+// calls whose result is fresh heap memory that outlives the function
+const ALLOCATORS: [&str; 5] = ["malloc", "calloc", "realloc", "heap_alloc", "arena_alloc"];
+
+// what a pointer value is known to point at
+#[derive(Debug, Clone, Copy)]
+enum Origin<'a> {
+    // a local or parameter of the function being checked
+    Stack(Variable<'a>),
+    // memory handed in by the caller through a pointer parameter
+    Caller,
+    Heap,
+    Unknown
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Variable<'a> {
+    name: &'a str,
+    declared: Position,
+    parameter: bool
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Local<'a> {
+    variable: Variable<'a>,
+    // what the pointer in the local, or in one of its fields for a struct, points at
+    holds: Origin<'a>
+}
+
+// nerv has no globals, so a local's address can only outlive the function by
+// being returned or written through a pointer that came from the caller or the
+// heap. the body runs top to bottom, so which pointer a local holds is exact at
+// every statement. anything not provably pointing at a local stays silent.
+pub struct EscapeAnalysis<'a, 'd> {
+    function: &'a str,
+    scopes: Vec<HashMap<&'a str, Local<'a>>>,
+    diagnostics: &'d mut Vec<Diagnostic>
+}
+
+impl<'a, 'd> EscapeAnalysis<'a, 'd> {
+    pub fn check(fx: &Function<'a>, diagnostics: &'d mut Vec<Diagnostic>) {
+        let parameters = fx.parameters.iter()
+            .map(|(name, _)| (*name, Local {
+                variable: Variable { name, declared: fx.position, parameter: true },
+                holds: Origin::Caller
+            }))
+            .collect();
+        let mut analysis = Self { function: fx.name, scopes: vec![parameters], diagnostics };
+        analysis.statements(&fx.body.stmts);
+    }
+
+    fn statements(&mut self, stmts: &[Stmt<'a>]) {
+        for stmt in stmts {
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &Stmt<'a>) {
+        match stmt {
+            Stmt::Let { name, value, position, .. } => {
+                let holds = self.origin(value);
+                self.scopes.last_mut().expect("UNREACHABLE")
+                    .insert(name, Local { variable: Variable { name, declared: *position, parameter: false }, holds });
+            }
+            Stmt::Assign { target, value } => {
+                let origin = self.origin(value);
+                self.store(target, origin, value.position);
+            }
+            Stmt::Return { value: Some(value), .. } => {
+                if let Origin::Stack(variable) = self.origin(value) {
+                    self.escape(variable, value.position, format!("returns a pointer to {}", Self::describe(variable)));
+                }
+            }
+            Stmt::Block(b) => {
+                self.scopes.push(HashMap::new());
+                self.statements(&b.stmts);
+                self.scopes.pop();
+            }
+            Stmt::Defer { body, .. } => {
+                self.scopes.push(HashMap::new());
+                self.statement(body);
+                self.scopes.pop();
+            }
+            _ => {}
+        }
+    }
+
+    // `target = value`, either a store that outlives the function or a local
+    // now holding a different pointer
+    fn store(&mut self, target: &Expr<'a>, value: Origin<'a>, position: Position) {
+        let into = match self.destination(target) {
+            Origin::Caller => "memory owned by the caller",
+            Origin::Heap => "heap memory",
+            Origin::Stack(variable) => {
+                let whole = matches!(target.kind, ExprKind::Local(_));
+                if let Some(local) = self.lookup_mut(variable.name) {
+                    // after a field store only a pointer to a local is still certain
+                    local.holds = if whole || matches!(value, Origin::Stack(_)) { value } else { Origin::Unknown };
+                }
+                return;
+            }
+            Origin::Unknown => return
+        };
+        if let Origin::Stack(variable) = value {
+            self.escape(variable, position, format!("stores a pointer to {} in {}", Self::describe(variable), into));
+        }
+    }
+
+    // where an assignment to `target` writes to
+    fn destination(&self, target: &Expr<'a>) -> Origin<'a> {
+        match &target.kind {
+            ExprKind::Local(name) => self.lookup(name).map_or(Origin::Unknown, |local| Origin::Stack(local.variable)),
+            ExprKind::Field { target, .. } => self.destination(target),
+            ExprKind::Deref(pointer) => self.origin(pointer),
+            _ => Origin::Unknown
+        }
+    }
+
+    // what the pointer `expr` evaluates to points at
+    fn origin(&self, expr: &Expr<'a>) -> Origin<'a> {
+        match &expr.kind {
+            ExprKind::AddressOf(value) => self.address_of(value),
+            ExprKind::Local(name) => self.lookup(name).map_or(Origin::Unknown, |local| local.holds),
+            ExprKind::Call { callee, .. } if matches!(callee.kind, ExprKind::Function(name) if ALLOCATORS.contains(&name)) => Origin::Heap,
+            ExprKind::StructLiteral { fields, .. } => fields.iter()
+                .map(|field| self.origin(&field.value))
+                .find(|origin| matches!(origin, Origin::Stack(_)))
+                .unwrap_or(Origin::Unknown),
+            _ => Origin::Unknown
+        }
+    }
+
+    // `&expr`
+    fn address_of(&self, expr: &Expr<'a>) -> Origin<'a> {
+        match &expr.kind {
+            ExprKind::Local(name) => self.lookup(name).map_or(Origin::Unknown, |local| Origin::Stack(local.variable)),
+            ExprKind::Field { target, .. } => self.address_of(target),
+            ExprKind::Deref(pointer) => self.origin(pointer),
+            _ => Origin::Unknown
+        }
+    }
+
+    fn escape(&mut self, variable: Variable<'a>, position: Position, message: String) {
+        let declared = if variable.parameter {
+            format!("`{}` is a parameter of `{}`", variable.name, self.function)
+        } else {
+            format!("`{}` is declared here", variable.name)
+        };
+        self.diagnostics.push(Diagnostic::warning(codes::DANGLING_POINTER, format!("`{}` {}", self.function, message))
+            .at(position, format!("`{}` stops existing when `{}` returns", variable.name, self.function))
+            .label(variable.declared, declared)
+            .note("allocate it on the heap if it has to outlive the function"));
+    }
+
+    fn describe(variable: Variable) -> String {
+        format!("the {} `{}`", if variable.parameter { "parameter" } else { "local" }, variable.name)
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Local<'a>> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn lookup_mut(&mut self, name: &str) -> Option<&mut Local<'a>> {
+        self.scopes.iter_mut().rev().find_map(|scope| scope.get_mut(name))
+    }
+}
@@ -1,16 +1,25 @@
 use std::collections::HashMap;
 
+mod control_flow;
+mod escape;
+
 use crate::shared::{
-    meta::AnyMetadata, parser_nodes::{
-        BlockStatement, Expression, ExpressionStatement, ExternFunctionStatement, FieldAccessExpression, FunctionDeclaration, Program, ReturnStatement, Statement, StructDeclaration, StructLiteralExpression, TypeDeclarationStatement, TypedExpression, VarDeclarationStatement, VariableReassignmentStatement
-    }, tokens::TokenType
+    compiler_defaults::ASM_REGISTERS, errors::{codes, Diagnostic}, meta::{AnyMetadata, NumberType}, parser_nodes::{
+        AsmOperandKind, AsmStatement, BlockStatement, ConstDeclarationStatement, DeferStatement, Expression, ExpressionStatement, ExternFunctionStatement, FunctionDeclaration, Program, ReturnStatement, Statement, StructDeclaration, TypeDeclarationStatement, TypedExpression, VarDeclarationStatement, VariableReassignmentStatement
+    }, positions::Position, tokens::TokenType, typed_nodes::{self, Asm, Block, Expr, ExprKind, FieldInit, Stmt, TypedProgram}
 };
 
 pub struct TypeChecker<'a> {
     program: Program<'a>,
+    // the typed tree built up while checking, for the code generator
+    checked: TypedProgram<'a>,
     env: TypeEnv,
+    diagnostics: Vec<Diagnostic>,
+    // set while checking what came from the bundled modules, which are never linted
+    in_bundled_module: bool
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub enum Types {
     Integer,
@@ -19,6 +28,7 @@ pub enum Types {
     Void,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub enum Declaration<'a> {
     FunctionDeclaration(FunctionDeclaration<'a>),
@@ -27,69 +37,220 @@ pub enum Declaration<'a> {
 #[derive(Debug, Clone)]
 pub struct TypeEnv {
     return_type: Option<TypedExpression>,
-    vars: HashMap<String, TypedExpression>,
-    functions: HashMap<String, (TypedExpression, Vec<TypedExpression>)>,
-    custom_types: HashMap<String, (TypedExpression)>,
-    struct_defs: HashMap<String, StructDef>
+    // innermost scope last, every function starts from a fresh stack
+    vars: Vec<HashMap<String, Variable>>,
+    functions: HashMap<String, (TypedExpression, Vec<TypedExpression>, bool)>,
+    // the program's own functions and externs, for the unused lints
+    items: HashMap<String, Item>,
+    custom_types: HashMap<String, TypedExpression>,
+    // the type and the value every use is folded to
+    constants: HashMap<String, (TypedExpression, i64)>,
+    // where every top level function, extern and constant is declared, they
+    // all end up as one symbol or name and can only be declared once
+    defined: HashMap<String, (Position, bool)>,
+    in_defer: bool
 }
 
 #[derive(Debug, Clone)]
-pub struct StructFieldDef {
-    pub name: String,
-    pub field_type: TypedExpression,
-    pub offset: usize,
-    pub size: usize,
-    pub align: usize
+pub struct Variable {
+    var_type: TypedExpression,
+    position: Position,
+    parameter: bool,
+    // read or written anywhere after its declaration
+    used: bool
 }
 
 #[derive(Debug, Clone)]
-pub struct StructDef {
-    pub name: String,
-    pub fields: Vec<StructFieldDef>,
-    pub size: usize,
-    pub align: usize
+pub struct Item {
+    position: Position,
+    external: bool,
+    used: bool
 }
 
 impl<'a> TypeChecker<'a> {
     pub fn new(program: Program<'a>) -> Self {
         Self {
             program,
+            checked: TypedProgram::default(),
             env: TypeEnv {
                 return_type: None,
-                vars: HashMap::new(),
+                vars: vec![HashMap::new()],
                 functions: HashMap::new(),
+                items: HashMap::new(),
                 custom_types: HashMap::new(),
-                struct_defs: HashMap::new()
+                constants: HashMap::new(),
+                defined: HashMap::new(),
+                in_defer: false
             },
+            diagnostics: vec![],
+            in_bundled_module: false
+        }
+    }
+
+    // every independent type error in the program along with the warnings, in
+    // source order. the program is fine to compile when none of them is an error.
+    pub fn check(&mut self) -> Vec<Diagnostic> {
+        let stmts = std::mem::take(&mut self.program.stmts);
+        let bundled = self.program.bundled;
+        // every signature can name every type, whichever comes first in the source
+        for stmt in &stmts {
+            self.declare_type(stmt);
+        }
+        for (i, stmt) in stmts.iter().enumerate() {
+            self.in_bundled_module = i < bundled;
+            self.check_redefinition(stmt);
+            self.declare_item(stmt);
         }
+        for (i, stmt) in stmts.iter().enumerate() {
+            self.in_bundled_module = i < bundled;
+            // functions and externs are collected as they are checked, of the
+            // rest only `asm` blocks end up in the executable
+            if let Some(Stmt::Asm(asm)) = self.type_check_statement(stmt) {
+                self.checked.global_asm.push(asm);
+            }
+        }
+        self.in_bundled_module = false;
+        self.report_unused_items();
+        let mut diagnostics = std::mem::take(&mut self.diagnostics);
+        diagnostics.sort_by_key(|e| e.primary.as_ref().map(|l| (l.position.line, l.position.column)));
+        diagnostics
+    }
+
+    // the typed tree of the checked program, only meaningful when `check`
+    // found no errors
+    pub fn into_typed_program(self) -> TypedProgram<'a> {
+        self.checked
     }
 
-    pub fn check(&mut self) {
-        self.start_type_checking(self.program.stmts.clone());
+    fn report(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
     }
 
-    pub fn start_type_checking(&mut self, stmts: Vec<Statement<'a>>) {
-        for x in stmts.clone() {
-            if let Statement::FunctionDeclaration(t) = x {
-                let mut args = vec![];
-                for arg in t.arguments {
-                    args.push(arg.arg_type);
+    // warnings about the program's own code, nobody can act on them for the bundled modules
+    fn lint(&mut self, diagnostic: Diagnostic) {
+        if !self.in_bundled_module {
+            self.report(diagnostic);
+        }
+    }
+
+    // reports and carries on with a type nothing else will complain about
+    fn error(&mut self, diagnostic: Diagnostic) -> TypedExpression {
+        self.report(diagnostic);
+        TypedExpression::Error
+    }
+
+    // the typed statement, None for declarations which don't run
+    fn type_check_statement(&mut self, stmt: &Statement<'a>) -> Option<Stmt<'a>> {
+        self.declare_item(stmt);
+        match stmt {
+            Statement::VarDeclaration(var_decl) => Some(self.type_check_var_declaration(var_decl)),
+            Statement::ExpressionStatement(expr_stmt) => Some(self.type_check_expression_statement(expr_stmt)),
+            Statement::FunctionDeclaration(func_decl) => {
+                self.type_check_function_declaration(func_decl);
+                None
+            }
+            Statement::BlockStatement(block_stmt) => Some(Stmt::Block(self.type_check_block_statement(block_stmt))),
+            Statement::ReturnStatement(ret_stmt) => Some(self.type_check_return_statement(ret_stmt)),
+            Statement::ExternStatement(ex) => {
+                self.type_check_extern_statement(ex);
+                None
+            }
+            Statement::VariableReassignmentStatement(vrs) => Some(self.type_check_reassignment_statement(vrs)),
+            Statement::TypeDeclarationStatement(_) | Statement::StructDeclaration(_) => {
+                // the top level ones were declared before anything else was checked
+                if self.env.return_type.is_some() {
+                    self.declare_type(stmt);
                 }
-                self.env.functions.insert(t.name.to_string(), (t.return_type, args));
+                None
             }
-        }
-        for stmt in stmts {
-            match stmt {
-                Statement::VarDeclaration(var_decl) => self.type_check_var_declaration(var_decl),
-                Statement::ExpressionStatement(expr_stmt) => self.type_check_expression_statement(expr_stmt),
-                Statement::FunctionDeclaration(func_decl) => self.type_check_function_declaration(func_decl),
-                Statement::BlockStatement(block_stmt) => self.type_check_block_statement(block_stmt),
-                Statement::ReturnStatement(ret_stmt) => self.type_check_return_statement(ret_stmt),
-                Statement::ExternStatement(ex) => self.type_check_extern_statement(ex),
-                Statement::VariableReassignmentStatement(vrs) => self.type_check_reassignment_statement(vrs),
-                Statement::TypeDeclarationStatement(tds) => self.check_type_declaration(&tds),
-                Statement::StructDeclaration(sd) => self.check_struct_declaration(sd),
+            Statement::DeferStatement(ds) => self.type_check_defer_statement(ds),
+            Statement::AsmStatement(asm) => Some(Stmt::Asm(self.type_check_asm_statement(asm))),
+            Statement::ConstDeclaration(c) => {
+                self.type_check_const_declaration(c);
+                None
             }
+            // the parser already reported it
+            Statement::Error(_) => None
+        }
+    }
+
+    fn declare_type(&mut self, stmt: &Statement<'a>) {
+        match stmt {
+            Statement::TypeDeclarationStatement(tds) => self.check_type_declaration(tds),
+            Statement::StructDeclaration(sd) => self.check_struct_declaration(sd),
+            _ => {}
+        }
+    }
+
+    // functions and constants can be used before their declaration
+    fn declare_item(&mut self, stmt: &Statement<'a>) {
+        if let Statement::FunctionDeclaration(t) = stmt {
+            let args = t.arguments.iter().map(|arg| arg.arg_type.clone()).collect();
+            self.env.functions.insert(t.name.to_string(), (t.return_type.clone(), args, false));
+            self.declare_lintable_item(t.name, t.position, false);
+        } else if let Statement::ConstDeclaration(c) = stmt {
+            let const_type = self.compile_user_defined_type(c.const_type.clone(), c.position);
+            self.env.constants.insert(c.name.to_string(), (const_type, Self::constant_value(c)));
+        }
+    }
+
+    // a second function, extern or constant of the same name, the bundled
+    // modules are declared first so a clash with them points at the program
+    fn check_redefinition(&mut self, stmt: &Statement<'a>) {
+        let (name, position) = match stmt {
+            Statement::FunctionDeclaration(f) => (f.name, f.position),
+            Statement::ExternStatement(ex) => (ex.fx_name, ex.position),
+            Statement::ConstDeclaration(c) => (c.name, c.position),
+            _ => return
+        };
+        let Some((first, bundled)) = self.env.defined.get(name).copied() else {
+            self.env.defined.insert(name.to_string(), (position, self.in_bundled_module));
+            return;
+        };
+        let diagnostic = Diagnostic::error(codes::REDEFINED_ITEM, format!("`{}` is defined more than once", name))
+            .at(position, "redefined here");
+        let diagnostic = if bundled {
+            diagnostic.note(format!("`{}` is part of the bundled standard library, pick another name", name))
+        } else {
+            diagnostic.label(first, format!("`{}` is first defined here", name))
+        };
+        self.report(diagnostic);
+    }
+
+    // an invalid initializer is reported when the declaration is checked
+    fn constant_value(c: &ConstDeclarationStatement<'a>) -> i64 {
+        match &c.value {
+            Expression::Literal(l) => match l.value.meta_data {
+                AnyMetadata::Number { value: NumberType::Integer(value) } => value,
+                _ => 0
+            },
+            _ => 0
+        }
+    }
+
+    fn declare_lintable_item(&mut self, name: &str, position: Position, external: bool) {
+        if !self.in_bundled_module {
+            self.env.items.entry(name.to_string()).or_insert(Item { position, external, used: false });
+        }
+    }
+
+    // `main` is used by whoever runs the program
+    fn report_unused_items(&mut self) {
+        let unused: Vec<(String, Item)> = self.env.items.iter()
+            .filter(|(name, item)| !item.used && *name != "main")
+            .map(|(name, item)| (name.clone(), item.clone()))
+            .collect();
+        for (name, item) in unused {
+            let diagnostic = if item.external {
+                Diagnostic::warning(codes::UNUSED_EXTERN, format!("extern function `{}` is never used", name))
+                    .at(item.position, "declared here")
+                    .width("extern".len())
+            } else {
+                Diagnostic::warning(codes::UNUSED_FUNCTION, format!("function `{}` is never used", name))
+                    .at(item.position, "")
+                    .width(1 + name.len())
+            };
+            self.report(diagnostic);
         }
     }
 
@@ -99,304 +260,623 @@ impl<'a> TypeChecker<'a> {
         }
     }
 
-    pub fn check_struct_declaration(&mut self, sd: StructDeclaration<'a>) {
-        let def = self.build_struct_def(sd.name, sd.fields);
-        self.env.struct_defs.insert(sd.name.to_string(), def);
+    // a field holding a struct by value needs that struct's layout, so it can
+    // neither be the struct itself nor one that isn't laid out yet. the field
+    // is left out of the layout once it is reported.
+    pub fn check_struct_declaration(&mut self, sd: &StructDeclaration<'a>) {
+        let mut fields = vec![];
+        for field in &sd.fields {
+            let field_type = self.compile_user_defined_type(field.field_type.clone(), field.position);
+            if let TypedExpression::Struct { name } = &field_type {
+                if name == sd.name {
+                    self.report(Diagnostic::error(codes::RECURSIVE_STRUCT, format!("`{}` contains itself", sd.name))
+                        .at(field.position, format!("`{}` holds `{}` by value", field.name, name))
+                        .label(sd.position, format!("`{}` would have no finite size", sd.name))
+                        .note(format!("hold a pointer instead, `&{}`", name)));
+                    continue;
+                }
+                if self.checked.layouts.get(name).is_none() {
+                    self.report(Diagnostic::error(codes::UNKNOWN_TYPE, format!("the size of `{}` is not known yet", name))
+                        .at(field.position, format!("`{}` holds `{}` by value", field.name, name))
+                        .note(format!("declare `{}` before `{}`, or hold a pointer to it", name, sd.name)));
+                    continue;
+                }
+            }
+            fields.push((field.name, field_type));
+        }
+        self.checked.layouts.declare_struct(sd.name, fields);
+    }
+
+    pub fn type_check_reassignment_statement(&mut self, vrs: &VariableReassignmentStatement<'a>) -> Stmt<'a> {
+        let target = self.eval(&vrs.lhs);
+        let value = self.eval(&vrs.rhs);
+        if !Self::is_assignable(&target.ty, &value.ty) {
+            let diagnostic = Self::mismatch(&target.ty, &value.ty, vrs.rhs.position())
+                .label(vrs.lhs.position(), format!("has type `{}`", target.ty));
+            self.report(diagnostic);
+        }
+        Stmt::Assign { target, value }
+    }
+
+    // structs are printed field by field straight from memory, so they have
+    // to live somewhere the fields can be addressed.
+    fn check_printable(&mut self, argument_type: &TypedExpression, argument: &Expression<'a>) {
+        match argument_type {
+            TypedExpression::Void => {
+                self.report(Diagnostic::error(codes::NOT_PRINTABLE, "can not print a void value")
+                    .at(argument.position(), "has no value"));
+            }
+            TypedExpression::Struct { name } => {
+                if !argument.is_lvalue() {
+                    self.report(Diagnostic::error(codes::NOT_PRINTABLE, format!("can not print a temporary `{}`", name))
+                        .at(argument.position(), "")
+                        .note("only struct variables and fields can be printed, store it in a variable first"));
+                    return;
+                }
+                let fields = self.checked.layouts.struct_def(name).fields.clone();
+                for field in &fields {
+                    self.check_printable(&field.field_type, argument);
+                }
+            }
+            _ => {}
+        }
     }
 
-    pub fn type_check_reassignment_statement(&self, vrs: VariableReassignmentStatement<'a>) {
-        let ldata_type = self.eval_expression(&vrs.lhs);
-        let rdata_type = self.eval_expression(&vrs.rhs);
-        if ldata_type != rdata_type {
-            panic!("Left Hand Side is of type {:?} and you're trying to assign {:?}", ldata_type, rdata_type);
+    // `&void` is the untyped pointer (what nil and the allocators hand out), it
+    // converts to and from every other pointer type without a cast.
+    fn is_assignable(expected: &TypedExpression, actual: &TypedExpression) -> bool {
+        match (expected, actual) {
+            (TypedExpression::Error, _) | (_, TypedExpression::Error) => true,
+            (TypedExpression::Pointer(e), TypedExpression::Pointer(a)) => {
+                **e == TypedExpression::Void || **a == TypedExpression::Void || e == a
+            }
+            _ => expected == actual
         }
     }
 
-    pub fn compile_user_defined_type(&self, user_defined_type: TypedExpression) -> TypedExpression {
+    fn mismatch(expected: &TypedExpression, actual: &TypedExpression, position: Position) -> Diagnostic {
+        Diagnostic::error(codes::MISMATCHED_TYPES, "mismatched types")
+            .at(position, format!("expected `{}`, found `{}`", expected, actual))
+    }
+
+    // the type with every alias expanded. `position` is where the type is
+    // spelled out, an alias nothing declared is reported there.
+    pub fn compile_user_defined_type(&mut self, user_defined_type: TypedExpression, position: Position) -> TypedExpression {
         match user_defined_type {
             TypedExpression::UserDefinedTypeAlias { identifier, .. } => {
                 if let Some(ut) = self.env.custom_types.get(&identifier) {
-                    self.compile_user_defined_type(ut.clone())
+                    self.compile_user_defined_type(ut.clone(), position)
                 } else {
-                    panic!("Unknown Type");
+                    self.error(Diagnostic::error(codes::UNKNOWN_TYPE, format!("unknown type `{}`", identifier))
+                        .at(position, "")
+                        .note(format!("`{}` is not declared at the top level of the program", identifier)))
                 }
             },
             TypedExpression::Pointer(x) => {
-                TypedExpression::Pointer(Box::new(self.compile_user_defined_type(*x)))
+                TypedExpression::Pointer(Box::new(self.compile_user_defined_type(*x, position)))
             }
-            TypedExpression::Function { args, return_type } => {
+            TypedExpression::Function { args, return_type, variadic } => {
                 let resolved_args = args.into_iter()
-                    .map(|arg| self.compile_user_defined_type(arg))
+                    .map(|arg| self.compile_user_defined_type(arg, position))
                     .collect();
-                let resolved_return = self.compile_user_defined_type(*return_type);
+                let resolved_return = self.compile_user_defined_type(*return_type, position);
                 TypedExpression::Function {
                     args: resolved_args,
-                    return_type: Box::new(resolved_return)
+                    return_type: Box::new(resolved_return),
+                    variadic
                 }
             }
             _ => user_defined_type
         }
     }
 
-    pub fn type_check_extern_statement(&mut self, ex: ExternFunctionStatement<'a>)  {
+    pub fn type_check_extern_statement(&mut self, ex: &ExternFunctionStatement<'a>)  {
 
         let mut args = vec![];
         for param in &ex.fx_sig.args {
-            args.push(self.compile_user_defined_type(param.clone()));
+            let arg = self.compile_user_defined_type(param.clone(), ex.position);
+            self.reject_struct_by_value(&arg, ex.position, "passed");
+            args.push(arg);
         }
 
-        let return_type = ex.fx_sig.return_type;
-        self.env.return_type = Some(return_type.clone());
-        self.env.functions.insert(ex.fx_sig.fx_name.to_string(), (return_type, args));
+        let return_type = ex.fx_sig.return_type.clone();
+        self.declare_lintable_item(ex.fx_name, ex.position, true);
+        self.checked.externs.push(ex.fx_name);
+        let resolved_return = self.compile_user_defined_type(return_type.clone(), ex.position);
+        self.reject_struct_by_value(&resolved_return, ex.position, "returned");
+        self.checked.signatures.insert(ex.fx_name, TypedExpression::Function {
+            args: args.clone(),
+            return_type: Box::new(resolved_return),
+            variadic: ex.fx_sig.variadic
+        });
+        self.env.functions.insert(ex.fx_sig.fx_name.to_string(), (return_type, args, ex.fx_sig.variadic));
     }
 
-    pub fn type_check_var_declaration(&mut self, v: VarDeclarationStatement<'a>) {
-        // Assuming v has a name and a declared type
+    pub fn type_check_var_declaration(&mut self, v: &VarDeclarationStatement<'a>) -> Stmt<'a> {
         let var_name = v.name;
-        let var_type = self.compile_user_defined_type(v.variable_type);
+        let value = self.eval(&v.value);
+        let var_type = match &v.variable_type {
+            Some(declared) => {
+                let var_type = self.compile_user_defined_type(declared.clone(), v.position);
+                if !Self::is_assignable(&var_type, &value.ty) {
+                    let diagnostic = Self::mismatch(&var_type, &value.ty, v.value.position())
+                        .label(v.position, format!("`{}` is declared as `{}`", var_name, var_type));
+                    self.report(diagnostic);
+                }
+                var_type
+            }
+            // `dec x = expr;`
+            None => self.infer(var_name, &v.value, value.ty.clone())
+        };
 
-        let expr_type = self.compile_user_defined_type(self.eval_expression(&v.value));
-        if expr_type != var_type {
-            panic!("Type mismatch in variable declaration: expected {:?}, got {:?}", var_type, expr_type);
+        // declared either way, later uses are checked against the declared type
+        self.declare_var(var_name, var_type.clone(), v.position, false);
+        Stmt::Let { name: var_name, var_type, value, position: v.position }
+    }
+
+    // the type of an initializer that is a type on its own, `nil` and `void`
+    // calls don't say what the variable is meant to hold
+    fn infer(&mut self, name: &str, value: &Expression<'a>, value_type: TypedExpression) -> TypedExpression {
+        let is_nil = matches!(value, Expression::Literal(l) if l.value.token_type == TokenType::Nil);
+        if is_nil {
+            return self.error(Diagnostic::error(codes::CAN_NOT_INFER, format!("can not infer a type for `{}` from `nil`", name))
+                .at(value.position(), "`nil` fits any pointer type")
+                .note(format!("spell out the pointer type, like `dec {} &int = nil;`", name)));
+        }
+        if value_type == TypedExpression::Void {
+            return self.error(Diagnostic::error(codes::CAN_NOT_INFER, format!("`{}` can not hold a `void`", name))
+                .at(value.position(), "this has no value"));
+        }
+        value_type
+    }
+
+    fn declare_var(&mut self, name: &str, var_type: TypedExpression, position: Position, parameter: bool) {
+        let scope = self.env.vars.last().expect("UNREACHABLE");
+        if scope.contains_key(name) {
+            self.report(Diagnostic::error(codes::REDECLARED_VARIABLE, format!("`{}` is already declared in this scope", name))
+                .at(position, "redeclared here")
+                .note("shadow it from an inner block instead"));
+            return;
         }
+        if let Some(outer) = self.lookup_var(name).map(|outer| (outer.position, outer.parameter)) {
+            let (shadowed, kind) = (outer.0, if outer.1 { "parameter" } else { "variable" });
+            self.lint(Diagnostic::warning(codes::SHADOWED_VARIABLE, format!("`{}` shadows the {} of the same name", name, kind))
+                .at(position, "")
+                .label(shadowed, format!("the {} `{}` is declared here", kind, name)));
+        }
+        self.env.vars.last_mut().expect("UNREACHABLE")
+            .insert(name.to_string(), Variable { var_type, position, parameter, used: false });
+    }
 
-        self.env.vars.insert(var_name.to_string(), var_type);
+    fn lookup_var(&self, name: &str) -> Option<&Variable> {
+        self.env.vars.iter().rev().find_map(|scope| scope.get(name))
     }
 
-    pub fn type_check_return_statement(&self, r: ReturnStatement<'a>) {
-        let expected_return_type = self.compile_user_defined_type(self.env.return_type.clone().unwrap());
-        let expr_type = self.compile_user_defined_type(self.eval_expression(&r.value));
+    fn push_scope(&mut self) {
+        self.env.vars.push(HashMap::new());
+    }
 
-        if expr_type != expected_return_type {
-            panic!(
-                "Return type mismatch: expected {:?}, got {:?} {}:{}",
-                expected_return_type, expr_type, r.position.line, r.position.column
-            );
+    // locals nothing read or wrote are reported when their scope ends,
+    // parameters are part of the signature and can't just be dropped
+    fn pop_scope(&mut self) {
+        let scope = self.env.vars.pop().expect("UNREACHABLE");
+        for (name, variable) in scope {
+            if !variable.used && !variable.parameter && !name.starts_with('_') {
+                self.lint(Diagnostic::warning(codes::UNUSED_VARIABLE, format!("unused variable `{}`", name))
+                    .at(variable.position, "declared here but never used")
+                    .note(format!("name it `_{}` if that is on purpose", name)));
+            }
         }
     }
 
-    pub fn type_check_block_statement(&mut self, b: BlockStatement<'a>) {
-        let previous_env = self.env.vars.clone();
+    pub fn type_check_const_declaration(&mut self, c: &ConstDeclarationStatement<'a>) {
+        let const_type = self.compile_user_defined_type(c.const_type.clone(), c.position);
+        let value = Self::constant_value(c);
+        if const_type != TypedExpression::Integer {
+            self.report(Diagnostic::error(codes::INVALID_CONSTANT, format!("constant `{}` has to be of type `int`, it is `{}`", c.name, const_type))
+                .at(c.position, ""));
+        } else if !matches!(&c.value, Expression::Literal(l) if matches!(l.value.meta_data, AnyMetadata::Number { value: NumberType::Integer(_) })) {
+            self.report(Diagnostic::error(codes::INVALID_CONSTANT, format!("constant `{}` has to be initialised with an integer literal", c.name))
+                .at(c.value.position(), "not an integer literal"));
+        }
+        self.env.constants.insert(c.name.to_string(), (const_type, value));
+    }
 
-        for stmt in b.values {
-            self.start_type_checking(vec![stmt]);
+    pub fn type_check_defer_statement(&mut self, d: &DeferStatement<'a>) -> Option<Stmt<'a>> {
+        let was_in_defer = self.env.in_defer;
+        self.env.in_defer = true;
+        self.push_scope();
+        let body = self.type_check_statement(&d.body);
+        self.pop_scope();
+        self.env.in_defer = was_in_defer;
+        body.map(|body| Stmt::Defer { body: Box::new(body), position: d.position })
+    }
+
+    pub fn type_check_asm_statement(&mut self, asm: &AsmStatement<'a>) -> Asm<'a> {
+        for (clobber, position) in &asm.clobbers {
+            if !ASM_REGISTERS.contains(clobber) {
+                self.report(Diagnostic::error(codes::INVALID_ASM_OPERAND, format!("unknown register `{}` in asm clobbers", clobber))
+                    .at(*position, "not a general purpose register"));
+            }
         }
+        let mut operands = vec![];
+        for operand in &asm.operands {
+            let position = operand.position;
+            if let Some(register) = operand.register && !ASM_REGISTERS.contains(&register) {
+                self.report(Diagnostic::error(codes::INVALID_ASM_OPERAND, format!("unknown register `{}` for asm operand", register))
+                    .at(position, ""));
+            }
+            let is_local = matches!(&operand.value, Expression::Literal(l) if matches!(l.value.meta_data, AnyMetadata::Identifier { value } if self.lookup_var(value).is_some()));
+            if operand.kind != AsmOperandKind::In && !is_local {
+                self.report(Diagnostic::error(codes::INVALID_ASM_OPERAND, "out, inout and mem asm operands have to be local variables")
+                    .at(operand.value.position(), "not a local variable"));
+                continue;
+            }
+            let value = self.eval(&operand.value);
+            if let TypedExpression::Struct { .. } | TypedExpression::Void = value.ty {
+                self.report(Diagnostic::error(codes::INVALID_ASM_OPERAND, format!("can not bind a `{}` to an asm operand", value.ty))
+                    .at(operand.value.position(), ""));
+            }
+            operands.push(typed_nodes::AsmOperand { kind: operand.kind, register: operand.register, value });
+        }
+        Asm { template: asm.template.clone(), operands, clobbers: asm.clobbers.iter().map(|(clobber, _)| *clobber).collect(), position: asm.position }
+    }
 
-        self.env.vars = previous_env;
+    pub fn type_check_return_statement(&mut self, r: &ReturnStatement<'a>) -> Stmt<'a> {
+        if self.env.in_defer {
+            self.report(Diagnostic::error(codes::RETURN_IN_DEFER, "can not return from inside a defer")
+                .at(r.position, "")
+                .note("deferred statements run while the function is already returning"));
+        }
+        let Some(return_type) = self.env.return_type.clone() else {
+            self.report(Diagnostic::error(codes::RETURN_OUTSIDE_FUNCTION, "`return` outside of a function")
+                .at(r.position, "")
+                .note("only function bodies can return"));
+            return Stmt::Return { value: None, position: r.position };
+        };
+        let expected_return_type = self.compile_user_defined_type(return_type, r.position);
+        let Some(value) = &r.value else {
+            if !Self::is_assignable(&expected_return_type, &TypedExpression::Void) {
+                self.report(Diagnostic::error(codes::MISMATCHED_TYPES, "`return;` without a value")
+                    .at(r.position, format!("the function returns `{}`", expected_return_type))
+                    .note("only void functions can return without a value"));
+            }
+            return Stmt::Return { value: None, position: r.position };
+        };
+        let typed = self.eval(value);
+
+        if !Self::is_assignable(&expected_return_type, &typed.ty) {
+            let diagnostic = Self::mismatch(&expected_return_type, &typed.ty, value.position())
+                .note(format!("the function returns `{}`", expected_return_type));
+            self.report(diagnostic);
+        }
+        Stmt::Return { value: Some(typed), position: r.position }
     }
 
-    pub fn type_check_expression_statement(&self, e: ExpressionStatement<'a>) {
-        self.eval_expression(&e.value);
+    pub fn type_check_block_statement(&mut self, b: &BlockStatement<'a>) -> Block<'a> {
+        self.push_scope();
+        let stmts = self.type_check_statements(&b.values);
+        self.pop_scope();
+        Block { stmts, position: b.position }
     }
 
-    pub fn type_check_function_declaration(&mut self, f: FunctionDeclaration<'a>) {
+    fn type_check_statements(&mut self, stmts: &[Statement<'a>]) -> Vec<Stmt<'a>> {
+        let mut typed = vec![];
+        for stmt in stmts {
+            typed.extend(self.type_check_statement(stmt));
+        }
+        typed
+    }
+
+    pub fn type_check_expression_statement(&mut self, e: &ExpressionStatement<'a>) -> Stmt<'a> {
+        Stmt::Expr(self.eval(&e.value))
+    }
+
+    pub fn type_check_function_declaration(&mut self, f: &FunctionDeclaration<'a>) {
         self.type_check_function(f);
     }
 
-    pub fn type_check_function(&mut self, fx: FunctionDeclaration<'a>) {
-        let return_type = self.compile_user_defined_type(fx.return_type);
+    pub fn type_check_function(&mut self, fx: &FunctionDeclaration<'a>) {
+        let return_type = self.compile_user_defined_type(fx.return_type.clone(), fx.position);
+        self.reject_struct_by_value(&return_type, fx.position, "returned");
         let old_env = self.env.clone();
         self.env.return_type = Some(return_type.clone());
 
+        // parameters share the body's outermost scope
+        self.env.vars = vec![HashMap::new()];
         let mut args = vec![];
+        let mut parameters = vec![];
         for param in &fx.arguments {
             args.push(param.arg_type.clone());
-            self.env
-                .vars
-                .insert(param.name.to_string(), self.compile_user_defined_type(param.arg_type.clone()));
+            let param_type = self.compile_user_defined_type(param.arg_type.clone(), fx.position);
+            self.reject_struct_by_value(&param_type, fx.position, "passed");
+            parameters.push((param.name, param_type.clone()));
+            self.declare_var(param.name, param_type, fx.position, true);
         }
-        
-        self.type_check_block_statement(fx.body);
-        self.env = old_env;
-        self.env.functions.insert(fx.name.to_string(), (return_type, args));
-    }
-
-    fn eval_custom_type(&self, token_identifier: &'a str) -> &TypedExpression {
-        if let Some(first_alias_for)  = self.env.custom_types.get(token_identifier) {
-            if let TypedExpression::UserDefinedTypeAlias { identifier, ..  } = first_alias_for {
-                return self.eval_custom_type(identifier);
-            }
-            return first_alias_for;
+        let body = Block { stmts: self.type_check_statements(&fx.body.values), position: fx.body.position };
+        self.pop_scope();
+        let function = typed_nodes::Function { name: fx.name, parameters, return_type: return_type.clone(), body, position: fx.position };
+        let mut lints = vec![];
+        let returns = control_flow::diverges_at(&function.body.stmts, &mut lints);
+        if returns.is_none() && return_type != TypedExpression::Void {
+            self.report(Diagnostic::error(codes::MISSING_RETURN, format!("`{}` can reach the end of its body without returning a value", fx.name))
+                .at(fx.body.end, "the function ends here without a return")
+                .width(1)
+                .label(fx.position, format!("`{}` is declared to return `{}`", fx.name, return_type)));
+        }
+        escape::EscapeAnalysis::check(&function, &mut lints);
+        for lint in lints {
+            self.lint(lint);
         }
-        panic!("Invalid User Defined Type: {:?}", token_identifier);
+        // which functions got used is known program wide
+        self.env = TypeEnv { items: std::mem::take(&mut self.env.items), ..old_env };
+        self.checked.signatures.insert(fx.name, TypedExpression::Function {
+            args: function.parameters.iter().map(|(_, param_type)| param_type.clone()).collect(),
+            return_type: Box::new(return_type.clone()),
+            variadic: false
+        });
+        self.checked.functions.push(function);
+        self.env.functions.insert(fx.name.to_string(), (return_type, args, false));
     }
 
-    fn eval_expression(&self, expr: &Expression<'a>) -> TypedExpression {
+    // the typed expression, its type has aliases resolved
+    fn eval(&mut self, expr: &Expression<'a>) -> Expr<'a> {
+        let position = expr.position();
         match expr {
             Expression::Binary(binary_expression) => {
-                let lhs = self.eval_expression(&binary_expression.left);
-                let rhs = self.eval_expression(&binary_expression.right);
-                match (binary_expression.operator.token_type, lhs, rhs) {
-                    (TokenType::Plus | TokenType::Minus | TokenType::Star, TypedExpression::Integer, TypedExpression::Integer) => {
+                let left = self.eval(&binary_expression.left);
+                let right = self.eval(&binary_expression.right);
+                let operator = binary_expression.operator.token_type;
+                let ty = match (operator, &left.ty, &right.ty) {
+                    (_, TypedExpression::Error, _) | (_, _, TypedExpression::Error) => {
+                        TypedExpression::Error
+                    },
+                    (TokenType::Plus | TokenType::Minus | TokenType::Star | TokenType::Slash, TypedExpression::Integer, TypedExpression::Integer) => {
                         TypedExpression::Integer
                     },
-                    (TokenType::Plus | TokenType::Minus | TokenType::Star, TypedExpression::Integer, TypedExpression::Float) => {
+                    (TokenType::Plus | TokenType::Minus | TokenType::Star | TokenType::Slash, TypedExpression::Integer, TypedExpression::Float) => {
                         TypedExpression::Float
                     },
-                    (TokenType::Plus | TokenType::Minus | TokenType::Star, TypedExpression::Float, TypedExpression::Integer) => {
+                    (TokenType::Plus | TokenType::Minus | TokenType::Star | TokenType::Slash, TypedExpression::Float, TypedExpression::Integer) => {
                         TypedExpression::Float
                     },
-                    (TokenType::Plus | TokenType::Minus | TokenType::Star, TypedExpression::Float, TypedExpression::Float) => {
+                    (TokenType::Plus | TokenType::Minus | TokenType::Star | TokenType::Slash, TypedExpression::Float, TypedExpression::Float) => {
                         TypedExpression::Float
                     },
                     (TokenType::EqualEqual, _, _) => {
-                        TypedExpression::Float
-                    },
-                    (TokenType::Slash, _, _) => {
-                        TypedExpression::Float
+                        TypedExpression::Bool
                     },
-                    _ => panic!("Type error in binary expression")
-                }
+                    (operator, lhs, rhs) => self.error(Diagnostic::error(codes::INVALID_OPERAND, format!("can not apply {} to `{}` and `{}`", operator.describe(), lhs, rhs))
+                        .at(binary_expression.operator.position, "")
+                        .label(binary_expression.left.position(), format!("`{}`", lhs))
+                        .label(binary_expression.right.position(), format!("`{}`", rhs)))
+                };
+                Expr::new(ExprKind::Binary { operator, left: Box::new(left), right: Box::new(right) }, ty, position)
             },
             Expression::Unary(u) => {
                 match u.operator.token_type {
                     TokenType::Ampersand => {
-                        let x = self.eval_expression(&u.value);
-                        TypedExpression::Pointer(Box::new(x))
+                        let value = self.eval(&u.value);
+                        let ty = match &value.ty {
+                            TypedExpression::Error => TypedExpression::Error,
+                            x => TypedExpression::Pointer(Box::new(x.clone()))
+                        };
+                        Expr::new(ExprKind::AddressOf(Box::new(value)), ty, position)
                     }
                     TokenType::Star => {
-                        if let TypedExpression::Pointer(x) = self.eval_expression(&u.value) {
-                            *x
-                        } else {
-                            panic!("You're trying to deref a {:?} type", self.eval_expression(&u.value));
-                        }
+                        let value = self.eval(&u.value);
+                        let ty = match &value.ty {
+                            TypedExpression::Pointer(x) => x.as_ref().clone(),
+                            TypedExpression::Error => TypedExpression::Error,
+                            other => self.error(Diagnostic::error(codes::INVALID_OPERAND, format!("can not dereference a `{}`", other))
+                                .at(u.value.position(), "not a pointer"))
+                        };
+                        Expr::new(ExprKind::Deref(Box::new(value)), ty, position)
+                    }
+                    operator => {
+                        self.eval(&u.value);
+                        self.report(Diagnostic::error(codes::INVALID_OPERAND, format!("unary {} is not supported", operator.describe()))
+                            .at(u.operator.position, ""));
+                        Expr::error(position)
                     }
-                    _ => unimplemented!()
                 }
             },
             Expression::Call(c) => {
-                let callee_type = self.compile_user_defined_type(self.eval_expression(&c.callee));
-                if let TypedExpression::Function { args, return_type } = callee_type {
-                    if args.len() != c.arguments.len() {
-                        panic!("Expected {} arguments got {}", args.len(), c.arguments.len());
+                let callee = self.eval(&c.callee);
+                let TypedExpression::Function { args, return_type, variadic } = callee.ty.clone() else {
+                    for arg in &c.arguments {
+                        self.eval(arg);
                     }
-                    (0..c.arguments.len()).for_each(|i| {
-                        let arg = c.arguments[i].clone();
-                        let arg_type = self.compile_user_defined_type(self.eval_expression(&arg));
-                        if arg_type != args[i] {
-                            panic!("Expected argument type to be {:?} instead got {:?} {}:{}", args[i], arg_type, c.position.line, c.position.column);
+                    if callee.ty != TypedExpression::Error {
+                        self.report(Diagnostic::error(codes::NOT_CALLABLE, format!("can not call a `{}`", callee.ty))
+                            .at(c.callee.position(), "not a function"));
+                    }
+                    return Expr::error(position);
+                };
+                if (variadic && c.arguments.len() < args.len()) || (!variadic && args.len() != c.arguments.len()) {
+                    let expected = if variadic { format!("at least {}", args.len()) } else { args.len().to_string() };
+                    self.report(Diagnostic::error(codes::WRONG_ARGUMENT_COUNT, format!("expected {} argument{}, got {}", expected, if args.len() == 1 { "" } else { "s" }, c.arguments.len()))
+                        .at(c.callee.position(), ""));
+                }
+                // named functions were checked where they were declared
+                if !matches!(callee.kind, ExprKind::Function(_)) {
+                    for arg in &args {
+                        self.reject_struct_by_value(arg, c.callee.position(), "passed");
+                    }
+                    self.reject_struct_by_value(&return_type, c.callee.position(), "returned");
+                }
+                let mut arguments = vec![];
+                for (i, arg) in c.arguments.iter().enumerate() {
+                    let typed = self.eval(arg);
+                    match args.get(i) {
+                        Some(expected) if !Self::is_assignable(expected, &typed.ty) => {
+                            let diagnostic = Self::mismatch(expected, &typed.ty, arg.position())
+                                .label(c.callee.position(), "in this call");
+                            self.report(diagnostic);
                         }
-                    });
-                    *return_type
-                } else {
-                    panic!("Trying to call a non-function type.");
+                        None if variadic => self.promote_variadic_argument(typed.ty.clone(), arg),
+                        // fine, or already reported as the wrong number of arguments
+                        _ => {}
+                    }
+                    arguments.push(typed);
                 }
+                Expr::new(ExprKind::Call { callee: Box::new(callee), arguments }, *return_type, position)
             }
             Expression::Literal(literal_expression) => {
-                match literal_expression.value.token_type {
-                    TokenType::Integer => TypedExpression::Integer,
-                    TokenType::String => TypedExpression::String,
-                    TokenType::Float => TypedExpression::Float,
-                    TokenType::Void => TypedExpression::Void,
-                    TokenType::Identifier => {
-                        if let AnyMetadata::Identifier { value } = literal_expression.value.meta_data {
-                            if let Some(variable_type) = self.env.vars.get(value) {
-                                if let TypedExpression::UserDefinedTypeAlias { identifier, .. } = variable_type {
-                                    return self.eval_custom_type(identifier).clone();
-                                }
-                                variable_type.clone()
-                            } else if let Some((return_type, args)) = self.env.functions.get(value) {
-                                TypedExpression::Function {
-                                    args: args.clone(),
-                                    return_type: Box::new(return_type.clone())
-                                }
-                            } else {
-                                panic!("Unknown Variable {}:{}", literal_expression.value.position.line, literal_expression.value.position.column);
+                let (kind, ty) = match (literal_expression.value.token_type, literal_expression.value.meta_data) {
+                    // a character literal is its code point
+                    (TokenType::Integer | TokenType::Character, AnyMetadata::Number { value: NumberType::Integer(value) }) => {
+                        (ExprKind::Integer(value), TypedExpression::Integer)
+                    }
+                    (TokenType::String, AnyMetadata::String { value }) => (ExprKind::String(value), TypedExpression::String),
+                    (TokenType::Float, AnyMetadata::Number { value: NumberType::Float(value) }) => (ExprKind::Float(value), TypedExpression::Float),
+                    // `unit`, the only value of `void`, nothing can hold or print it
+                    (TokenType::Void, _) => (ExprKind::Integer(0), TypedExpression::Void),
+                    (TokenType::Nil, _) => (ExprKind::Nil, TypedExpression::Pointer(Box::new(TypedExpression::Void))),
+                    (TokenType::True | TokenType::False, _) => {
+                        (ExprKind::Bool(literal_expression.value.token_type == TokenType::True), TypedExpression::Bool)
+                    }
+                    (TokenType::Identifier, AnyMetadata::Identifier { value }) => {
+                        if let Some(variable) = self.env.vars.iter_mut().rev().find_map(|scope| scope.get_mut(value)) {
+                            variable.used = true;
+                            (ExprKind::Local(value), variable.var_type.clone())
+                        } else if let Some((constant_type, constant)) = self.env.constants.get(value) {
+                            (ExprKind::Integer(*constant), constant_type.clone())
+                        } else if let Some((return_type, args, variadic)) = self.env.functions.get(value).cloned() {
+                            if let Some(item) = self.env.items.get_mut(value) {
+                                item.used = true;
                             }
+                            let ty = self.compile_user_defined_type(TypedExpression::Function {
+                                args,
+                                return_type: Box::new(return_type),
+                                variadic
+                            }, literal_expression.value.position);
+                            (ExprKind::Function(value), ty)
                         } else {
-                            panic!("Unknown Variable {}:{}", literal_expression.value.position.line, literal_expression.value.position.column);
+                            self.report(Diagnostic::error(codes::UNKNOWN_VARIABLE, format!("unknown variable `{}`", value))
+                                .at(literal_expression.value.position, "not declared in this scope"));
+                            return Expr::error(position);
                         }
                     }
-                    _ => {
-                        panic!("Unknown Literal Expression: {:?} {}:{}", literal_expression.value, literal_expression.value.position.line, literal_expression.value.position.column);
+                    (token_type, _) => {
+                        self.report(Diagnostic::error(codes::UNEXPECTED_TOKEN, format!("{} is not a value", token_type.describe()))
+                            .at(literal_expression.value.position, "expected an expression"));
+                        return Expr::error(position);
                     }
-                }
+                };
+                Expr::new(kind, ty, position)
             },
-            Expression::StructLiteral(sl) => {
-                let struct_def = self.env.struct_defs.get(sl.name)
-                    .unwrap_or_else(|| panic!("Unknown struct type {}", sl.name));
-                if sl.fields.len() != struct_def.fields.len() {
-                    panic!("Struct literal missing fields {}", sl.name);
+            Expression::Syscall(s) => {
+                if s.arguments.is_empty() || s.arguments.len() > 7 {
+                    self.report(Diagnostic::error(codes::INVALID_SYSCALL, format!("syscall takes a syscall number and up to six arguments, got {} arguments", s.arguments.len()))
+                        .at(s.position, ""));
+                }
+                let mut arguments = vec![];
+                for argument in &s.arguments {
+                    let typed = self.eval(argument);
+                    if !matches!(typed.ty, TypedExpression::Integer | TypedExpression::Pointer(_) | TypedExpression::String | TypedExpression::Function { .. } | TypedExpression::Error) {
+                        self.report(Diagnostic::error(codes::INVALID_SYSCALL, format!("syscall arguments have to be integers or pointers, got `{}`", typed.ty))
+                            .at(argument.position(), ""));
+                    }
+                    arguments.push(typed);
+                }
+                Expr::new(ExprKind::Syscall(arguments), TypedExpression::Integer, position)
+            }
+            Expression::Print(p) => {
+                let mut arguments = vec![];
+                for argument in &p.arguments {
+                    let typed = self.eval(argument);
+                    self.check_printable(&typed.ty, argument);
+                    arguments.push(typed);
                 }
+                Expr::new(ExprKind::Print { arguments, newline: p.newline }, TypedExpression::Void, position)
+            }
+            Expression::StructLiteral(sl) => {
+                let Some(struct_def) = self.checked.layouts.get(sl.name).cloned() else {
+                    for field in &sl.fields {
+                        self.eval(&field.value);
+                    }
+                    self.report(Diagnostic::error(codes::UNKNOWN_STRUCT, format!("unknown struct `{}`", sl.name))
+                        .at(sl.position, ""));
+                    return Expr::error(position);
+                };
+                let mut fields = vec![];
                 for field in &sl.fields {
-                    let expected_field = struct_def.fields.iter().find(|f| f.name == field.name)
-                        .unwrap_or_else(|| panic!("Unknown field {} for struct {}", field.name, sl.name));
-                    let value_type = self.compile_user_defined_type(self.eval_expression(&field.value));
-                    if value_type != expected_field.field_type {
-                        panic!("Struct field {} expects {:?} got {:?}", field.name, expected_field.field_type, value_type);
+                    let value = self.eval(&field.value);
+                    let Some(expected_field) = struct_def.fields.iter().find(|f| f.name == field.name) else {
+                        self.report(Diagnostic::error(codes::UNKNOWN_FIELD, format!("struct `{}` has no field `{}`", sl.name, field.name))
+                            .at(field.value.position(), ""));
+                        continue;
+                    };
+                    if !Self::is_assignable(&expected_field.field_type, &value.ty) {
+                        self.report(Self::mismatch(&expected_field.field_type, &value.ty, field.value.position()));
                     }
+                    fields.push(FieldInit { name: field.name, offset: expected_field.offset, value });
+                }
+                let missing: Vec<String> = struct_def.fields.iter()
+                    .filter(|f| !sl.fields.iter().any(|given| given.name == f.name))
+                    .map(|f| format!("`{}`", f.name))
+                    .collect();
+                if !missing.is_empty() {
+                    self.report(Diagnostic::error(codes::MISSING_FIELDS, format!("missing fields {} in `{}` literal", missing.join(", "), sl.name))
+                        .at(sl.position, ""));
                 }
-                TypedExpression::Struct { name: sl.name.to_string() }
+                // still a struct of that type, whatever was wrong with its fields
+                Expr::new(ExprKind::StructLiteral { name: sl.name, fields }, TypedExpression::Struct { name: sl.name.to_string() }, position)
             }
             Expression::FieldAccess(fa) => {
-                let target_type = self.compile_user_defined_type(self.eval_expression(&fa.target));
-                if let TypedExpression::Struct { name } = target_type {
-                    let struct_def = self.env.struct_defs.get(&name)
-                        .unwrap_or_else(|| panic!("Unknown struct type {}", name));
-                    if let Some(field) = struct_def.fields.iter().find(|f| f.name == fa.field) {
-                        field.field_type.clone()
-                    } else {
-                        panic!("Unknown field {} for struct {}", fa.field, name);
+                let target = self.eval(&fa.target);
+                let field = match &target.ty {
+                    TypedExpression::Struct { name } => {
+                        let struct_def = self.checked.layouts.struct_def(name);
+                        if let Some(field) = struct_def.fields.iter().find(|f| f.name == fa.field) {
+                            Some((field.field_type.clone(), field.offset))
+                        } else {
+                            self.report(Diagnostic::error(codes::UNKNOWN_FIELD, format!("struct `{}` has no field `{}`", name, fa.field))
+                                .at(fa.position, "unknown field"));
+                            None
+                        }
                     }
-                } else {
-                    panic!("Field access on non-struct type");
-                }
+                    TypedExpression::Error => None,
+                    _ => {
+                        self.report(Diagnostic::error(codes::UNKNOWN_FIELD, format!("no field `{}` on `{}`", fa.field, target.ty))
+                            .at(fa.position, "")
+                            .label(fa.target.position(), "not a struct"));
+                        None
+                    }
+                };
+                let Some((ty, offset)) = field else {
+                    return Expr::error(position);
+                };
+                Expr::new(ExprKind::Field { target: Box::new(target), name: fa.field, offset }, ty, position)
             }
         }
     }
 
-    fn type_size_align(&self, t: &TypedExpression) -> (usize, usize) {
-        match t {
-            TypedExpression::Integer => (4, 4),
-            TypedExpression::Float => (8, 8),
-            TypedExpression::String => (8, 8),
-            TypedExpression::Void => (1, 1),
-            TypedExpression::Pointer(_) => (8, 8),
-            TypedExpression::Function { .. } => (8, 8),
-            TypedExpression::Struct { name } => {
-                let def = self.env.struct_defs.get(name)
-                    .unwrap_or_else(|| panic!("Unknown struct type {}", name));
-                (def.size, def.align)
-            }
-            TypedExpression::UserDefinedTypeAlias { identifier, .. } => {
-                let resolved = self.eval_custom_type(identifier).clone();
-                self.type_size_align(&resolved)
-            }
+    // C's default argument promotions for the `...` part of a call. a `bool` is
+    // widened to an int when it is lowered, character literals already are ints
+    // and `float` is a double, so the rest passes through as is. only things C
+    // can't receive through `va_arg` are rejected.
+    // structs only live in memory, calls move them through pointers
+    fn reject_struct_by_value(&mut self, ty: &TypedExpression, position: Position, how: &str) {
+        if let TypedExpression::Struct { name, .. } = ty {
+            self.report(Diagnostic::error(codes::STRUCT_BY_VALUE, format!("`{}` can not be {} by value", name, how))
+                .at(position, "")
+                .note(format!("use a pointer to it instead, `&{}`", name)));
         }
     }
 
-    fn build_struct_def(&self, name: &str, fields: Vec<crate::shared::parser_nodes::StructField<'a>>) -> StructDef {
-        let mut layout_fields = vec![];
-        let mut offset = 0;
-        let mut max_align = 1;
-        for field in fields {
-            let field_type = self.compile_user_defined_type(field.field_type);
-            let (size, align) = self.type_size_align(&field_type);
-            if align > max_align {
-                max_align = align;
-            }
-            let rem = offset % align;
-            if rem != 0 {
-                offset += align - rem;
-            }
-            layout_fields.push(StructFieldDef {
-                name: field.name.to_string(),
-                field_type,
-                offset,
-                size,
-                align
-            });
-            offset += size;
-        }
-        let final_rem = offset % max_align;
-        if final_rem != 0 {
-            offset += max_align - final_rem;
-        }
-        StructDef {
-            name: name.to_string(),
-            fields: layout_fields,
-            size: offset,
-            align: max_align
+    fn promote_variadic_argument(&mut self, arg_type: TypedExpression, arg: &Expression<'a>) {
+        match arg_type {
+            TypedExpression::Integer
+            | TypedExpression::Bool
+            | TypedExpression::Float
+            | TypedExpression::String
+            | TypedExpression::Pointer(_)
+            | TypedExpression::Function { .. }
+            | TypedExpression::Error => {}
+            _ => self.report(Diagnostic::error(codes::MISMATCHED_TYPES, format!("can not pass `{}` as a variadic argument", arg_type))
+                .at(arg.position(), ""))
         }
     }
 }
@@ -0,0 +1,155 @@
+mod common;
+
+// straight line program (nerv has no loops yet) allocating blocks of mixed
+// sizes, filling each with its own byte and counting the bytes back.
+#[cfg(target_os = "linux")]
+fn stress_program(sizes: &[usize]) -> String {
+    let mut program = String::from(
+r#"@count_bytes(&void block, int value, int size) int {
+    dec count int = 0;
+    asm {
+        "xor eax, eax",
+        ".next:",
+        "cmp [rdi], sil",
+        "jne .skip",
+        "inc eax",
+        ".skip:",
+        "inc rdi",
+        "dec rcx",
+        "jnz .next",
+        in("rdi") block,
+        in("rsi") value,
+        in("rcx") size,
+        out("rax") count
+    }
+    return count;
+}
+
+@report(int value) int {
+    write_int(STDOUT, value);
+    return write_string(STDOUT, " ");
+}
+
+@main() int {
+"#);
+    let fill = |program: &mut String, name: &str, round: usize| {
+        for (i, size) in sizes.iter().enumerate() {
+            let value = (i + round) % 250 + 1;
+            program.push_str(&format!("    dec {name}{i} &void = heap_alloc({size});\n"));
+            program.push_str(&format!("    mem_set({name}{i}, {value}, {size});\n"));
+        }
+        for (i, size) in sizes.iter().enumerate() {
+            let value = (i + round) % 250 + 1;
+            program.push_str(&format!("    report(count_bytes({name}{i}, {value}, {size}));\n"));
+        }
+        program.push_str("    report(heap_mapped_bytes());\n");
+    };
+    let release = |program: &mut String, name: &str| {
+        // interleave the order blocks are freed in
+        for i in (0..sizes.len()).step_by(2).chain((1..sizes.len()).step_by(2).rev()) {
+            program.push_str(&format!("    heap_free({name}{i});\n"));
+        }
+        program.push_str("    report(heap_mapped_bytes());\n");
+    };
+    fill(&mut program, "first", 0);
+    release(&mut program, "first");
+    fill(&mut program, "second", 7);
+    release(&mut program, "second");
+
+    program.push_str("    dec arena &Arena = arena_new(4096);\n");
+    for (i, size) in sizes.iter().enumerate() {
+        let value = i % 250 + 1;
+        program.push_str(&format!("    dec bump{i} &void = arena_alloc(arena, {size});\n"));
+        program.push_str(&format!("    mem_set(bump{i}, {value}, {size});\n"));
+    }
+    for (i, size) in sizes.iter().enumerate() {
+        program.push_str(&format!("    report(count_bytes(bump{i}, {}, {size}));\n", i % 250 + 1));
+    }
+    program.push_str("    arena_free(arena);\n    flush(STDOUT);\n    return 0;\n}\n");
+    program
+}
+
+#[cfg(target_os = "linux")]
+fn check_stress(freestanding: bool) {
+    let mut seed = 12345u64;
+    let sizes: Vec<usize> = (0..400).map(|i| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        if i % 16 == 15 { 70000 } else { (seed >> 33) as usize % 4200 + 1 }
+    }).collect();
+    let name = if freestanding { "stress_nolibc.nerv" } else { "stress.nerv" };
+    let binary = common::build_source(name, &stress_program(&sizes), freestanding);
+    let output = common::run(&binary, &[]);
+    assert!(output.status.success());
+    let numbers: Vec<usize> = String::from_utf8(output.stdout).unwrap()
+        .split_whitespace().map(|n| n.parse().unwrap()).collect();
+
+    let n = sizes.len();
+    let (first, rest) = numbers.split_at(n + 2);
+    let (second, bump) = rest.split_at(n + 2);
+    // every byte of every block survived the later allocations
+    assert_eq!(&first[..n], &sizes[..]);
+    assert_eq!(&second[..n], &sizes[..]);
+    assert_eq!(bump, &sizes[..]);
+    // freeing everything and allocating the same sizes again maps nothing new
+    assert_eq!(first[n], second[n]);
+    assert_eq!(first[n + 1], second[n + 1]);
+    // only the size class chunks stay mapped
+    assert!(first[n + 1] < first[n]);
+    assert_eq!(first[n + 1] % 65536, 0);
+}
+
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+#[cfg(target_os = "linux")]
+fn heap_stress_on_top_of_libc() {
+    check_stress(false);
+}
+
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+#[cfg(target_os = "linux")]
+fn heap_stress_without_libc() {
+    check_stress(true);
+}
+
+// the allocator is nerv code on top of mmap, it neither calls into libc nor
+// needs anything but the compiler to check how programs use it
+#[test]
+#[cfg(target_os = "linux")]
+fn allocations_lower_to_bundled_calls() {
+    let source = "@main() int {\n    dec block &int = heap_alloc(4);\n    *block = 7;\n    dec arena = arena_new(4096);\n    dec bump &int = arena_alloc(arena, 4);\n    *bump = *block;\n    heap_free(block);\n    arena_free(arena);\n    return 0;\n}\n";
+    let ir = common::emit_ir("allocations.nerv", source);
+    assert!(!ir.contains("extern @"), "{ir}");
+    for signature in ["fn heap_alloc(i32 %0) -> ptr {", "fn heap_free(ptr %0) -> i32 {", "fn arena_new(i32 %0) -> ptr {", "fn arena_alloc(ptr %0, i32 %1) -> ptr {"] {
+        assert!(ir.contains(signature), "{ir}");
+    }
+    let main = "    %0 = const i32 4
+    %1 = call ptr @heap_alloc(%0)
+    %2 = const i32 7
+    store i32 %2, %1
+    %3 = const i32 4096
+    %4 = call ptr @arena_new(%3)
+    %5 = const i32 4
+    %6 = call ptr @arena_alloc(%4, %5)
+    %7 = load i32 %1
+    store i32 %7, %6
+    %8 = call i32 @heap_free(%1)
+    %9 = call i32 @arena_free(%4)
+";
+    assert!(ir.contains(main), "{ir}");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn heap_memory_comes_from_mmap() {
+    let ir = common::emit_ir("heap.nerv", "@main() int {\n    return 0;\n}\n");
+    // mmap(0, 64 KiB, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0) for a size class chunk
+    let alloc = common::ir_function(&ir, "heap_alloc");
+    assert!(alloc.contains("\"mov esi, 65536\", \"mov edx, 3\", \"mov r10d, 34\", \"mov r8, -1\", \"xor r9d, r9d\", \"mov eax, 9\", \"syscall\""), "{alloc}");
+    assert!(alloc.contains("in(rdi) %0, out(rax) $0"), "{alloc}");
+    // large blocks go back with munmap
+    let free = common::ir_function(&ir, "heap_free");
+    assert!(free.contains("\"mov eax, 11\", \"syscall\""), "{free}");
+    // one free list per size class, 32 bytes to 4 KiB
+    assert!(ir.contains("asm \"nerv_heap_free_lists: resq 8\"\nasm \"nerv_heap_mapped: resq 1\"\n"), "{ir}");
+}
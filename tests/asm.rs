@@ -0,0 +1,24 @@
+mod common;
+
+#[test]
+fn invalid_operands_are_reported() {
+    let source = "@main() int {\n    dec x int = 1;\n    asm {\n        \"nop\",\n        in(\"xmm9\") x,\n        out(\"rax\") 5,\n        clobber(\"flags\")\n    }\n    return x;\n}\n";
+    let error = common::compile_error("operands.nerv", source);
+    assert!(error.contains("error[E0211]: unknown register `xmm9` for asm operand\n") && error.contains("operands.nerv:5:9\n"), "{error}");
+    assert!(error.contains("error[E0211]: out, inout and mem asm operands have to be local variables\n") && error.contains("operands.nerv:6:20\n"), "{error}");
+    assert!(error.contains("error[E0211]: unknown register `flags` in asm clobbers\n") && error.contains("operands.nerv:7:17\n"), "{error}");
+    assert!(error.ends_with("error: aborting due to 3 previous errors\n"), "{error}");
+}
+
+// a local the template names keeps its stack slot so the asm can address it,
+// the rest of the function's locals are still promoted to registers
+#[test]
+fn bound_locals_stay_in_memory() {
+    let source = "@main() int {\n    dec counter int = 41;\n    dec result int = 0;\n    dec other int = 1;\n    asm {\n        \"mov eax, {counter}\",\n        \"inc eax\",\n        \"mov {result}, eax\",\n        mem counter,\n        mem result,\n        clobber(\"rax\")\n    }\n    return result - other;\n}\n";
+    let ir = common::emit_ir("bound.nerv", source);
+    let main = common::ir_function(&ir, "main");
+    assert!(main.starts_with("fn main() -> i32 {\n    $0 = slot 4, align 4 ; counter\n    $1 = slot 4, align 4 ; result\nbb0:\n"), "{ir}");
+    assert!(main.contains("    asm [\"mov eax, {counter}\", \"inc eax\", \"mov {result}, eax\"], {counter} = $0, {result} = $1, clobber(rax)\n    %5 = addr $1\n    %6 = load i32 %5\n    %7 = sub i32 %6, %4\n"), "{ir}");
+    let asm = common::emit_asm("bound.nerv", source, &[]);
+    assert!(asm.contains("\tmov eax, DWORD [rbp-4]\n\tinc eax\n\tmov DWORD [rbp-8], eax\n"), "{asm}");
+}
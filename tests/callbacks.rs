@@ -0,0 +1,96 @@
+mod common;
+
+const CALLEE_SAVED: [[&str; 4]; 5] = [
+    ["rbx", "ebx", "bx", "bl"],
+    ["r12", "r12d", "r12w", "r12b"],
+    ["r13", "r13d", "r13w", "r13b"],
+    ["r14", "r14d", "r14w", "r14b"],
+    ["r15", "r15d", "r15w", "r15b"],
+];
+
+// a comparator keeping values in callee-saved registers across its own calls
+const COMPARATOR: &str = "extern qsort(&int, int, int, fn(&int, &int) -> int) void;\n\n@weight(int value) int {\n    return value * 3;\n}\n\n@compare(&int left, &int right) int {\n    dec l int = weight(*left);\n    dec r int = weight(*right);\n    return l - r;\n}\n\n@main() int {\n    dec numbers int = 3;\n    qsort(&numbers, 1, 4, compare);\n    return numbers;\n}\n";
+
+fn callee_saved(operand: &str) -> Option<&'static str> {
+    CALLEE_SAVED.iter().find(|names| names.contains(&operand)).map(|names| names[0])
+}
+
+// qsort calls the comparator like any C function, whatever it keeps in rbx or
+// r12-r15 has to be saved by the prologue and restored before every return
+#[test]
+fn callbacks_save_exactly_the_callee_saved_registers_they_use() {
+    let asm = common::emit_asm("comparator.nerv", COMPARATOR, &[]);
+    let compare = common::asm_function(&asm, "compare");
+    let mut saved = vec![];
+    let mut restored = vec![];
+    let mut used = vec![];
+    for line in &compare {
+        if let Some((slot, register)) = line.strip_prefix("mov QWORD [rbp-").and_then(|rest| rest.split_once("], "))
+            && let Some(register) = callee_saved(register) {
+            saved.push((slot, register));
+            continue;
+        }
+        if let Some((register, slot)) = line.strip_prefix("mov ").and_then(|rest| rest.split_once(", QWORD [rbp-"))
+            && let Some(register) = callee_saved(register) {
+            restored.push((slot.trim_end_matches(']'), register));
+            continue;
+        }
+        for word in line.split(|c: char| !c.is_ascii_alphanumeric()) {
+            if let Some(register) = callee_saved(word)
+                && !used.contains(&register) {
+                used.push(register);
+            }
+        }
+    }
+    assert!(!used.is_empty(), "{compare:?}");
+    let mut saved_registers: Vec<&str> = saved.iter().map(|(_, register)| *register).collect();
+    saved_registers.sort();
+    used.sort();
+    assert_eq!(saved_registers, used, "{compare:?}");
+    assert_eq!(saved, restored, "{compare:?}");
+    // the saves right after the frame is set up, the restores right before `leave`
+    assert!(compare[2].starts_with("sub rsp, "), "{compare:?}");
+    assert!(compare[3..3 + saved.len()].iter().all(|line| line.starts_with("mov QWORD [rbp-")), "{compare:?}");
+    let leave = compare.iter().position(|line| *line == "leave").unwrap();
+    assert!(compare[leave - restored.len()..leave].iter().all(|line| line.contains(", QWORD [rbp-")), "{compare:?}");
+}
+
+// rsp is 8 bytes off after the call into the comparator, the calls it makes
+// itself still have to see it 16 byte aligned
+#[test]
+fn callbacks_keep_the_stack_aligned_at_their_calls() {
+    let asm = common::emit_asm("comparator.nerv", COMPARATOR, &[]);
+    let compare = common::asm_function(&asm, "compare");
+    let mut depth = 8;
+    let mut calls = 0;
+    for line in &compare {
+        let amount = |prefix: &str| line.strip_prefix(prefix).map(|n| n.parse::<usize>().unwrap());
+        if line.starts_with("push ") {
+            depth += 8;
+        } else if line.starts_with("pop ") {
+            depth -= 8;
+        } else if let Some(n) = amount("sub rsp, ") {
+            depth += n;
+        } else if let Some(n) = amount("add rsp, ") {
+            depth -= n;
+        } else if line.starts_with("call ") {
+            assert_eq!(depth % 16, 0, "misaligned at `{line}` in {compare:?}");
+            calls += 1;
+        }
+    }
+    assert_eq!(calls, 2, "{compare:?}");
+}
+
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+fn qsort_with_nerv_comparator() {
+    let output = common::compile_and_run("qsort.nerv");
+    assert_eq!(output, "0 3 7 19 42 1000\n");
+}
+
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+fn bsearch_with_nerv_comparator() {
+    let output = common::compile_and_run("bsearch.nerv");
+    assert_eq!(output, "7 (nil)\n");
+}
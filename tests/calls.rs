@@ -0,0 +1,47 @@
+mod common;
+
+const SOURCE: &str = "@sum(int a, int b, int c, int d, int e, int f, int g, int h, int i) int {
+    return a + b + c + d + e + f + g + h + i;
+}
+
+@twice(int n) int {
+    return n * 2;
+}
+
+@main() int {
+    return sum(1, 2, 3, 4, 5, 6, 7, twice(8), twice(twice(9)));
+}
+";
+
+// arguments are evaluated left to right into temporaries, inner calls finish
+// before the call they are an argument of starts
+#[test]
+fn nested_calls_are_lowered_through_temporaries() {
+    let ir = common::emit_ir("nested.nerv", SOURCE);
+    let main = "    %6 = const i32 7
+    %7 = const i32 8
+    %8 = call i32 @twice(%7)
+    %9 = const i32 9
+    %10 = call i32 @twice(%9)
+    %11 = call i32 @twice(%10)
+    %12 = call i32 @sum(%0, %1, %2, %3, %4, %5, %6, %8, %11)
+";
+    assert!(ir.contains(main), "{ir}");
+}
+
+// the three arguments past the sixth go on the stack, 24 bytes rounded up to
+// keep rsp 16 byte aligned at the call
+#[test]
+fn arguments_past_the_sixth_go_on_the_stack() {
+    let asm = common::emit_asm("nested.nerv", SOURCE, &[]);
+    let setup = common::before_call(&asm, "sum", "\tsub rsp, ");
+    assert!(setup.starts_with("\tsub rsp, 32\n"), "{asm}");
+    for offset in [0, 8, 16] {
+        assert!(setup.contains(&format!("\tmov QWORD [rsp+{offset}], ")), "{asm}");
+    }
+    assert!(asm.contains("\tcall sum\n\tadd rsp, 32\n"), "{asm}");
+    let sum = common::asm_function(&asm, "sum");
+    for offset in [16, 24, 32] {
+        assert!(sum.iter().any(|line| line.ends_with(&format!(", QWORD [rbp+{offset}]"))), "{asm}");
+    }
+}
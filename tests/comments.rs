@@ -0,0 +1,15 @@
+mod common;
+
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+#[cfg(target_os = "linux")]
+fn line_block_and_doc_comments() {
+    let output = common::compile_and_run("comments.nerv");
+    assert_eq!(output, "3 // not a comment /* nor this */\n");
+}
+
+#[test]
+fn unterminated_block_comment() {
+    let error = common::compile_error("unterminated_comment.nerv", "@main() int { /* /* */\n    return 0;\n}\n");
+    assert!(error.contains("error[E0006]: unterminated block comment") && error.contains("unterminated_comment.nerv:1:15"), "{error}");
+}
@@ -0,0 +1,166 @@
+// Helpers for the integration tests. `build*` and `compile_and_run` assemble
+// and link what nerv wrote, the tests using them are `#[ignore]`d and
+// `cargo test -- --ignored` runs them, they fail when nasm or the linker
+// aren't installed. Everything else needs nothing but the compiler itself.
+use std::{env, path::{Path, PathBuf}, process::{Command, Output}};
+
+fn tool_available(tool: &str) -> bool {
+    Command::new(tool).arg("--version").output().is_ok()
+}
+
+// Compiles a program from tests/programs with the nerv binary, assembles and
+// links it the same way the Makefile does and returns the path of the executable.
+pub fn build(program: &str, freestanding: bool) -> PathBuf {
+    build_with(program, freestanding, &[])
+}
+
+// Same as `build` with extra arguments for nerv, like `--no-regalloc`.
+#[allow(dead_code)]
+pub fn build_with(program: &str, freestanding: bool, args: &[&str]) -> PathBuf {
+    let source = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/programs").join(program);
+    build_path(program, &source, freestanding, args)
+}
+
+// Same as `build` for a program generated by the test itself.
+#[allow(dead_code)]
+pub fn build_source(program: &str, source_code: &str, freestanding: bool) -> PathBuf {
+    let (_, source) = write_program("generated", program, source_code);
+    build_path(program, &source, freestanding, &[])
+}
+
+// writes `source_code` into a directory of its own for this test run, returns
+// the directory and the path of the program
+fn write_program(kind: &str, program: &str, source_code: &str) -> (PathBuf, PathBuf) {
+    let out_dir = env::temp_dir().join(format!("nerv-{kind}-{}", std::process::id()));
+    std::fs::create_dir_all(&out_dir).expect("Could not create the output directory.");
+    let source = out_dir.join(program);
+    std::fs::write(&source, source_code).expect("Could not write the program.");
+    (out_dir, source)
+}
+
+fn nerv(args: &[&str], source: &Path, output: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lang")).args(args).arg(source).arg(output).output().unwrap()
+}
+
+fn build_path(program: &str, source: &Path, freestanding: bool, args: &[&str]) -> PathBuf {
+    let linker = if freestanding { "ld" } else { "gcc" };
+    assert!(tool_available("nasm") && tool_available(linker), "{program} is an end to end test, it needs nasm and {linker}");
+
+    let mode = if freestanding { "-nolibc" } else { "" };
+    let out_dir = env::temp_dir().join(format!("nerv-{}{}{}-{}", program.trim_end_matches(".nerv"), mode, args.concat(), std::process::id()));
+    std::fs::create_dir_all(&out_dir).expect("Could not create the output directory.");
+    let asm = out_dir.join("out.s");
+    let object = out_dir.join("out.o");
+    let binary = out_dir.join("out");
+
+    let mut nerv = Command::new(env!("CARGO_BIN_EXE_lang"));
+    if freestanding {
+        nerv.arg("--no-libc");
+    }
+    let status = nerv.args(args).arg(source).arg(&asm).status().unwrap();
+    assert!(status.success(), "nerv failed to compile {program}");
+
+    let format = if cfg!(target_os = "macos") { "macho64" } else { "elf64" };
+    let status = Command::new("nasm").args(["-f", format]).arg(&asm).arg("-o").arg(&object).status().unwrap();
+    assert!(status.success(), "nasm rejected the output for {program}");
+
+    let mut link = Command::new(linker);
+    if freestanding {
+        link.arg("-static");
+    } else {
+        link.arg("-no-pie");
+    }
+    let status = link.arg(&object).arg("-o").arg(&binary).status().unwrap();
+    assert!(status.success(), "linking {program} failed");
+
+    binary
+}
+
+pub fn run(binary: &PathBuf, args: &[&str]) -> Output {
+    Command::new(binary).args(args).output().unwrap()
+}
+
+// Builds and runs a program linked against libc, it has to exit successfully.
+#[allow(dead_code)]
+pub fn compile_and_run(program: &str) -> String {
+    let binary = build(program, false);
+    let output = run(&binary, &[]);
+    assert!(output.status.success(), "{program} exited with {:?}", output.status);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+// Runs nerv on a program that is expected to be rejected and returns what it
+// reported.
+#[allow(dead_code)]
+pub fn compile_error(program: &str, source_code: &str) -> String {
+    compile_error_with(program, source_code, &[])
+}
+
+#[allow(dead_code)]
+pub fn compile_error_with(program: &str, source_code: &str, args: &[&str]) -> String {
+    let (out_dir, source) = write_program("rejected", program, source_code);
+    let output = nerv(args, &source, &out_dir.join("out.s"));
+    assert!(!output.status.success(), "nerv accepted {program}");
+    String::from_utf8(output.stderr).unwrap()
+}
+
+// Runs nerv on a program that has to be accepted and returns the warnings it
+// printed.
+#[allow(dead_code)]
+pub fn compile_warnings(program: &str, source_code: &str, args: &[&str]) -> String {
+    let (out_dir, source) = write_program("warned", program, source_code);
+    let output = nerv(args, &source, &out_dir.join("out.s"));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.success(), "nerv rejected {program}\n{stderr}");
+    stderr
+}
+
+// Runs nerv with `--emit=ir` on a program and returns the IR it wrote.
+#[allow(dead_code)]
+pub fn emit_ir(program: &str, source_code: &str) -> String {
+    emit(program, source_code, &["--emit=ir"], "ir")
+}
+
+// Runs nerv on a program and returns the assembly it wrote.
+#[allow(dead_code)]
+pub fn emit_asm(program: &str, source_code: &str, args: &[&str]) -> String {
+    emit(program, source_code, args, "s")
+}
+
+fn emit(program: &str, source_code: &str, args: &[&str], extension: &str) -> String {
+    let (out_dir, source) = write_program("emitted", program, source_code);
+    let output = out_dir.join(format!("{program}{}.{extension}", args.concat()));
+    let result = nerv(args, &source, &output);
+    assert!(result.status.success(), "nerv rejected {program}\n{}", String::from_utf8_lossy(&result.stderr));
+    std::fs::read_to_string(output).expect("Could not read the output.")
+}
+
+// `fn name(...) { ... }` out of the IR, the closing brace included
+#[allow(dead_code)]
+pub fn ir_function<'s>(ir: &'s str, name: &str) -> &'s str {
+    let start = ir.find(&format!("fn {name}(")).unwrap_or_else(|| panic!("no function {name}\n{ir}"));
+    let body = &ir[start..];
+    &body[..body.find("\n}\n").unwrap() + 3]
+}
+
+// the instructions of one function in the generated assembly, without the
+// labels of its blocks
+#[allow(dead_code)]
+pub fn asm_function<'s>(asm: &'s str, name: &str) -> Vec<&'s str> {
+    asm.lines()
+        .skip_while(|line| *line != format!("{name}:"))
+        .skip(1)
+        .take_while(|line| line.starts_with('\t') || line.starts_with('.'))
+        .filter(|line| line.starts_with('\t'))
+        .map(str::trim)
+        .collect()
+}
+
+// the assembly from the last `from` before the first call to `callee` up to
+// that call
+#[allow(dead_code)]
+pub fn before_call<'s>(asm: &'s str, callee: &str, from: &str) -> &'s str {
+    let call = asm.find(&format!("\tcall {callee}\n")).unwrap_or_else(|| panic!("no call to {callee}\n{asm}"));
+    let start = asm[..call].rfind(from).unwrap_or_else(|| panic!("no `{from}` before the call to {callee}\n{asm}"));
+    &asm[start..call]
+}
@@ -0,0 +1,31 @@
+mod common;
+
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+#[cfg(target_os = "linux")]
+fn bare_returns_and_nested_blocks() {
+    let output = common::compile_and_run("control_flow.nerv");
+    assert_eq!(output, "checked 3\nreported 3\n7\n");
+}
+
+#[test]
+fn falling_off_the_end() {
+    let error = common::compile_error("no_return.nerv", "@f(int n) int {\n    dec twice int = n * 2;\n}\n\n@main() int {\n    return f(1);\n}\n");
+    assert!(error.contains("error[E0214]: `f` can reach the end of its body without returning a value"), "{error}");
+    assert!(error.contains("no_return.nerv:3:1"), "{error}");
+}
+
+#[test]
+fn bare_return_needs_a_void_function() {
+    let error = common::compile_error("bare_return.nerv", "@main() int {\n    return;\n}\n");
+    assert!(error.contains("error[E0200]: `return;` without a value"), "{error}");
+}
+
+#[test]
+fn statements_after_a_return_are_unreachable() {
+    let warnings = common::compile_warnings("unreachable.nerv", "@main() int {\n    return 0;\n    println(1);\n    println(2);\n}\n", &[]);
+    assert!(warnings.starts_with("warning[W0001]: unreachable statement\n"), "{warnings}");
+    assert!(warnings.contains("unreachable.nerv:3:5"), "{warnings}");
+    assert_eq!(warnings.matches("warning[W0001]").count(), 1, "{warnings}");
+    assert!(warnings.ends_with("warning: 1 warning emitted\n"), "{warnings}");
+}
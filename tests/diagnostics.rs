@@ -0,0 +1,82 @@
+mod common;
+
+fn assert_no_backtrace(error: &str) {
+    assert!(!error.contains("panicked") && !error.contains("RUST_BACKTRACE"), "{error}");
+}
+
+#[test]
+fn type_errors_show_the_source_line() {
+    let error = common::compile_error("mismatch.nerv", "@main() int {\n    dec x int = \"five\";\n    return x;\n}\n");
+    assert!(error.starts_with("error[E0200]: mismatched types\n"), "{error}");
+    assert!(error.contains("mismatch.nerv:2:17\n"), "{error}");
+    assert!(error.contains("2 |     dec x int = \"five\";\n"), "{error}");
+    assert!(error.contains("  |                 ^^^^^^ expected"), "{error}");
+    assert!(error.contains("  |     --- `x` is declared as"), "{error}");
+    assert_no_backtrace(&error);
+}
+
+#[test]
+fn syntax_errors_name_what_was_expected() {
+    let error = common::compile_error("missing_semicolon.nerv", "@main() int {\n    dec x int = 5\n    return x;\n}\n");
+    assert!(error.starts_with("error[E0100]: expected `;`, found `return`\n"), "{error}");
+    assert!(error.contains("3 |     return x;\n  |     ^^^^^^ expected `;`\n"), "{error}");
+    assert_no_backtrace(&error);
+}
+
+#[test]
+fn running_out_of_tokens() {
+    let error = common::compile_error("unclosed.nerv", "@main() int {\n    return 0;\n");
+    assert!(error.starts_with("error[E0101]: expected `}`, found the end of the file\n"), "{error}");
+    assert!(error.contains("1 | @main() int {\n  |             - to close this block\n"), "{error}");
+    assert_no_backtrace(&error);
+}
+
+#[test]
+fn notes_follow_the_snippet() {
+    let error = common::compile_error("defer_return.nerv", "@main() int {\n    defer return 1;\n    return 0;\n}\n");
+    assert!(error.starts_with("error[E0212]: can not return from inside a defer\n"), "{error}");
+    assert!(error.contains("  |\n  = note: deferred statements run"), "{error}");
+    assert_no_backtrace(&error);
+}
+
+#[test]
+fn every_syntax_error_in_one_run() {
+    let error = common::compile_error("typos.nerv", "@main() int {\n    dec a int = 1\n    dec b int = a +;\n    prnt(a b);\n    return 0;\n}\n");
+    assert_eq!(error.matches("error[E0100]").count(), 3, "{error}");
+    assert!(error.contains("typos.nerv:3:5\n") && error.contains("typos.nerv:3:20\n") && error.contains("typos.nerv:4:12\n"), "{error}");
+    assert!(error.ends_with("error: aborting due to 3 previous errors\n"), "{error}");
+    assert_no_backtrace(&error);
+}
+
+#[test]
+fn every_type_error_in_one_run() {
+    let source = "@twice(int n) int {\n    return n * 2;\n}\n\n@main() int {\n    dec p &int = nil;\n    dec s string = p;\n    dec d int = missing + 1;\n    dec _e int = d * 2;\n    dec f fn(int) -> int = twice;\n    return f(s);\n}\n";
+    let error = common::compile_error("types.nerv", source);
+    assert!(error.ends_with("error: aborting due to 3 previous errors\n"), "{error}");
+    // sorted by position, one error per mistake, types spelled the nerv way
+    let mismatch = error.find("7 |     dec s string = p;").unwrap();
+    let unknown = error.find("error[E0201]: unknown variable `missing`").unwrap();
+    let argument = error.find("11 |     return f(s);").unwrap();
+    assert!(mismatch < unknown && unknown < argument, "{error}");
+    assert!(error.contains("expected `string`, found `&int`"), "{error}");
+    assert!(error.contains("expected `int`, found `string`"), "{error}");
+    assert_no_backtrace(&error);
+}
+
+// types declared in a function body are gone once the body is checked
+#[test]
+fn unknown_types_in_signatures() {
+    let source = "@main() int {\n    return twice(2);\n}\n\n@local() void {\n    type Num: int;\n}\n\n@twice(Num n) int {\n    return n * 2;\n}\n";
+    let error = common::compile_error("scoped_type.nerv", source);
+    assert!(error.starts_with("error[E0102]: unknown type `Num`\n") && error.contains("scoped_type.nerv:2:12\n"), "{error}");
+    assert!(error.contains("scoped_type.nerv:9:1\n"), "{error}");
+    assert_no_backtrace(&error);
+}
+
+#[test]
+fn return_outside_of_a_function() {
+    let error = common::compile_error("top_return.nerv", "extern puts(string) int;\nreturn 0;\n\n@main() int {\n    return 0;\n}\n");
+    assert!(error.contains("error[E0217]: `return` outside of a function\n") && error.contains("top_return.nerv:2:1\n"), "{error}");
+    assert!(error.contains("2 | return 0;\n  | ^^^^^^\n"), "{error}");
+    assert_no_backtrace(&error);
+}
@@ -0,0 +1,35 @@
+mod common;
+
+const DANGLING: &str = "@dangling() &int {\n    dec a int = 4;\n    dec p &int = &a;\n    return p;\n}\n\n@main() int {\n    dec p &int = dangling();\n    return *p;\n}\n";
+
+#[test]
+fn returning_the_address_of_a_local() {
+    let warnings = common::compile_warnings("dangling.nerv", DANGLING, &[]);
+    assert!(warnings.starts_with("warning[W0002]: `dangling` returns a pointer to the local `a`\n"), "{warnings}");
+    assert!(warnings.contains("dangling.nerv:4:12\n"), "{warnings}");
+    assert!(warnings.contains("2 |     dec a int = 4;\n  |     --- `a` is declared here\n"), "{warnings}");
+    assert!(warnings.contains("^ `a` stops existing when `dangling` returns"), "{warnings}");
+}
+
+#[test]
+fn escapes_can_be_denied() {
+    let error = common::compile_error_with("dangling.nerv", DANGLING, &["-D", "dangling_pointer"]);
+    assert!(error.starts_with("error[W0002]: `dangling` returns a pointer to the local `a`\n"), "{error}");
+    assert!(error.contains("= note: `dangling_pointer` is denied on the command line\n"), "{error}");
+}
+
+#[test]
+fn stores_that_outlive_the_function() {
+    let source = "extern malloc(int) &int;\n\n@out(&&int slot, int n) void {\n    *slot = &n;\n}\n\n@heap() &int {\n    dec a int = 1;\n    dec cell &int = malloc(8);\n    *cell = &a;\n    return cell;\n}\n\n@main() int {\n    return 0;\n}\n";
+    let error = common::compile_error("stores.nerv", source);
+    assert!(error.contains("warning[W0002]: `out` stores a pointer to the parameter `n` in memory owned by the caller\n"), "{error}");
+    assert!(error.contains("`n` is a parameter of `out`"), "{error}");
+    // `cell` is `&int`, so the store is also a type error
+    assert!(error.contains("warning[W0002]: `heap` stores a pointer to the local `a` in heap memory\n"), "{error}");
+}
+
+#[test]
+fn unprovable_pointers_stay_silent() {
+    let source = "extern malloc(int) &int;\n\n@pick(&int p) &int {\n    dec a int = 1;\n    dec q &int = &a;\n    q = p;\n    return q;\n}\n\n@fresh() &int {\n    dec a int = 1;\n    dec q &int = &a;\n    q = malloc(8);\n    return q;\n}\n\n@local_only() int {\n    dec a int = 1;\n    dec b &int = nil;\n    dec p &&int = &b;\n    *p = &a;\n    return a;\n}\n\n@main() int {\n    return 0;\n}\n";
+    assert_eq!(common::compile_warnings("silent.nerv", source, &["-A", "unused_function"]), "");
+}
@@ -0,0 +1,12 @@
+mod common;
+
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+#[cfg(target_os = "linux")]
+fn runs_without_libc() {
+    let binary = common::build("freestanding.nerv", true);
+    let output = common::run(&binary, &["one", "two"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "no libc\n");
+    // main's return value becomes the exit status, argc counts the program name
+    assert_eq!(output.status.code(), Some(43));
+}
@@ -0,0 +1,28 @@
+mod common;
+
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+#[cfg(target_os = "linux")]
+fn declarations_without_a_type() {
+    let output = common::compile_and_run("inference.nerv");
+    assert_eq!(output, "7 nerv 1.500000 true 14 7\nPoint { x: 3, y: 4 }\n");
+}
+
+#[test]
+fn nil_does_not_say_which_pointer() {
+    let error = common::compile_error("nil.nerv", "@main() int {\n    dec p = nil;\n    return 0;\n}\n");
+    assert!(error.contains("error[E0215]: can not infer a type for `p` from `nil`\n"), "{error}");
+    assert!(error.contains("  |             ^^^ `nil` fits any pointer type\n"), "{error}");
+}
+
+#[test]
+fn void_is_not_a_value() {
+    let error = common::compile_error("void.nerv", "@nothing() void {\n    return;\n}\n\n@main() int {\n    dec v = nothing();\n    return 0;\n}\n");
+    assert!(error.contains("error[E0215]: `v` can not hold a `void`\n"), "{error}");
+}
+
+#[test]
+fn inferred_types_are_checked_like_declared_ones() {
+    let error = common::compile_error("later.nerv", "@main() int {\n    dec n = \"five\";\n    return n;\n}\n");
+    assert!(error.contains("expected `int`, found `string`"), "{error}");
+}
@@ -0,0 +1,108 @@
+mod common;
+
+#[test]
+fn emit_ir_dumps_the_lowered_functions() {
+    let ir = common::emit_ir("add.nerv", "@add(int a, int b) int {\n    return a + b;\n}\n\n@main() int {\n    return add(1, 2);\n}\n");
+    let add = "fn add(i32 %0, i32 %1) -> i32 {
+bb0:
+    %2 = add i32 %0, %1
+    jump bb1
+bb1:
+    ret %2
+}
+";
+    assert!(ir.contains(add), "{ir}");
+    assert!(ir.contains("    %2 = call i32 @add(%0, %1)\n"), "{ir}");
+}
+
+#[test]
+fn mixed_arithmetic_converts_the_int() {
+    let ir = common::emit_ir("mixed.nerv", "@main() int {\n    dec x = 1.5 * 2;\n    return 0;\n}\n");
+    assert!(ir.contains("    %1 = const i32 2\n    %2 = itof %1\n    %3 = mul f64 %0, %2\n"), "{ir}");
+}
+
+#[test]
+fn deferred_statements_run_before_the_jump_to_the_exit() {
+    let ir = common::emit_ir("defer.nerv", "extern puts(string) int;\n\n@main() int {\n    defer puts(\"bye\");\n    return 0;\n}\n");
+    assert!(ir.contains("    %2 = call i32 @puts(%1)\n    jump bb1\nbb1:\n    %3 = const i32 1\n    %4 = call i32 @flush(%3)\n    ret %0\n"), "{ir}");
+}
+
+// the constants `mark` is called with in `function`, in the order of the calls
+fn marks(ir: &str, function: &str) -> Vec<i64> {
+    let body = common::ir_function(ir, function);
+    let constant = |vreg: &str| body.lines()
+        .find_map(|line| line.trim().strip_prefix(&format!("{vreg} = const i32 ")))
+        .map(|value| value.parse().unwrap());
+    body.lines()
+        .filter_map(|line| line.split_once(" = call i32 @mark(").map(|(_, argument)| argument.trim_end_matches(')')))
+        .map(|argument| constant(argument).unwrap())
+        .collect()
+}
+
+// a block runs its defers in reverse when it ends, a return runs the ones of
+// every enclosing block, innermost first, before leaving
+#[test]
+fn deferred_calls_run_in_reverse_per_scope() {
+    let source = "extern mark(int) int;
+
+@main() int {
+    defer mark(1);
+    defer mark(2);
+    {
+        defer mark(3);
+        {
+            defer mark(4);
+            mark(10);
+        }
+        defer mark(5);
+        return 0;
+    }
+}
+
+@finish() void {
+    defer mark(6);
+    {
+        defer mark(7);
+    }
+    mark(11);
+}
+";
+    let ir = common::emit_ir("defer_order.nerv", source);
+    assert_eq!(marks(&ir, "main"), [10, 4, 5, 3, 2, 1], "{ir}");
+    assert_eq!(marks(&ir, "finish"), [7, 11, 6], "{ir}");
+}
+
+// a local declared after the `defer` can shadow what it names, the deferred
+// call still reads the one that was in scope at the `defer`
+#[test]
+fn deferred_calls_keep_the_bindings_of_the_defer() {
+    let ir = common::emit_ir("defer_shadow.nerv", "extern use(int) int;\n\n@main() int {\n    dec x int = 1;\n    {\n        defer use(x);\n        dec x int = 2;\n        use(x);\n    }\n    return 0;\n}\n");
+    assert!(ir.contains("    %0 = const i32 1\n    %1 = const i32 2\n    %2 = call i32 @use(%1)\n    %3 = call i32 @use(%0)\n"), "{ir}");
+}
+
+// struct copies are lowered field by field, every field is read before any is
+// written so a struct can be rebuilt from its own fields
+#[test]
+fn structs_are_copied_field_by_field() {
+    let ir = common::emit_ir("struct_copy.nerv", "struct P {\n    x: int,\n    y: int\n}\n\n@main() int {\n    dec a P = #P{x: 1, y: 2};\n    dec b P = a;\n    a = #P{x: a.y, y: a.x};\n    return a.x + b.y;\n}\n");
+    assert!(ir.contains("    %4 = addr $0\n    %5 = load i32 %4\n    %6 = offset %4, 4\n    %7 = load i32 %6\n    %8 = addr $1\n    store i32 %5, %8\n    %9 = offset %8, 4\n    store i32 %7, %9\n"), "{ir}");
+    assert!(ir.contains("    %12 = load i32 %11\n    %13 = addr $0\n    %14 = load i32 %13\n    %15 = addr $0\n    store i32 %12, %15\n    %16 = offset %15, 4\n    store i32 %14, %16\n"), "{ir}");
+}
+
+// locals are kept in registers unless something needs their address
+#[test]
+fn only_locals_with_their_address_taken_keep_a_slot() {
+    let ir = common::emit_ir("slots.nerv", "@read(&int p) int {\n    return *p;\n}\n\n@main() int {\n    dec kept int = 1;\n    dec promoted int = 2;\n    return read(&kept) + promoted;\n}\n");
+    let main = common::ir_function(&ir, "main");
+    assert!(main.contains("    $0 = slot 4, align 4 ; kept\nbb0:\n"), "{ir}");
+    assert!(main.contains("    %4 = call i32 @read(%3)\n    %5 = add i32 %4, %2\n"), "{ir}");
+}
+
+// float arithmetic and comparisons are selected from the IR types
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+#[cfg(target_os = "linux")]
+fn arithmetic_follows_the_operand_types() {
+    let output = common::compile_and_run("arithmetic.nerv");
+    assert_eq!(output, "0.500000 2.250000 1.500000 9.500000\n3 -3 7\n");
+}
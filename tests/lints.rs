@@ -0,0 +1,46 @@
+mod common;
+
+const PROGRAM: &str = "extern puts(string) int;\nextern abs(int) int;\n\n@helper() int {\n    return 1;\n}\n\n@twice(int n) int {\n    dec unused int = 2;\n    dec _on_purpose int = 3;\n    {\n        dec n int = 4;\n    }\n    return n * 2;\n}\n\n@main() int {\n    puts(\"hi\");\n    return twice(2);\n}\n";
+
+#[test]
+fn unused_items_and_locals() {
+    let warnings = common::compile_warnings("unused.nerv", PROGRAM, &[]);
+    assert!(warnings.contains("warning[W0005]: extern function `abs` is never used\n"), "{warnings}");
+    assert!(warnings.contains("warning[W0004]: function `helper` is never used\n"), "{warnings}");
+    assert!(warnings.contains("warning[W0003]: unused variable `unused`\n"), "{warnings}");
+    // the inner `n` is never read either, `_on_purpose`, `puts` and `main` are fine
+    assert!(warnings.contains("warning[W0003]: unused variable `n`\n"), "{warnings}");
+    assert!(warnings.ends_with("warning: 4 warnings emitted\n"), "{warnings}");
+}
+
+#[test]
+fn levels_from_the_command_line() {
+    let warnings = common::compile_warnings("levels.nerv", PROGRAM, &["-A", "unused_variable", "-Aunused_extern", "-W", "shadowed_variable"]);
+    assert!(warnings.contains("warning[W0006]: `n` shadows the parameter of the same name\n"), "{warnings}");
+    assert!(!warnings.contains("W0003") && !warnings.contains("W0005"), "{warnings}");
+    assert_eq!(common::compile_warnings("quiet.nerv", PROGRAM, &["-A", "warnings"]), "");
+
+    let error = common::compile_error_with("denied.nerv", PROGRAM, &["-D", "unused_function"]);
+    assert!(error.contains("error[W0004]: function `helper` is never used\n"), "{error}");
+    assert!(error.ends_with("error: aborting due to 1 previous error; 3 warnings emitted\n"), "{error}");
+}
+
+#[test]
+fn unknown_lints_are_rejected() {
+    let error = common::compile_error_with("unknown.nerv", PROGRAM, &["-D", "unused"]);
+    assert!(error.starts_with("error: unknown lint `unused`\n"), "{error}");
+}
+
+#[test]
+fn allow_comments() {
+    let source = "// nerv: allow(unused_extern)\nextern abs(int) int;\n\n@main() int {\n    dec a int = 1; // nerv: allow(unused_variable)\n    return 0;\n    // nerv: allow(unreachable_code)\n    dec b int = 2;\n}\n";
+    let warnings = common::compile_warnings("allowed.nerv", source, &[]);
+    // only the one the comments don't name is left
+    assert!(warnings.starts_with("warning[W0003]: unused variable `b`\n"), "{warnings}");
+    assert!(warnings.ends_with("warning: 1 warning emitted\n"), "{warnings}");
+}
+
+#[test]
+fn the_bundled_modules_are_not_linted() {
+    assert_eq!(common::compile_warnings("empty.nerv", "@main() int {\n    return 0;\n}\n", &[]), "");
+}
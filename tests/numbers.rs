@@ -0,0 +1,24 @@
+mod common;
+
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+#[cfg(target_os = "linux")]
+fn numeric_and_character_literals() {
+    let output = common::compile_and_run("numbers.nerv");
+    assert_eq!(output, "255 170 493 1000000 10 3\n0.500000 0.001000 2500.000000 1.500000\n97 10 39 233\n");
+}
+
+#[test]
+fn out_of_range_literal() {
+    let error = common::compile_error("overflow.nerv", "@main() int {\n    dec small int = 300u8;\n    return 0;\n}\n");
+    assert!(error.contains("error[E0004]: invalid or out of range number literal") && error.contains("overflow.nerv:2:"), "{error}");
+}
+
+// there is only one int type, a wider suffix does not make room for more
+#[test]
+fn wide_suffixes_still_have_to_fit_an_int() {
+    for literal in ["9000000000i64", "4000000000u32", "2147483648u64"] {
+        let error = common::compile_error("wide.nerv", &format!("@main() int {{\n    dec big = {literal};\n    return 0;\n}}\n"));
+        assert!(error.contains("error[E0004]: invalid or out of range number literal") && error.contains("wide.nerv:2:15"), "{error}");
+    }
+}
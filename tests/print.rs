@@ -0,0 +1,45 @@
+mod common;
+
+#[cfg(target_os = "linux")]
+fn check_output(stdout: Vec<u8>) {
+    let stdout = String::from_utf8(stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[..5], [
+        "a1b",
+        "x -42 false true",
+        "Point { x: 1, y: 2 }",
+        "Line { start: Point { x: 0, y: 0 }, end: Point { x: 3, y: 4 }, label: diag, visible: true }",
+        "0x0",
+    ]);
+    let pointer = lines[5].strip_prefix("0x").expect("pointers print as hex");
+    assert!(u64::from_str_radix(pointer, 16).unwrap() != 0);
+}
+
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+#[cfg(target_os = "linux")]
+fn print_on_top_of_libc() {
+    let binary = common::build("print.nerv", false);
+    check_output(common::run(&binary, &[]).stdout);
+}
+
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+#[cfg(target_os = "linux")]
+fn print_without_libc() {
+    let binary = common::build("print.nerv", true);
+    check_output(common::run(&binary, &[]).stdout);
+}
+
+// the fields are read from one address, the argument is evaluated once
+#[test]
+#[cfg(target_os = "linux")]
+fn struct_arguments_are_evaluated_once() {
+    let source = "struct Inner {\n    a: int\n}\n\nstruct Point {\n    x: int,\n    inner: Inner,\n    y: float\n}\n\n@make_point() &Point {\n    return heap_alloc(16);\n}\n\n@main() int {\n    print(*make_point());\n    return 0;\n}\n";
+    let ir = common::emit_ir("once.nerv", source);
+    let main = common::ir_function(&ir, "main");
+    assert_eq!(main.matches("@make_point()").count(), 1, "{main}");
+    assert_eq!(main.matches("@write_int").count(), 2, "{main}");
+    assert_eq!(main.matches("@write_float").count(), 1, "{main}");
+}
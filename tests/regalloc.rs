@@ -0,0 +1,96 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+const REGISTERS: &str = "1 2 3 4 5 6 7 8 9 10 11 12\n40 32 132 78\n51.500000\n-7 -7 -3 -3\n1 1.250000 12 15.000000 done\n";
+
+fn instruction_count(asm: &str) -> usize {
+    asm.lines().filter(|line| line.starts_with('\t') && !line.starts_with("\tglobal") && !line.starts_with("\textern")).count()
+}
+
+#[test]
+fn values_stay_in_the_argument_registers() {
+    let asm = common::emit_asm("add.nerv", "@add(int a, int b) int {\n    return a + b;\n}\n\n@main() int {\n    return add(1, 2);\n}\n", &[]);
+    assert_eq!(common::asm_function(&asm, "add"), ["push rbp", "mov rbp, rsp", "add edi, esi", "mov rax, rdi", "leave", "ret"]);
+    let main = common::asm_function(&asm, "main");
+    assert!(main.windows(3).any(|w| w == ["mov edi, 1", "mov esi, 2", "call add"]), "{main:?}");
+}
+
+#[test]
+fn values_live_across_a_call_are_kept_in_callee_saved_registers() {
+    let source = "@id(int x) int {\n    return x;\n}\n\n@main() int {\n    dec kept = id(1);\n    dec other = id(2);\n    return kept - other;\n}\n";
+    let asm = common::emit_asm("across.nerv", source, &[]);
+    let main = common::asm_function(&asm, "main");
+    assert!(main.contains(&"mov QWORD [rbp-8], rbx"), "{main:?}");
+    assert!(main.contains(&"mov rbx, rax"), "{main:?}");
+    assert!(main.contains(&"mov rbx, QWORD [rbp-8]"), "{main:?}");
+}
+
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+#[cfg(target_os = "linux")]
+fn spilled_values_and_stack_arguments() {
+    let output = common::compile_and_run("registers.nerv");
+    assert_eq!(output, REGISTERS);
+}
+
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+#[cfg(target_os = "linux")]
+fn no_regalloc_keeps_every_value_in_the_frame() {
+    let binary = common::build_with("registers.nerv", false, &["--no-regalloc"]);
+    let output = common::run(&binary, &[]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), REGISTERS);
+}
+
+#[test]
+fn allocation_shrinks_the_benchmarks() {
+    for program in ["calls.nerv", "floats.nerv"] {
+        let source = std::fs::read_to_string(format!("{}/tests/programs/benchmarks/{program}", env!("CARGO_MANIFEST_DIR"))).unwrap();
+        let allocated = instruction_count(&common::emit_asm(program, &source, &[]));
+        let spilled = instruction_count(&common::emit_asm(program, &source, &["--no-regalloc"]));
+        assert!(allocated * 3 < spilled * 2, "{program}: {allocated} instructions, {spilled} without register allocation");
+    }
+}
+
+fn fastest_run(binary: &std::path::PathBuf) -> (Duration, String) {
+    let mut fastest = Duration::MAX;
+    let mut stdout = String::new();
+    for _ in 0..5 {
+        let start = Instant::now();
+        let output = common::run(binary, &[]);
+        fastest = fastest.min(start.elapsed());
+        assert!(output.status.success());
+        stdout = String::from_utf8(output.stdout).unwrap();
+    }
+    (fastest, stdout)
+}
+
+// cargo test --release -- --ignored --nocapture register_allocation_speeds_up_the_benchmarks
+#[test]
+#[ignore = "benchmark"]
+#[cfg(target_os = "linux")]
+fn register_allocation_speeds_up_the_benchmarks() {
+    for program in ["benchmarks/calls.nerv", "benchmarks/floats.nerv"] {
+        let (allocated, spilled) = (common::build(program, false), common::build_with(program, false, &["--no-regalloc"]));
+        let (allocated_time, allocated_output) = fastest_run(&allocated);
+        let (spilled_time, spilled_output) = fastest_run(&spilled);
+        println!("{program}: {allocated_time:?}, {spilled_time:?} without register allocation");
+        assert_eq!(allocated_output, spilled_output);
+        assert!(allocated_time < spilled_time, "{program} got slower");
+    }
+}
+
+// nothing is saved around an asm block, so no value may be in a register while
+// it runs: the value live across it is spilled, and the callee-saved register
+// it clobbers is saved by the prologue
+#[test]
+fn values_live_across_asm_are_spilled() {
+    let source = "@id(int x) int {\n    return x;\n}\n\n@main() int {\n    dec kept = id(5);\n    asm {\n        \"mov edi, 1\",\n        \"mov ebx, 2\",\n        clobber(\"rdi\", \"rbx\")\n    }\n    return kept;\n}\n";
+    let asm = common::emit_asm("across_asm.nerv", source, &[]);
+    let main = &asm[asm.find("main:\n").unwrap()..];
+    assert!(main.contains("\tcall id\n\tmov QWORD [rbp-8], rax\n\n\t; INLINE ASM\n\tmov edi, 1\n\tmov ebx, 2\n"), "{asm}");
+    assert!(main.contains("\tmov rax, QWORD [rbp-8]\n"), "{asm}");
+    assert!(main.contains("\tmov QWORD [rbp-16], rbx\n") && main.contains("\tmov rbx, QWORD [rbp-16]\n"), "{asm}");
+}
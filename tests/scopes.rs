@@ -0,0 +1,27 @@
+mod common;
+
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+#[cfg(target_os = "linux")]
+fn shadowing_and_sibling_blocks() {
+    let output = common::compile_and_run("scopes.nerv");
+    assert_eq!(output, "three\n2\n20\n5\n6\n1 100\ninner defer 9\ndeferred sees 1\n");
+}
+
+#[test]
+fn use_after_the_block_ends() {
+    let error = common::compile_error("out_of_scope.nerv", "@main() int {\n    {\n        dec y int = 5;\n    }\n    return y;\n}\n");
+    assert!(error.contains("error[E0201]: unknown variable `y`") && error.contains("out_of_scope.nerv:5:12"), "{error}");
+}
+
+#[test]
+fn locals_do_not_leak_between_functions() {
+    let error = common::compile_error("leak.nerv", "@f() int {\n    dec leaked int = 1;\n    return leaked;\n}\n\n@main() int {\n    return leaked;\n}\n");
+    assert!(error.contains("error[E0201]: unknown variable `leaked`") && error.contains("leak.nerv:7:12"), "{error}");
+}
+
+#[test]
+fn redeclaring_in_the_same_scope() {
+    let error = common::compile_error("redeclare.nerv", "@main() int {\n    dec a int = 1;\n    dec a int = 2;\n    return a;\n}\n");
+    assert!(error.contains("error[E0202]: `a` is already declared in this scope"), "{error}");
+}
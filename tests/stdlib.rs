@@ -0,0 +1,60 @@
+mod common;
+
+#[cfg(target_os = "linux")]
+const EXPECTED: &str = "5 -1 0 -12345 2147483647\ncopied\n0 77\n";
+
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+#[cfg(target_os = "linux")]
+fn stdlib_on_top_of_libc() {
+    let binary = common::build("stdlib.nerv", false);
+    let output = common::run(&binary, &[]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), EXPECTED);
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "unbuffered\n");
+}
+
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+#[cfg(target_os = "linux")]
+fn stdlib_without_libc() {
+    let binary = common::build("stdlib.nerv", true);
+    let output = common::run(&binary, &[]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), EXPECTED);
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "unbuffered\n");
+}
+
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+#[cfg(target_os = "linux")]
+fn float_formatting() {
+    let output = common::compile_and_run("float_format.nerv");
+    assert_eq!(output, "3.142 -2.50 2.00 42\n");
+}
+
+// two `str_len:` labels would only be noticed by the assembler
+#[test]
+#[cfg(target_os = "linux")]
+fn bundled_names_can_not_be_redefined() {
+    let error = common::compile_error("str_len.nerv", "@str_len(string s) int {\n    return 0;\n}\n\n@main() int {\n    return str_len(\"x\");\n}\n");
+    assert!(error.starts_with("error[E0216]: `str_len` is defined more than once\n") && error.contains("str_len.nerv:1:1\n"), "{error}");
+    assert!(error.contains("= note: `str_len` is part of the bundled standard library"), "{error}");
+    let error = common::compile_error("flush.nerv", "extern flush() int;\n\n@main() int {\n    return flush();\n}\n");
+    assert!(error.starts_with("error[E0216]: `flush` is defined more than once\n"), "{error}");
+}
+
+#[test]
+fn functions_are_defined_once() {
+    let error = common::compile_error("twice.nerv", "@f() int {\n    return 1;\n}\n\n@f() int {\n    return 2;\n}\n\n@main() int {\n    return f();\n}\n");
+    assert!(error.contains("twice.nerv:5:1\n") && error.contains("`f` is first defined here"), "{error}");
+}
+
+// nothing written with write_string is lost when main returns without a flush
+#[test]
+#[cfg(target_os = "linux")]
+fn main_flushes_stdout_when_it_returns() {
+    let ir = common::emit_ir("unflushed.nerv", "@greet() void {\n    write_string(STDOUT, \"hi\");\n}\n\n@main() int {\n    greet();\n    return 0;\n}\n");
+    let greet = common::ir_function(&ir, "greet");
+    assert!(!greet.contains("@flush"), "{ir}");
+    let main = common::ir_function(&ir, "main");
+    assert!(main.contains("bb1:\n    %1 = const i32 1\n    %2 = call i32 @flush(%1)\n    ret %0\n"), "{main}");
+}
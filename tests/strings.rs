@@ -0,0 +1,35 @@
+mod common;
+
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+#[cfg(target_os = "linux")]
+fn escapes_raw_and_multi_line_strings() {
+    let output = common::compile_and_run("strings.nerv");
+    assert_eq!(output, concat!(
+        "tab\there \"quoted\" back\\slash\n",
+        "hex Az unicode \u{e9}\u{1F600} caf\u{e9} = café\n",
+        "raw \\n stays \\t as written\n",
+        "hashes let \"quotes\" in\n",
+        "first line\nsecond line\n",
+        "joined together\n",
+        "3 4\n",
+    ));
+}
+
+#[test]
+fn invalid_escape() {
+    let error = common::compile_error("bad_escape.nerv", "@main() int {\n    print(\"bad \\q escape\");\n    return 0;\n}\n");
+    assert!(error.contains("error[E0002]: invalid escape sequence") && error.contains("bad_escape.nerv:2:16"), "{error}");
+}
+
+#[test]
+fn hex_escapes_stay_ascii() {
+    let error = common::compile_error("bad_hex.nerv", "@main() int {\n    print(\"\\xff\");\n    return 0;\n}\n");
+    assert!(error.contains("error[E0002]: invalid escape sequence"), "{error}");
+}
+
+#[test]
+fn unterminated_string() {
+    let error = common::compile_error("unterminated.nerv", "@main() int {\n    print(\"never closed);\n    return 0;\n}\n");
+    assert!(error.contains("error[E0003]: unterminated string literal") && error.contains("unterminated.nerv:2:11"), "{error}");
+}
@@ -0,0 +1,35 @@
+mod common;
+
+// the SYS_* constants are folded into the syscall number
+#[test]
+#[cfg(target_os = "linux")]
+fn bundled_syscall_numbers() {
+    let source = "@main() int {\n    dec pid = syscall(SYS_getpid);\n    syscall(SYS_exit, pid);\n    return 0;\n}\n";
+    let ir = common::emit_ir("numbers.nerv", source);
+    assert!(ir.contains("    %0 = const i32 39\n    %1 = syscall(%0)\n"), "{ir}");
+    assert!(ir.contains("    %2 = const i32 60\n    %3 = syscall(%2, %1)\n"), "{ir}");
+}
+
+// the number goes into rax and the arguments into rdi, rsi, rdx, ints are
+// sign extended as the kernel reads all 64 bits
+#[test]
+#[cfg(target_os = "linux")]
+fn syscalls_are_lowered_to_the_kernel_convention() {
+    let source = "@main() int {\n    dec written = syscall(SYS_write, STDOUT, \"hi\", 2);\n    return written;\n}\n";
+    let asm = common::emit_asm("write.nerv", source, &[]);
+    let main: Vec<&str> = asm.lines().skip_while(|line| *line != "main:").map(str::trim).collect();
+    let syscall = main.iter().position(|line| *line == "syscall").expect("no syscall instruction");
+    assert_eq!(main[syscall - 7..=syscall], [
+        "mov rax, rdi", "mov rdi, rsi", "mov rsi, r8", "mov rdx, r9",
+        "movsxd rax, eax", "movsxd rdi, edi", "movsxd rdx, edx", "syscall"
+    ]);
+}
+
+#[test]
+fn a_syscall_takes_at_most_six_arguments() {
+    let error = common::compile_error("seven.nerv", "@main() int {\n    return syscall(1, 2, 3, 4, 5, 6, 7, 8);\n}\n");
+    assert!(error.starts_with("error[E0213]: syscall takes a syscall number and up to six arguments, got 8 arguments\n"), "{error}");
+    assert!(error.contains("seven.nerv:2:12\n"), "{error}");
+    let error = common::compile_error("none.nerv", "@main() int {\n    return syscall();\n}\n");
+    assert!(error.starts_with("error[E0213]:"), "{error}");
+}
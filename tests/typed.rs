@@ -0,0 +1,51 @@
+mod common;
+
+// aliases, integer division and struct layouts as the type checker resolved
+// them are what the code generator works from
+#[test]
+#[ignore = "end to end, needs nasm and a linker"]
+#[cfg(target_os = "linux")]
+fn code_generation_follows_the_checked_types() {
+    let output = common::compile_and_run("typed.nerv");
+    assert_eq!(output, "4 3 0.250000 true\nSample { flag: true, ratio: 0.250000, length: 9 }\n");
+}
+
+#[test]
+fn integer_division_stays_an_int() {
+    let error = common::compile_error("division.nerv", "@main() int {\n    dec q = 7 / 2;\n    dec s string = q;\n    return 0;\n}\n");
+    assert!(error.contains("expected `string`, found `int`"), "{error}");
+}
+
+// every type is known before any signature or constant is resolved
+#[test]
+fn aliases_resolve_wherever_they_are_declared() {
+    let ir = common::emit_ir("const_alias.nerv", "type Num: int;\nconst X Num = 5;\n\n@main() int {\n    return X;\n}\n");
+    assert!(ir.contains("    %0 = const i32 5\n"), "{ir}");
+    let ir = common::emit_ir("later_alias.nerv", "@main() int {\n    return twice(2);\n}\n\ntype Num: int;\n\n@twice(Num n) Num {\n    return n * 2;\n}\n");
+    assert!(ir.contains("fn twice(i32 %0) -> i32 {"), "{ir}");
+}
+
+// a struct holding itself by value would have no size, through a pointer is fine
+#[test]
+fn structs_can_not_contain_themselves() {
+    let error = common::compile_error("recursive.nerv", "struct A {\n    a: A\n}\n\n@main() int {\n    return 0;\n}\n");
+    assert!(error.starts_with("error[E0218]: `A` contains itself\n") && error.contains("recursive.nerv:2:5\n"), "{error}");
+    assert_eq!(error.matches("error[").count(), 1, "{error}");
+    // declared in a function body, which is only checked after the top level structs are laid out
+    let error = common::compile_error("unfinished.nerv", "@f() void {\n    struct A {\n        x: int\n    }\n}\n\nstruct B {\n    a: A\n}\n\n@main() int {\n    f();\n    return 0;\n}\n");
+    assert!(error.starts_with("error[E0102]: the size of `A` is not known yet\n") && error.contains("unfinished.nerv:8:5\n"), "{error}");
+    common::emit_ir("linked.nerv", "struct Node {\n    value: int,\n    next: &Node\n}\n\n@main() int {\n    dec n Node = #Node{value: 1, next: nil};\n    return n.value;\n}\n");
+}
+
+#[test]
+fn structs_are_not_passed_or_returned_by_value() {
+    let error = common::compile_error("by_value.nerv", "struct P {\n    x: int\n}\n\nextern take(P) int;\n\n@make() P {\n    return #P{x: 1};\n}\n\n@apply(fn(P) -> int f, &P p) int {\n    return f(*p);\n}\n\n@main() int {\n    return 0;\n}\n");
+    assert_eq!(error.matches("error[E0219]: `P` can not be passed by value\n").count(), 2, "{error}");
+    assert!(error.contains("error[E0219]: `P` can not be returned by value\n"), "{error}");
+    // the extern, the return type and the call through the function pointer
+    for at in ["by_value.nerv:5:1\n", "by_value.nerv:7:1\n", "by_value.nerv:12:12\n"] {
+        assert!(error.contains(at), "{error}");
+    }
+    assert!(error.contains("= note: use a pointer to it instead, `&P`"), "{error}");
+    assert_eq!(error.matches("error[").count(), 3, "{error}");
+}
@@ -0,0 +1,34 @@
+mod common;
+
+// bools are widened to int like C promotes them, the callee reads all 32 bits
+#[test]
+fn bools_are_promoted_to_int() {
+    let source = "extern printf(string, ...) int;\n\n@main() int {\n    dec yes = true;\n    printf(\"%d %d\\n\", yes, false);\n    return 0;\n}\n";
+    let ir = common::emit_ir("bools.nerv", source);
+    assert!(ir.contains("    %2 = zext %0\n    %3 = const i8 0\n    %4 = zext %3\n    %5 = call i32 @printf(%1, %2, %4, ...)\n"), "{ir}");
+    let asm = common::emit_asm("bools.nerv", source, &[]);
+    assert!(asm.contains("\tmovzx edi, dil\n"), "{asm}");
+}
+
+#[test]
+fn structs_are_not_variadic_arguments() {
+    let error = common::compile_error("struct.nerv", "extern printf(string, ...) int;\n\nstruct P {\n    x: int\n}\n\n@main() int {\n    dec p P = #P{x: 1};\n    printf(\"%d\\n\", p);\n    return 0;\n}\n");
+    assert!(error.contains("can not pass `P` as a variadic argument"), "{error}");
+}
+
+// `al` holds how many vector registers carry arguments, the floats go into
+// them in order whatever ints come in between
+#[test]
+fn floats_are_counted_in_al() {
+    let source = "extern printf(string, ...) int;\n\n@main() int {\n    dec half float = 0.5;\n    printf(\"%d %f %d %f\\n\", 1, half, 2, 2.5);\n    return 0;\n}\n";
+    let asm = common::emit_asm("mixed.nerv", source, &[]);
+    // everything after the prologue saved rbx
+    let setup: Vec<&str> = common::before_call(&asm, "printf", "\tmov QWORD [rbp-8], rbx\n").lines().skip(1).collect();
+    assert_eq!(setup.last(), Some(&"\tmov eax, 2"), "{asm}");
+    assert!(setup.contains(&"\tmov rdi, 0x3fe0000000000000") && setup.contains(&"\tmovq xmm0, rdi"), "{asm}");
+    assert!(setup.contains(&"\tmov r10, 0x4004000000000000") && setup.contains(&"\tmovq xmm1, r10"), "{asm}");
+    assert!(setup.contains(&"\tmov rsi, r8") && setup.contains(&"\tmov rdx, r9"), "{asm}");
+
+    let asm = common::emit_asm("ints.nerv", "extern printf(string, ...) int;\n\n@main() int {\n    printf(\"%d\\n\", 1);\n    return 0;\n}\n", &[]);
+    assert_eq!(common::before_call(&asm, "printf", "\tmov QWORD [rbp-8], rbx\n").lines().last(), Some("\tmov eax, 0"), "{asm}");
+}